@@ -0,0 +1,606 @@
+use crate::messages::*;
+use crate::{
+    check_encoding_version, deserialize_messages, Error, Network, CURRENT_ENCODING_VERSION,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The state of every registered network right after the block numbers for an epoch were set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedEpoch {
+    /// The Merkle root posted along with the block numbers. Epochs that were skipped with an empty
+    /// `SetBlockNumbersForNextEpoch` message don't have one.
+    pub merkle_root: Option<Bytes32>,
+    /// The epoch number that the payload claims the block numbers are for. Only encoding version
+    /// 1 and later carry one, and never for skipped epochs.
+    pub epoch_number: Option<u64>,
+    pub networks: Vec<(String, Network)>,
+}
+
+impl DecodedEpoch {
+    /// Returns the block number of the given network, if it was registered during this epoch.
+    pub fn block_number(&self, network_id: &str) -> Option<u64> {
+        self.networks
+            .iter()
+            .find(|(id, _)| id == network_id)
+            .map(|(_, network)| network.block_number)
+    }
+}
+
+/// The [`Decoder`] is the inverse of the [`Encoder`](crate::Encoder): it replays
+/// [`CompressedMessage`]s and rebuilds absolute block numbers and deltas from accelerations, just
+/// like the Epoch Subgraph does.
+///
+/// Decoding is transactional: if any message in a batch is invalid, none of them is applied, which
+/// matches how the Epoch Subgraph discards invalid payloads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decoder {
+    networks: Vec<(String, Network)>,
+    /// The Epoch Subgraph remembers the latest block number of removed networks, and picks up from
+    /// there if they're registered again.
+    removed_networks: BTreeMap<String, Network>,
+    encoding_version: u64,
+    decoded: Vec<DecodedEpoch>,
+}
+
+impl Decoder {
+    /// Creates a new [`Decoder`] with the specified initial state.
+    pub fn new(encoding_version: u64, networks: Vec<(String, Network)>) -> Result<Self, Error> {
+        check_encoding_version(encoding_version)?;
+
+        Ok(Self {
+            networks,
+            removed_networks: BTreeMap::new(),
+            encoding_version,
+            decoded: Vec::new(),
+        })
+    }
+
+    /// Sets the networks that were removed before the [`Decoder`] was created, so that they pick up
+    /// from their latest block number if they are registered again.
+    pub fn with_removed_networks(
+        mut self,
+        removed_networks: impl IntoIterator<Item = (String, Network)>,
+    ) -> Self {
+        self.removed_networks.extend(removed_networks);
+        self
+    }
+
+    /// The registered networks, sorted by network index.
+    pub fn networks(&self) -> &[(String, Network)] {
+        &self.networks
+    }
+
+    /// Returns the latest encoding version used by this [`Decoder`].
+    pub fn encoding_version(&self) -> u64 {
+        self.encoding_version
+    }
+
+    /// Deserializes a payload and replays its messages. See [`Decoder::decompress`].
+    pub fn decode(&mut self, payload: &[u8]) -> Result<Vec<DecodedEpoch>, Error> {
+        let messages =
+            deserialize_messages(payload, self.encoding_version, self.networks.len() as u64)?;
+        self.decompress(&messages)
+    }
+
+    /// Replays `messages` in order and returns the state of all networks for every new epoch.
+    pub fn decompress(
+        &mut self,
+        messages: &[CompressedMessage],
+    ) -> Result<Vec<DecodedEpoch>, Error> {
+        let mut next = self.clone();
+        for message in messages {
+            next.decompress_message(message)?;
+        }
+        let decoded = std::mem::take(&mut next.decoded);
+        *self = next;
+        Ok(decoded)
+    }
+
+    fn decompress_message(&mut self, message: &CompressedMessage) -> Result<(), Error> {
+        match message {
+            CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::Empty { count },
+            ) => {
+                for _ in 0..*count {
+                    self.push_epoch(None, None);
+                }
+            }
+            CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                    epoch_number,
+                    accelerations,
+                    root,
+                },
+            ) => self.decompress_accelerations(*epoch_number, accelerations, *root)?,
+            CompressedMessage::RegisterNetworks { remove, add } => {
+                self.register_networks(remove, add.iter())?
+            }
+            CompressedMessage::RegisterNetworksAndAliases { remove, add } => {
+                self.register_networks(remove, add.iter().map(|(id, _alias)| id))?
+            }
+            CompressedMessage::Reset => {
+                self.networks.clear();
+                self.removed_networks.clear();
+            }
+            CompressedMessage::UpdateVersion { version_number } => {
+                check_encoding_version(*version_number)?;
+                self.encoding_version = *version_number;
+            }
+            CompressedMessage::CorrectLastEpoch {
+                chain_id,
+                block_number,
+                merkle_root,
+            } => self.correct_last_epoch(chain_id, *block_number, *merkle_root)?,
+            // Permissions don't affect block numbers.
+            CompressedMessage::ChangePermissions { .. } => {}
+            CompressedMessage::CorrectEpochs {
+                epoch_number,
+                data_by_network_id,
+                ..
+            } => self.check_correct_epochs(*epoch_number, data_by_network_id)?,
+        }
+        Ok(())
+    }
+
+    fn decompress_accelerations(
+        &mut self,
+        epoch_number: Option<u64>,
+        accelerations: &[i64],
+        root: Bytes32,
+    ) -> Result<(), Error> {
+        match (self.encoding_version, epoch_number) {
+            (CURRENT_ENCODING_VERSION, Some(_)) => {
+                return Err(Error::UnexpectedEpochNumber(self.encoding_version))
+            }
+            (version, None) if version != CURRENT_ENCODING_VERSION => {
+                return Err(Error::MissingEpochNumber(version))
+            }
+            _ => {}
+        }
+        if accelerations.len() != self.networks.len() {
+            return Err(Error::AccelerationCountMismatch {
+                expected: self.networks.len(),
+                found: accelerations.len(),
+            });
+        }
+
+        for ((id, network), acceleration) in self.networks.iter_mut().zip(accelerations) {
+            // Just like the Epoch Subgraph, we reject negative deltas.
+            let invalid_delta = || Error::InvalidBlockDelta {
+                network_id: id.clone(),
+                block_number: network.block_number,
+                acceleration: *acceleration,
+            };
+            let delta = network
+                .block_delta
+                .checked_add(*acceleration)
+                .filter(|delta| *delta >= 0)
+                .ok_or_else(invalid_delta)?;
+            network.block_number = network
+                .block_number
+                .checked_add(delta as u64)
+                .ok_or_else(invalid_delta)?;
+            network.block_delta = delta;
+        }
+
+        self.push_epoch(epoch_number, Some(root));
+        Ok(())
+    }
+
+    /// Removals refer to network indices before any network is added or removed. The remaining
+    /// networks are then reindexed by position, with new networks at the end.
+    fn register_networks<'a>(
+        &mut self,
+        remove: &[NetworkIndex],
+        add: impl Iterator<Item = &'a String>,
+    ) -> Result<(), Error> {
+        // Like the Encoder, reject indices that are out of bounds or removed twice.
+        let mut removed_indices = BTreeSet::new();
+        if let Some(index) = remove.iter().find(|index| {
+            **index >= self.networks.len() as NetworkIndex || !removed_indices.insert(**index)
+        }) {
+            return Err(Error::UnknownNetworkIndex(*index));
+        }
+
+        // Like in the Epoch Subgraph, networks that this message removes are still registered
+        // while it adds networks.
+        let previously_registered: Vec<String> =
+            self.networks.iter().map(|(id, _)| id.clone()).collect();
+        let networks = std::mem::take(&mut self.networks);
+        for (i, (id, network)) in networks.into_iter().enumerate() {
+            if removed_indices.contains(&(i as NetworkIndex)) {
+                self.removed_networks.insert(id, network);
+            } else {
+                self.networks.push((id, network));
+            }
+        }
+
+        for id in add {
+            if previously_registered.contains(id)
+                || self.networks.iter().any(|(existing, _)| existing == id)
+            {
+                return Err(Error::NetworkAlreadyRegistered(id.clone()));
+            }
+            let network = self.removed_networks.remove(id).unwrap_or_default();
+            self.networks.push((id.clone(), network));
+        }
+
+        for (i, (_, network)) in self.networks.iter_mut().enumerate() {
+            network.array_index = i as NetworkIndex;
+        }
+        Ok(())
+    }
+
+    fn correct_last_epoch(
+        &mut self,
+        chain_id: &str,
+        block_number: u64,
+        merkle_root: Bytes32,
+    ) -> Result<(), Error> {
+        let (_, network) = self
+            .networks
+            .iter_mut()
+            .find(|(id, _)| id == chain_id)
+            .ok_or_else(|| Error::InvalidNetworkId(chain_id.to_string()))?;
+
+        // The delta of the last epoch is relative to the block number of the one before it.
+        let previous_block_number = network.block_number as i64 - network.block_delta;
+        network.block_delta = block_number as i64 - previous_block_number;
+        network.block_number = block_number;
+
+        // If the corrected epoch was decoded in this same batch, it must be amended too.
+        let networks = self.networks.clone();
+        if let Some(epoch) = self.decoded.last_mut() {
+            epoch.merkle_root = Some(merkle_root);
+            epoch.networks = networks;
+        }
+        Ok(())
+    }
+
+    /// Corrections don't change the block numbers of the epochs that follow, only their deltas
+    /// and accelerations. The [`Decoder`] doesn't know the epoch numbers of what it decodes,
+    /// though, so there's no way to tell which decoded epoch (if any) is the corrected one and we
+    /// only validate the message.
+    fn check_correct_epochs(
+        &self,
+        epoch_number: u64,
+        data_by_network_id: &BTreeMap<NetworkIndex, u64>,
+    ) -> Result<(), Error> {
+        if epoch_number == 0 {
+            return Err(Error::CannotCorrectEpochZero);
+        }
+        match data_by_network_id
+            .keys()
+            .find(|index| **index >= self.networks.len() as u64)
+        {
+            Some(index) => Err(Error::UnknownNetworkIndex(*index)),
+            None => Ok(()),
+        }
+    }
+
+    fn push_epoch(&mut self, epoch_number: Option<u64>, merkle_root: Option<Bytes32>) {
+        self.decoded.push(DecodedEpoch {
+            merkle_root,
+            epoch_number,
+            networks: self.networks.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockPtr, Encoder, Message, EPOCH_NUMBER_ENCODING_VERSION};
+
+    fn block_ptrs(block_numbers: &[(&str, u64)]) -> Message {
+        epoch_block_ptrs(None, block_numbers)
+    }
+
+    fn epoch_block_ptrs(epoch_number: Option<u64>, block_numbers: &[(&str, u64)]) -> Message {
+        Message::SetBlockNumbersForNextEpoch {
+            epoch_number,
+            block_ptrs: block_numbers
+                .iter()
+                .map(|(id, number)| (id.to_string(), BlockPtr::new(*number, [0; 32])))
+                .collect(),
+        }
+    }
+
+    fn register(add: &[&str]) -> Message {
+        Message::RegisterNetworks {
+            remove: vec![],
+            add: add.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn encoder_round_trip() {
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
+        let mut decoder = Decoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
+
+        let messages = [
+            register(&["A:1", "B:2"]),
+            block_ptrs(&[("A:1", 100), ("B:2", 5000)]),
+            block_ptrs(&[("A:1", 150), ("B:2", 5001)]),
+            register(&["C:3"]),
+            block_ptrs(&[("A:1", 170), ("C:3", 42)]),
+        ];
+        let compressed = encoder.compress(&messages).unwrap();
+        let payload = encoder.encode(&compressed);
+        let epochs = decoder.decode(&payload).unwrap();
+
+        assert_eq!(epochs.len(), 3);
+        assert_eq!(epochs[0].block_number("A:1"), Some(100));
+        assert_eq!(epochs[0].block_number("B:2"), Some(5000));
+        assert_eq!(epochs[1].block_number("A:1"), Some(150));
+        assert_eq!(epochs[1].block_number("B:2"), Some(5001));
+        assert_eq!(epochs[1].block_number("C:3"), None);
+        assert_eq!(epochs[2].block_number("A:1"), Some(170));
+        // Networks that are missing from the message keep their previous block number.
+        assert_eq!(epochs[2].block_number("B:2"), Some(5001));
+        assert_eq!(epochs[2].block_number("C:3"), Some(42));
+        let block_deltas = |networks: &[(String, Network)]| {
+            networks
+                .iter()
+                .map(|(id, network)| (id.clone(), network.block_number, network.block_delta))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            block_deltas(decoder.networks()),
+            block_deltas(encoder.network_deltas())
+        );
+    }
+
+    #[test]
+    fn empty_epochs() {
+        let mut decoder = Decoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
+        let epochs = decoder
+            .decompress(&[CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::Empty { count: 3 },
+            )])
+            .unwrap();
+        assert_eq!(epochs.len(), 3);
+        assert!(epochs.iter().all(|epoch| epoch.merkle_root.is_none()));
+    }
+
+    #[test]
+    fn epoch_numbers() {
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
+        let mut decoder = Decoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
+
+        // Version 0 payloads don't carry epoch numbers, even if the messages have them.
+        let compressed = encoder
+            .compress(&[
+                register(&["A:1"]),
+                epoch_block_ptrs(Some(7), &[("A:1", 100)]),
+            ])
+            .unwrap();
+        let epochs = decoder.decode(&encoder.encode(&compressed)).unwrap();
+        assert_eq!(epochs[0].epoch_number, None);
+
+        let compressed = encoder
+            .compress(&[Message::UpdateVersion {
+                version_number: EPOCH_NUMBER_ENCODING_VERSION,
+            }])
+            .unwrap();
+        decoder.decode(&encoder.encode(&compressed)).unwrap();
+        assert_eq!(decoder.encoding_version(), EPOCH_NUMBER_ENCODING_VERSION);
+
+        assert!(matches!(
+            encoder.compress(&[block_ptrs(&[("A:1", 150)])]),
+            Err(Error::MissingEpochNumber(EPOCH_NUMBER_ENCODING_VERSION))
+        ));
+        let compressed = encoder
+            .compress(&[
+                epoch_block_ptrs(Some(8), &[("A:1", 150)]),
+                epoch_block_ptrs(Some(9), &[("A:1", 200)]),
+            ])
+            .unwrap();
+        let epochs = decoder.decode(&encoder.encode(&compressed)).unwrap();
+        let epoch_numbers: Vec<_> = epochs.iter().map(|epoch| epoch.epoch_number).collect();
+        assert_eq!(epoch_numbers, [Some(8), Some(9)]);
+        assert_eq!(epochs[1].block_number("A:1"), Some(200));
+
+        // Epoch numbers must match the encoding version.
+        let without_epoch_number = CompressedMessage::SetBlockNumbersForNextEpoch(
+            CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                epoch_number: None,
+                accelerations: vec![0],
+                root: [0; 32],
+            },
+        );
+        assert!(matches!(
+            decoder.decompress(&[without_epoch_number]),
+            Err(Error::MissingEpochNumber(EPOCH_NUMBER_ENCODING_VERSION))
+        ));
+        assert!(matches!(
+            Decoder::new(2, vec![]),
+            Err(Error::UnsupportedEncodingVersion(2))
+        ));
+    }
+
+    #[test]
+    fn removals_reindex_networks() {
+        let networks = ["A:1", "B:2", "C:3", "D:4"]
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.to_string(), Network::new(i as u64 * 10, 1, i as u64)))
+            .collect();
+        let mut decoder = Decoder::new(CURRENT_ENCODING_VERSION, networks).unwrap();
+
+        decoder
+            .decompress(&[CompressedMessage::RegisterNetworks {
+                remove: vec![2, 0],
+                add: vec!["E:5".to_string()],
+            }])
+            .unwrap();
+
+        let ids: Vec<_> = decoder
+            .networks()
+            .iter()
+            .map(|(id, _)| id.as_str())
+            .collect();
+        assert_eq!(ids, ["B:2", "D:4", "E:5"]);
+        let indices: Vec<_> = decoder
+            .networks()
+            .iter()
+            .map(|(_, network)| network.array_index)
+            .collect();
+        assert_eq!(indices, [0, 1, 2]);
+
+        // Networks that are registered again start from their previous block number.
+        decoder
+            .decompress(&[CompressedMessage::RegisterNetworks {
+                remove: vec![],
+                add: vec!["C:3".to_string()],
+            }])
+            .unwrap();
+        assert_eq!(decoder.networks()[3].1, Network::new(20, 1, 3));
+    }
+
+    #[test]
+    fn networks_removed_before_the_decoder() {
+        let mut decoder = Decoder::new(CURRENT_ENCODING_VERSION, vec![])
+            .unwrap()
+            .with_removed_networks([("C:3".to_string(), Network::new(20, 1, 2))]);
+        decoder
+            .decompress(&[CompressedMessage::RegisterNetworks {
+                remove: vec![],
+                add: vec!["C:3".to_string()],
+            }])
+            .unwrap();
+        assert_eq!(decoder.networks()[0].1, Network::new(20, 1, 0));
+    }
+
+    #[test]
+    fn duplicate_removals_are_rejected() {
+        let mut decoder = Decoder::new(
+            CURRENT_ENCODING_VERSION,
+            vec![
+                ("A:1".to_string(), Network::new(10, 1, 0)),
+                ("B:2".to_string(), Network::new(20, 1, 1)),
+            ],
+        )
+        .unwrap();
+        let before = decoder.clone();
+        assert!(matches!(
+            decoder.decompress(&[CompressedMessage::RegisterNetworks {
+                remove: vec![0, 0],
+                add: vec![],
+            }]),
+            Err(Error::UnknownNetworkIndex(0))
+        ));
+        assert_eq!(decoder, before);
+    }
+
+    #[test]
+    fn reset_forgets_everything() {
+        let mut decoder = Decoder::new(
+            CURRENT_ENCODING_VERSION,
+            vec![("A:1".to_string(), Network::new(10, 10, 0))],
+        )
+        .unwrap();
+        decoder
+            .decompress(&[
+                CompressedMessage::Reset,
+                CompressedMessage::RegisterNetworks {
+                    remove: vec![],
+                    add: vec!["A:1".to_string()],
+                },
+            ])
+            .unwrap();
+        assert_eq!(decoder.networks()[0].1, Network::default());
+    }
+
+    #[test]
+    fn correct_last_epoch() {
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
+        let mut decoder = Decoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
+        let compressed = encoder
+            .compress(&[
+                register(&["A:1"]),
+                block_ptrs(&[("A:1", 100)]),
+                block_ptrs(&[("A:1", 150)]),
+            ])
+            .unwrap();
+        decoder.decompress(&compressed).unwrap();
+
+        let epochs = decoder
+            .decompress(&[CompressedMessage::CorrectLastEpoch {
+                chain_id: "A:1".to_string(),
+                block_number: 140,
+                merkle_root: [1; 32],
+            }])
+            .unwrap();
+        assert!(epochs.is_empty());
+        assert_eq!(decoder.networks()[0].1, Network::new(140, 40, 0));
+    }
+
+    #[test]
+    fn invalid_batches_are_not_applied() {
+        let mut decoder = Decoder::new(
+            CURRENT_ENCODING_VERSION,
+            vec![("A:1".to_string(), Network::new(10, 10, 0))],
+        )
+        .unwrap();
+        let before = decoder.clone();
+
+        let result = decoder.decompress(&[
+            CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                    epoch_number: None,
+                    accelerations: vec![5],
+                    root: [0; 32],
+                },
+            ),
+            CompressedMessage::RegisterNetworks {
+                remove: vec![1],
+                add: vec![],
+            },
+        ]);
+        assert!(matches!(result, Err(Error::UnknownNetworkIndex(1))));
+        assert_eq!(decoder, before);
+    }
+
+    #[test]
+    fn block_numbers_cant_go_below_zero() {
+        let mut decoder = Decoder::new(
+            CURRENT_ENCODING_VERSION,
+            vec![("A:1".to_string(), Network::new(10, 10, 0))],
+        )
+        .unwrap();
+        let result = decoder.decompress(&[CompressedMessage::SetBlockNumbersForNextEpoch(
+            CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                epoch_number: None,
+                accelerations: vec![-30],
+                root: [0; 32],
+            },
+        )]);
+        assert!(matches!(result, Err(Error::InvalidBlockDelta { .. })));
+    }
+
+    #[test]
+    fn correct_epochs_is_validated() {
+        let mut decoder = Decoder::new(
+            CURRENT_ENCODING_VERSION,
+            vec![("A:1".to_string(), Network::new(10, 10, 0))],
+        )
+        .unwrap();
+        let correction = |epoch_number, index| CompressedMessage::CorrectEpochs {
+            epoch_number,
+            merkle_root: [0; 32],
+            data_by_network_id: [(index, 5)].into_iter().collect(),
+        };
+
+        assert!(matches!(
+            decoder.decompress(&[correction(0, 0)]),
+            Err(Error::CannotCorrectEpochZero)
+        ));
+        assert!(matches!(
+            decoder.decompress(&[correction(1, 1)]),
+            Err(Error::UnknownNetworkIndex(1))
+        ));
+        assert_eq!(decoder.decompress(&[correction(1, 0)]).unwrap(), []);
+        assert_eq!(decoder.networks()[0].1, Network::new(10, 10, 0));
+    }
+}
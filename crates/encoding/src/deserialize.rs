@@ -0,0 +1,669 @@
+use crate::messages::*;
+use crate::CURRENT_ENCODING_VERSION;
+use std::collections::BTreeSet;
+
+const PREAMBLE_BIT_LENGTH: usize = 8;
+const TAG_BIT_LENGTH: usize = 4;
+const PREAMBLE_CAPACITY: usize = PREAMBLE_BIT_LENGTH / TAG_BIT_LENGTH;
+const TAG_MASK: u8 = (1 << TAG_BIT_LENGTH) - 1;
+
+/// Something that went wrong when decoding a payload with [`deserialize_messages`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("Unexpected end of payload at offset {offset}: needed {needed} more byte(s)")]
+    UnexpectedEnd { offset: usize, needed: usize },
+    #[error("Unknown message tag {tag} in the preamble at offset {offset}")]
+    UnknownTag { tag: u8, offset: usize },
+    #[error("Invalid UTF-8 string at offset {offset}")]
+    InvalidString { offset: usize },
+    #[error(
+        "Network index {index} is out of bounds: only {network_count} networks are registered"
+    )]
+    NetworkIndexOutOfBounds { index: u64, network_count: u64 },
+    #[error("Network index {index} is removed more than once")]
+    DuplicateNetworkIndex { index: u64 },
+}
+
+/// Decodes a payload produced by [`serialize_messages`](crate::serialize_messages) back into
+/// [`CompressedMessage`]s.
+///
+/// `SetBlockNumbersForNextEpoch` messages don't carry their own length: the amount of
+/// accelerations is the number of registered networks at that point in the payload. That's why
+/// the caller must provide the `network_count` before the first message; it is then kept up to
+/// date as `RegisterNetworks`, `RegisterNetworksAndAliases` and `Reset` messages are decoded, the
+/// same way the Epoch Subgraph does it.
+///
+/// Likewise, whether `SetBlockNumbersForNextEpoch` messages start with an epoch number depends on
+/// the `encoding_version`, which `UpdateVersion` messages change.
+pub fn deserialize_messages(
+    bytes: &[u8],
+    encoding_version: u64,
+    network_count: u64,
+) -> Result<Vec<CompressedMessage>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let mut encoding_version = encoding_version;
+    let mut network_count = network_count;
+    let mut messages = Vec::new();
+
+    while !reader.is_empty() {
+        let preamble_offset = reader.offset;
+        let preamble = reader.u8()?;
+
+        for i in 0..PREAMBLE_CAPACITY {
+            // The last preamble might be only partially filled, in which case the remaining tags
+            // are just padding.
+            if reader.is_empty() {
+                break;
+            }
+
+            let tag = (preamble >> (TAG_BIT_LENGTH * i)) & TAG_MASK;
+            let message = deserialize_message(
+                &mut reader,
+                tag,
+                encoding_version,
+                network_count,
+                preamble_offset,
+            )?;
+            network_count = network_count_after(&message, network_count)?;
+            if let CompressedMessage::UpdateVersion { version_number } = message {
+                encoding_version = version_number;
+            }
+            messages.push(message);
+        }
+    }
+
+    Ok(messages)
+}
+
+fn deserialize_message(
+    reader: &mut Reader,
+    tag: u8,
+    encoding_version: u64,
+    network_count: u64,
+    preamble_offset: usize,
+) -> Result<CompressedMessage, DecodeError> {
+    let message = match tag {
+        0 => CompressedMessage::SetBlockNumbersForNextEpoch(
+            deserialize_set_block_numbers_for_next_epoch(reader, encoding_version, network_count)?,
+        ),
+        1 => {
+            let epoch_number = reader.u64()?;
+            let merkle_root = reader.array::<32>()?;
+            let corrections = reader.vec(|r| Ok((r.u64()?, r.u64()?)))?;
+            check_network_indices(corrections.iter().map(|(index, _)| index), network_count)?;
+            CompressedMessage::CorrectEpochs {
+                epoch_number,
+                merkle_root,
+                data_by_network_id: corrections.into_iter().collect(),
+            }
+        }
+        2 => CompressedMessage::UpdateVersion {
+            version_number: reader.u64()?,
+        },
+        3 => {
+            let remove = deserialize_network_indices(reader)?;
+            let add = reader.vec(|r| r.str())?;
+            CompressedMessage::RegisterNetworks { remove, add }
+        }
+        4 => {
+            let address = reader.array::<20>()?;
+            let valid_through = reader.u64()?;
+            let permissions = reader.vec(|r| r.u64())?;
+            CompressedMessage::ChangePermissions {
+                address,
+                valid_through,
+                permissions,
+            }
+        }
+        5 => {
+            // `Reset` has no contents, but it's serialized as a zero nonetheless.
+            reader.u64()?;
+            CompressedMessage::Reset
+        }
+        6 => {
+            let remove = deserialize_network_indices(reader)?;
+            let add = reader.vec(|r| Ok((r.str()?, r.str()?)))?;
+            CompressedMessage::RegisterNetworksAndAliases { remove, add }
+        }
+        7 => CompressedMessage::CorrectLastEpoch {
+            chain_id: reader.str()?,
+            block_number: reader.u64()?,
+            merkle_root: reader.array::<32>()?,
+        },
+        _ => {
+            return Err(DecodeError::UnknownTag {
+                tag,
+                offset: preamble_offset,
+            })
+        }
+    };
+    Ok(message)
+}
+
+fn deserialize_set_block_numbers_for_next_epoch(
+    reader: &mut Reader,
+    encoding_version: u64,
+    network_count: u64,
+) -> Result<CompressedSetBlockNumbersForNextEpoch, DecodeError> {
+    if network_count == 0 {
+        return Ok(CompressedSetBlockNumbersForNextEpoch::Empty {
+            count: reader.u64()?,
+        });
+    }
+
+    let epoch_number = match encoding_version {
+        CURRENT_ENCODING_VERSION => None,
+        _ => Some(reader.u64()?),
+    };
+    let root = reader.array::<32>()?;
+    let accelerations = (0..network_count)
+        .map(|_| reader.i64())
+        .collect::<Result<_, _>>()?;
+    Ok(CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+        epoch_number,
+        accelerations,
+        root,
+    })
+}
+
+fn deserialize_network_indices(reader: &mut Reader) -> Result<Vec<NetworkIndex>, DecodeError> {
+    reader.vec(|r| r.u64())
+}
+
+/// Keeps track of how many networks are registered after `message`, so that the following
+/// `SetBlockNumbersForNextEpoch` messages can be decoded.
+fn network_count_after(
+    message: &CompressedMessage,
+    network_count: u64,
+) -> Result<u64, DecodeError> {
+    let (remove, add_count) = match message {
+        CompressedMessage::RegisterNetworks { remove, add } => (remove, add.len()),
+        CompressedMessage::RegisterNetworksAndAliases { remove, add } => (remove, add.len()),
+        CompressedMessage::Reset => return Ok(0),
+        _ => return Ok(network_count),
+    };
+
+    // Just like the Epoch Subgraph, indices are checked against the network list as it was
+    // before any removals.
+    check_network_indices(remove, network_count)?;
+    // The Epoch Subgraph can't remove the same network twice.
+    let mut removed = BTreeSet::new();
+    if let Some(index) = remove.iter().find(|index| !removed.insert(**index)) {
+        return Err(DecodeError::DuplicateNetworkIndex { index: *index });
+    }
+
+    Ok(network_count.saturating_sub(remove.len() as u64) + add_count as u64)
+}
+
+fn check_network_indices<'a>(
+    indices: impl IntoIterator<Item = &'a NetworkIndex>,
+    network_count: u64,
+) -> Result<(), DecodeError> {
+    match indices.into_iter().find(|index| **index >= network_count) {
+        Some(index) => Err(DecodeError::NetworkIndexOutOfBounds {
+            index: *index,
+            network_count,
+        }),
+        None => Ok(()),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        let remaining = self.bytes.len() - self.offset;
+        if n > remaining {
+            return Err(DecodeError::UnexpectedEnd {
+                offset: self.offset,
+                needed: n - remaining,
+            });
+        }
+        let slice = &self.bytes[self.offset..self.offset + n];
+        self.offset += n;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn vec<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Vec<T>, DecodeError> {
+        let len = self.u64()?;
+        // `len` comes straight from the payload, so we don't pre-allocate. Every element takes at
+        // least one byte, so a bogus `len` quickly runs into the end of the payload.
+        let mut elements = Vec::new();
+        for _ in 0..len {
+            elements.push(f(self)?);
+        }
+        Ok(elements)
+    }
+
+    fn str(&mut self) -> Result<String, DecodeError> {
+        let len = usize::try_from(self.u64()?).unwrap_or(usize::MAX);
+        let offset = self.offset;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidString { offset })
+    }
+
+    fn i64(&mut self) -> Result<i64, DecodeError> {
+        // Uses ZigZag encoding. See
+        // <https://developers.google.com/protocol-buffers/docs/encoding#signed-ints>.
+        let unsigned = self.u64()?;
+        Ok((unsigned >> 1) as i64 ^ -((unsigned & 1) as i64))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        let offset = self.offset;
+        let first = *self
+            .bytes
+            .get(offset)
+            .ok_or(DecodeError::UnexpectedEnd { offset, needed: 1 })?;
+        // The number of trailing zeros in the first byte tells us how many bytes follow it.
+        let num_bytes = (first.trailing_zeros() as usize).min(8) + 1;
+        let bytes = self.take(num_bytes)?;
+
+        // Whatever bits are left in the first byte after the length prefix are the least
+        // significant ones.
+        let first_byte_bits = 8usize.saturating_sub(num_bytes);
+        let mut value = if first_byte_bits > 0 {
+            (first >> num_bytes) as u64
+        } else {
+            0
+        };
+        for (i, byte) in bytes[1..].iter().enumerate() {
+            value |= (*byte as u64) << (8 * i + first_byte_bits);
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialize_messages;
+    use proptest::prelude::*;
+
+    fn decode_u64(bytes: &[u8]) -> Result<u64, DecodeError> {
+        Reader::new(bytes).u64()
+    }
+
+    fn serialize(messages: &[CompressedMessage]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        serialize_messages(messages, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn decode_u64_vectors() {
+        let vectors: &[(u64, &[u8])] = &[
+            (0, &[1]),
+            (23, &[47]),
+            (9000, &[162, 140]),
+            (1455594, &[84, 175, 177]),
+            (72057594037927935, &[128, 255, 255, 255, 255, 255, 255, 255]),
+            (u64::MAX, &[0, 255, 255, 255, 255, 255, 255, 255, 255]),
+        ];
+        for (value, bytes) in vectors {
+            assert_eq!(decode_u64(bytes), Ok(*value));
+        }
+    }
+
+    #[test]
+    fn truncated_u64() {
+        assert_eq!(
+            decode_u64(&[162]),
+            Err(DecodeError::UnexpectedEnd {
+                offset: 0,
+                needed: 1
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_tag() {
+        let error = deserialize_messages(&[0x0F, 1], CURRENT_ENCODING_VERSION, 0).unwrap_err();
+        assert_eq!(error, DecodeError::UnknownTag { tag: 15, offset: 0 });
+    }
+
+    #[test]
+    fn truncated_merkle_root() {
+        let bytes = serialize(&[CompressedMessage::CorrectLastEpoch {
+            chain_id: "eip155:1".to_string(),
+            block_number: 1,
+            merkle_root: [7; 32],
+        }]);
+        let error = deserialize_messages(&bytes[..bytes.len() - 1], CURRENT_ENCODING_VERSION, 0)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            DecodeError::UnexpectedEnd { needed: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn out_of_bounds_removal() {
+        let bytes = serialize(&[CompressedMessage::RegisterNetworks {
+            remove: vec![2],
+            add: vec![],
+        }]);
+        assert_eq!(
+            deserialize_messages(&bytes, CURRENT_ENCODING_VERSION, 2),
+            Err(DecodeError::NetworkIndexOutOfBounds {
+                index: 2,
+                network_count: 2
+            })
+        );
+    }
+
+    #[test]
+    fn duplicate_removal() {
+        let bytes = serialize(&[CompressedMessage::RegisterNetworks {
+            remove: vec![1, 0, 1],
+            add: vec![],
+        }]);
+        assert_eq!(
+            deserialize_messages(&bytes, CURRENT_ENCODING_VERSION, 2),
+            Err(DecodeError::DuplicateNetworkIndex { index: 1 })
+        );
+    }
+
+    #[test]
+    fn block_numbers_depend_on_registered_networks() {
+        let messages = vec![
+            CompressedMessage::Reset,
+            CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::Empty { count: 3 },
+            ),
+            CompressedMessage::RegisterNetworksAndAliases {
+                remove: vec![],
+                add: vec![
+                    ("eip155:1".to_string(), "mainnet".to_string()),
+                    ("eip155:100".to_string(), "gnosis".to_string()),
+                ],
+            },
+            CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                    epoch_number: None,
+                    accelerations: vec![15, -3],
+                    root: [1; 32],
+                },
+            ),
+            CompressedMessage::RegisterNetworks {
+                remove: vec![0],
+                add: vec![],
+            },
+            CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                    epoch_number: None,
+                    accelerations: vec![i64::MIN],
+                    root: [2; 32],
+                },
+            ),
+        ];
+        let bytes = serialize(&messages);
+        // The initial network count is irrelevant because of the `Reset`.
+        assert_eq!(
+            deserialize_messages(&bytes, CURRENT_ENCODING_VERSION, 42),
+            Ok(messages)
+        );
+    }
+
+    #[test]
+    fn out_of_bounds_correction() {
+        let bytes = serialize(&[CompressedMessage::CorrectEpochs {
+            epoch_number: 3,
+            merkle_root: [0; 32],
+            data_by_network_id: [(1, 100)].into_iter().collect(),
+        }]);
+        assert_eq!(
+            deserialize_messages(&bytes, CURRENT_ENCODING_VERSION, 1),
+            Err(DecodeError::NetworkIndexOutOfBounds {
+                index: 1,
+                network_count: 1
+            })
+        );
+    }
+
+    fn arb_bytes32() -> impl Strategy<Value = Bytes32> {
+        any::<[u8; 32]>()
+    }
+
+    fn arb_message() -> impl Strategy<Value = CompressedMessage> {
+        prop_oneof![
+            any::<u64>().prop_map(|count| CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::Empty { count }
+            )),
+            (
+                any::<u64>(),
+                prop::collection::vec(any::<i64>(), 0..8),
+                arb_bytes32()
+            )
+                .prop_map(|(epoch_number, accelerations, root)| {
+                    CompressedMessage::SetBlockNumbersForNextEpoch(
+                        CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                            epoch_number: Some(epoch_number),
+                            accelerations,
+                            root,
+                        },
+                    )
+                }),
+            any::<u64>()
+                .prop_map(|version_number| CompressedMessage::UpdateVersion { version_number }),
+            (
+                prop::collection::vec(any::<u64>(), 0..4),
+                prop::collection::vec(".*", 0..4)
+            )
+                .prop_map(|(remove, add)| CompressedMessage::RegisterNetworks { remove, add }),
+            (
+                any::<[u8; 20]>(),
+                any::<u64>(),
+                prop::collection::vec(any::<u64>(), 0..8)
+            )
+                .prop_map(|(address, valid_through, permissions)| {
+                    CompressedMessage::ChangePermissions {
+                        address,
+                        valid_through,
+                        permissions,
+                    }
+                }),
+            Just(CompressedMessage::Reset),
+            (
+                prop::collection::vec(any::<u64>(), 0..4),
+                prop::collection::vec((".*", ".*"), 0..4)
+            )
+                .prop_map(|(remove, add)| {
+                    CompressedMessage::RegisterNetworksAndAliases { remove, add }
+                }),
+            (
+                any::<u64>(),
+                arb_bytes32(),
+                prop::collection::btree_map(any::<u64>(), any::<u64>(), 0..4)
+            )
+                .prop_map(|(epoch_number, merkle_root, data_by_network_id)| {
+                    CompressedMessage::CorrectEpochs {
+                        epoch_number,
+                        merkle_root,
+                        data_by_network_id,
+                    }
+                }),
+            (".*", any::<u64>(), arb_bytes32()).prop_map(
+                |(chain_id, block_number, merkle_root)| CompressedMessage::CorrectLastEpoch {
+                    chain_id,
+                    block_number,
+                    merkle_root,
+                }
+            ),
+        ]
+    }
+
+    /// Arbitrary messages aren't necessarily valid in sequence, so we tweak them to match the
+    /// encoding version and the amount of registered networks at each point.
+    fn make_consistent(
+        encoding_version: u64,
+        network_count: u64,
+        messages: Vec<CompressedMessage>,
+    ) -> Vec<CompressedMessage> {
+        let mut encoding_version = encoding_version;
+        let mut network_count = network_count;
+        let mut consistent = Vec::with_capacity(messages.len());
+        for message in messages {
+            let message = match message {
+                CompressedMessage::SetBlockNumbersForNextEpoch(set) => {
+                    let (count, epoch_number, mut accelerations, root) = match set {
+                        CompressedSetBlockNumbersForNextEpoch::Empty { count } => {
+                            (count, Some(0), vec![], [0; 32])
+                        }
+                        CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                            epoch_number,
+                            accelerations,
+                            root,
+                        } => (
+                            accelerations.len() as u64,
+                            epoch_number,
+                            accelerations,
+                            root,
+                        ),
+                    };
+                    let set = if network_count == 0 {
+                        CompressedSetBlockNumbersForNextEpoch::Empty { count }
+                    } else {
+                        accelerations.resize(network_count as usize, 1);
+                        CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                            epoch_number: epoch_number
+                                .filter(|_| encoding_version != CURRENT_ENCODING_VERSION),
+                            accelerations,
+                            root,
+                        }
+                    };
+                    CompressedMessage::SetBlockNumbersForNextEpoch(set)
+                }
+                CompressedMessage::RegisterNetworks { remove, add } => {
+                    CompressedMessage::RegisterNetworks {
+                        remove: valid_removals(network_count, remove),
+                        add,
+                    }
+                }
+                CompressedMessage::RegisterNetworksAndAliases { remove, add } => {
+                    CompressedMessage::RegisterNetworksAndAliases {
+                        remove: valid_removals(network_count, remove),
+                        add,
+                    }
+                }
+                CompressedMessage::CorrectEpochs {
+                    epoch_number,
+                    merkle_root,
+                    data_by_network_id,
+                } => CompressedMessage::CorrectEpochs {
+                    epoch_number,
+                    merkle_root,
+                    data_by_network_id: data_by_network_id
+                        .into_iter()
+                        .filter(|(index, _)| *index < network_count)
+                        .collect(),
+                },
+                other => other,
+            };
+            network_count = network_count_after(&message, network_count).unwrap();
+            if let CompressedMessage::UpdateVersion { version_number } = message {
+                encoding_version = version_number;
+            }
+            consistent.push(message);
+        }
+        consistent
+    }
+
+    fn valid_removals(network_count: u64, remove: Vec<u64>) -> Vec<u64> {
+        let mut valid: Vec<u64> = Vec::new();
+        for index in remove {
+            if network_count > 0 && !valid.contains(&(index % network_count)) {
+                valid.push(index % network_count);
+            }
+        }
+        valid
+    }
+
+    proptest! {
+        #[test]
+        fn u64_round_trip(value: u64) {
+            let mut bytes = Vec::new();
+            serialize_messages(
+                &[CompressedMessage::UpdateVersion { version_number: value }],
+                &mut bytes,
+            );
+            prop_assert_eq!(decode_u64(&bytes[1..]), Ok(value));
+        }
+
+        #[test]
+        fn messages_round_trip(
+            encoding_version in 0u64..2,
+            network_count in 0u64..6,
+            messages in prop::collection::vec(arb_message(), 0..12),
+        ) {
+            let messages = make_consistent(encoding_version, network_count, messages);
+            let bytes = serialize(&messages);
+            prop_assert_eq!(
+                deserialize_messages(&bytes, encoding_version, network_count),
+                Ok(messages)
+            );
+        }
+
+        #[test]
+        fn duplicate_removals_are_rejected(
+            network_count in 1u64..6,
+            remove in prop::collection::vec(any::<u64>(), 1..6),
+            duplicate in any::<prop::sample::Index>(),
+            aliases: bool,
+        ) {
+            let mut remove: Vec<u64> = remove.into_iter().map(|i| i % network_count).collect();
+            remove.push(remove[duplicate.index(remove.len())]);
+            let message = if aliases {
+                CompressedMessage::RegisterNetworksAndAliases { remove, add: vec![] }
+            } else {
+                CompressedMessage::RegisterNetworks { remove, add: vec![] }
+            };
+            let bytes = serialize(&[message]);
+            let result = deserialize_messages(&bytes, CURRENT_ENCODING_VERSION, network_count);
+            prop_assert!(
+                matches!(result, Err(DecodeError::DuplicateNetworkIndex { .. })),
+                "{:?}",
+                result
+            );
+        }
+
+        #[test]
+        fn truncated_payloads_never_panic(
+            encoding_version in 0u64..2,
+            network_count in 0u64..6,
+            messages in prop::collection::vec(arb_message(), 1..6),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            let messages = make_consistent(encoding_version, network_count, messages);
+            let bytes = serialize(&messages);
+            let cut = cut.index(bytes.len());
+            // Truncating might still produce a valid payload by chance, we just want to make sure
+            // there's no panic.
+            let _ = deserialize_messages(&bytes[..cut], encoding_version, network_count);
+        }
+    }
+}
@@ -1,15 +1,30 @@
+mod decoder;
+mod deserialize;
 mod merkle;
 pub mod messages;
 mod serialize;
 
-use merkle::{merkle_root, MerkleLeaf};
 use messages::*;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
+pub use decoder::{DecodedEpoch, Decoder};
+pub use deserialize::{deserialize_messages, DecodeError};
+pub use merkle::{merkle_proof, merkle_root, MerkleLeaf, MerkleProof};
 pub use messages::{BlockPtr, CompressedMessage, CompressedSetBlockNumbersForNextEpoch, Message};
 pub use serialize::serialize_messages;
 
 pub const CURRENT_ENCODING_VERSION: u64 = 0;
+/// The first encoding version in which `SetBlockNumbersForNextEpoch` messages carry the number of
+/// the epoch they're for, so that payloads that land late or twice can be told apart.
+pub const EPOCH_NUMBER_ENCODING_VERSION: u64 = 1;
+
+/// Fails unless `encoding_version` is one that the [`Encoder`] and [`Decoder`] understand.
+pub(crate) fn check_encoding_version(encoding_version: u64) -> Result<(), Error> {
+    match encoding_version {
+        CURRENT_ENCODING_VERSION | EPOCH_NUMBER_ENCODING_VERSION => Ok(()),
+        _ => Err(Error::UnsupportedEncodingVersion(encoding_version)),
+    }
+}
 
 /// Something that went wrong when using the [`Encoder`].
 #[derive(Debug, thiserror::Error)]
@@ -22,6 +37,28 @@ pub enum Error {
     MessageAfterEncodingVersionChange,
     #[error("Invalid Network ID: {0}")]
     InvalidNetworkId(String),
+    #[error("Unknown network index: {0}")]
+    UnknownNetworkIndex(NetworkIndex),
+    #[error("Network {0} is already registered")]
+    NetworkAlreadyRegistered(String),
+    #[error("Expected {expected} accelerations, one per registered network, but found {found}")]
+    AccelerationCountMismatch { expected: usize, found: usize },
+    #[error("Acceleration {acceleration} for network {network_id} at block {block_number} results in an invalid block delta")]
+    InvalidBlockDelta {
+        network_id: String,
+        block_number: u64,
+        acceleration: i64,
+    },
+    #[error("Epoch 0 can't be corrected")]
+    CannotCorrectEpochZero,
+    #[error(
+        "Encoding version {0} requires the epoch number of SetBlockNumbersForNextEpoch messages"
+    )]
+    MissingEpochNumber(u64),
+    #[error("Encoding version {0} doesn't support epoch numbers in SetBlockNumbersForNextEpoch messages")]
+    UnexpectedEpochNumber(u64),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
 }
 
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
@@ -50,24 +87,38 @@ impl Network {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Encoder {
     networks: Vec<(String, Network)>,
+    /// Like the Epoch Subgraph, the [`Encoder`] picks up from the latest block number of networks
+    /// that are removed and then registered again.
+    removed_networks: BTreeMap<String, Network>,
     encoding_version: u64,
     compressed: Vec<CompressedMessage>,
 }
 
 impl Encoder {
     /// Creates a new [`Encoder`] with the specificied initial state.
-    pub fn new(encoding_version: u64, networks: Vec<(String, Network)>) -> Result<Self, Error> {
-        if encoding_version != CURRENT_ENCODING_VERSION {
-            return Err(Error::UnsupportedEncodingVersion(encoding_version));
-        }
+    pub fn new(encoding_version: u64, mut networks: Vec<(String, Network)>) -> Result<Self, Error> {
+        check_encoding_version(encoding_version)?;
 
+        // Network indices are positions in this list.
+        networks.sort_by_key(|(_, network)| network.array_index);
         Ok(Self {
             encoding_version,
             networks,
+            removed_networks: BTreeMap::new(),
             compressed: Vec::new(),
         })
     }
 
+    /// Sets the networks that were removed before the [`Encoder`] was created, so that they pick up
+    /// from their latest block number if they are registered again.
+    pub fn with_removed_networks(
+        mut self,
+        removed_networks: impl IntoIterator<Item = (String, Network)>,
+    ) -> Self {
+        self.removed_networks.extend(removed_networks);
+        self
+    }
+
     pub fn network_deltas(&self) -> &[(String, Network)] {
         &self.networks
     }
@@ -110,36 +161,57 @@ impl Encoder {
         }
 
         match message {
-            Message::SetBlockNumbersForNextEpoch(block_ptrs) => {
-                // There are separate cases for empty sets and non-empty sets.
-                if block_ptrs.is_empty() {
+            Message::SetBlockNumbersForNextEpoch {
+                epoch_number,
+                block_ptrs,
+            } => {
+                // Only epochs without registered networks can be encoded as empty, because that's
+                // how they're told apart when decoding. Otherwise, networks without a new block
+                // keep their previous block number.
+                if block_ptrs.is_empty() && self.networks.is_empty() {
                     self.compress_empty_block_ptrs();
                 } else {
-                    self.compress_block_ptrs(block_ptrs.clone())?;
+                    let epoch_number = match self.encoding_version {
+                        CURRENT_ENCODING_VERSION => None,
+                        version => Some(epoch_number.ok_or(Error::MissingEpochNumber(version))?),
+                    };
+                    self.compress_block_ptrs(epoch_number, block_ptrs.clone())?;
                 }
             }
             Message::RegisterNetworks { remove, add } => {
-                for index in remove {
-                    self.remove_network(*index);
-                }
-                for id in add {
-                    self.add_network(id);
-                }
-
+                self.register_networks(remove, add.iter())?;
                 self.compressed.push(CompressedMessage::RegisterNetworks {
                     remove: remove.clone(),
                     add: add.clone(),
                 });
             }
-            Message::CorrectEpochs { data_by_network_id } => {
+            Message::CorrectEpochs {
+                epoch_number,
+                merkle_root,
+                data_by_network_id,
+            } => {
+                // Correcting the first epoch would leave nothing to recompute deltas from.
+                if *epoch_number == 0 {
+                    return Err(Error::CannotCorrectEpochZero);
+                }
+                if let Some(index) = data_by_network_id
+                    .keys()
+                    .find(|index| **index >= self.networks.len() as u64)
+                {
+                    return Err(Error::UnknownNetworkIndex(*index));
+                }
+
+                // The block numbers of later epochs stay the same, and only their deltas and
+                // accelerations are recomputed. The [`Encoder`] doesn't know which epoch its
+                // network state belongs to, so it's up to the caller to refresh it afterwards.
                 self.compressed.push(CompressedMessage::CorrectEpochs {
+                    epoch_number: *epoch_number,
+                    merkle_root: *merkle_root,
                     data_by_network_id: data_by_network_id.clone(),
                 });
             }
             Message::UpdateVersion { version_number } => {
-                if *version_number != CURRENT_ENCODING_VERSION {
-                    return Err(Error::UnsupportedEncodingVersion(*version_number));
-                }
+                check_encoding_version(*version_number)?;
 
                 self.encoding_version = *version_number;
                 self.compressed.push(CompressedMessage::UpdateVersion {
@@ -148,16 +220,11 @@ impl Encoder {
             }
             Message::Reset => {
                 self.networks.clear();
+                self.removed_networks.clear();
                 self.compressed.push(CompressedMessage::Reset);
             }
             Message::RegisterNetworksAndAliases { remove, add } => {
-                for index in remove {
-                    self.remove_network(*index);
-                }
-                for (id, _) in add {
-                    self.add_network(id);
-                }
-
+                self.register_networks(remove, add.iter().map(|(id, _alias)| id))?;
                 self.compressed
                     .push(CompressedMessage::RegisterNetworksAndAliases {
                         remove: remove.clone(),
@@ -193,22 +260,46 @@ impl Encoder {
         Ok(())
     }
 
-    fn add_network(&mut self, id: &str) {
-        self.networks.push((id.to_string(), Network::default()));
-    }
-
-    /// Remove a network from [`Encoder.networks`].
+    /// Removes and registers networks the same way the Epoch Subgraph does. Removals refer to
+    /// network indices from before the message, the remaining networks keep their order, new
+    /// networks are appended, and finally every network is reindexed by its position.
     ///
-    /// Removal occurs by position, based on the `array_index` field of the target element.
-    fn remove_network(&mut self, network_index: NetworkIndex) {
-        let position = self
-            .networks
-            .iter()
-            .position(|(_, network)| network.array_index == network_index)
-            .unwrap_or_else(|| {
-                panic!("Failed to find the a network with array_index equal to {network_index}")
-            });
-        self.networks.remove(position);
+    /// Fails without changing anything if an index is out of bounds or removed twice, or if a
+    /// network is registered while it is still registered, even if the same message removes it.
+    fn register_networks<'a>(
+        &mut self,
+        remove: &[NetworkIndex],
+        add: impl Iterator<Item = &'a String>,
+    ) -> Result<(), Error> {
+        let mut removed_indices = BTreeSet::new();
+        for index in remove {
+            if *index >= self.networks.len() as NetworkIndex || !removed_indices.insert(*index) {
+                return Err(Error::UnknownNetworkIndex(*index));
+            }
+        }
+        let add: Vec<&String> = add.collect();
+        for (i, id) in add.iter().enumerate() {
+            if self.network_index(id).is_some() || add[..i].contains(id) {
+                return Err(Error::NetworkAlreadyRegistered(id.to_string()));
+            }
+        }
+
+        let networks = std::mem::take(&mut self.networks);
+        for (i, (id, network)) in networks.into_iter().enumerate() {
+            if removed_indices.contains(&(i as NetworkIndex)) {
+                self.removed_networks.insert(id, network);
+            } else {
+                self.networks.push((id, network));
+            }
+        }
+        for id in add {
+            let network = self.removed_networks.remove(id).unwrap_or_default();
+            self.networks.push((id.clone(), network));
+        }
+        for (i, (_, network)) in self.networks.iter_mut().enumerate() {
+            network.array_index = i as NetworkIndex;
+        }
+        Ok(())
     }
 
     /// Takes in some network data by network ID and turns it into a [`Vec`] with the correct
@@ -231,13 +322,14 @@ impl Encoder {
             })
             .collect::<Result<Vec<(NetworkIndex, T)>, Error>>()?;
         // Sort by network index.
-        sorted.sort_by(|(i, _), (j, _)| i.cmp(j));
+        sorted.sort_by_key(|(i, _)| *i);
         // Now remove the network index, which is implied by element positioning within the vector.
         Ok(sorted.into_iter().map(|(_, x)| x).collect())
     }
 
     fn compress_block_ptrs(
         &mut self,
+        epoch_number: Option<u64>,
         mut block_ptrs: BTreeMap<String, BlockPtr>,
     ) -> Result<(), Error> {
         for network in &self.networks {
@@ -277,6 +369,7 @@ impl Encoder {
         self.compressed
             .push(CompressedMessage::SetBlockNumbersForNextEpoch(
                 CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                    epoch_number,
                     accelerations,
                     root: merkle_root(&merkle_leaves),
                 },
@@ -317,9 +410,10 @@ mod tests {
         let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, networks).unwrap();
         let block_updates = vec![("A:1".to_string(), BlockPtr::new(1, [0; 32]))];
         let compressed = encoder
-            .compress(&[Message::SetBlockNumbersForNextEpoch(
-                block_updates.into_iter().collect(),
-            )])
+            .compress(&[Message::SetBlockNumbersForNextEpoch {
+                epoch_number: None,
+                block_ptrs: block_updates.into_iter().collect(),
+            }])
             .unwrap();
 
         let accelerations = compressed
@@ -344,9 +438,10 @@ mod tests {
             ("B:2".to_string(), BlockPtr::new(250, [0; 32])),
         ];
         let compressed = encoder
-            .compress(&[Message::SetBlockNumbersForNextEpoch(
-                block_updates.into_iter().collect(),
-            )])
+            .compress(&[Message::SetBlockNumbersForNextEpoch {
+                epoch_number: None,
+                block_ptrs: block_updates.into_iter().collect(),
+            }])
             .unwrap();
 
         let accelerations = compressed
@@ -362,33 +457,26 @@ mod tests {
     fn pipeline() {
         let mut messages = Vec::new();
 
-        // Skip some empty epochs
+        // Skip some empty epochs, before any network is registered.
         for _ in 0..20 {
-            messages.push(Message::SetBlockNumbersForNextEpoch(BTreeMap::new()));
+            messages.push(Message::SetBlockNumbersForNextEpoch {
+                epoch_number: None,
+                block_ptrs: BTreeMap::new(),
+            });
         }
 
-        let networks: Vec<_> = ["A:1991", "B:2kl", "C:190", "D:18818"]
-            .iter()
-            .map(|i| i.to_string())
-            .enumerate()
-            .map(|(i, s)| {
-                (
-                    s,
-                    Network {
-                        block_number: 0,
-                        block_delta: 0,
-                        array_index: i as u64,
-                    },
-                )
-            })
-            .collect();
+        let networks = ["A:1991", "B:2kl", "C:190", "D:18818"];
+        messages.push(Message::RegisterNetworks {
+            remove: vec![],
+            add: networks.iter().map(|id| id.to_string()).collect(),
+        });
 
         // Add blocks
         for i in 0..4 {
             let nums = networks
                 .iter()
                 .enumerate()
-                .map(|(network_i, (name, _network))| {
+                .map(|(network_i, name)| {
                     (
                         name.to_string(),
                         BlockPtr {
@@ -398,28 +486,47 @@ mod tests {
                     )
                 })
                 .collect();
-            messages.push(Message::SetBlockNumbersForNextEpoch(nums));
+            messages.push(Message::SetBlockNumbersForNextEpoch {
+                epoch_number: None,
+                block_ptrs: nums,
+            });
         }
 
-        let mut engine = Encoder::new(0, networks).unwrap();
-        engine.compress(&messages[..]).unwrap();
-
-        // FIXME
-        //assert!(matches!(
-        //    engine.compressed[0],
-        //    CompressedMessage::SetBlockNumbersForNextEpoch(
-        //        CompressedSetBlockNumbersForNextEpoch::Empty { count: 20 }
-        //    )
-        //));
-        //assert!(matches!(
-        //    engine.compressed.last().unwrap(),
-        //    CompressedMessage::SetBlockNumbersForNextEpoch(
-        //        CompressedSetBlockNumbersForNextEpoch::NonEmpty { .. }
-        //    )
-        //));
-
-        // TODO: Add ability to skip epochs? Right now the way to get past this is to
-        // just add 80 or so SetBlockNumbers.
+        // Once networks are registered, an epoch without blocks keeps all block numbers.
+        messages.push(Message::SetBlockNumbersForNextEpoch {
+            epoch_number: None,
+            block_ptrs: BTreeMap::new(),
+        });
+
+        let mut engine = Encoder::new(0, vec![]).unwrap();
+        let compressed = engine.compress(&messages[..]).unwrap();
+
+        assert_eq!(compressed.len(), 7);
+        assert_eq!(
+            compressed[0],
+            CompressedMessage::SetBlockNumbersForNextEpoch(
+                CompressedSetBlockNumbersForNextEpoch::Empty { count: 20 }
+            )
+        );
+        assert!(matches!(
+            compressed[1],
+            CompressedMessage::RegisterNetworks { .. }
+        ));
+        let accelerations: Vec<_> = compressed[2..]
+            .iter()
+            .map(|message| message.as_non_empty_block_numbers().unwrap().0.to_vec())
+            .collect();
+        assert_eq!(accelerations[0], [0, 300, 600, 900]);
+        assert_eq!(accelerations[1], [301, 1, -299, -599]);
+        assert_eq!(accelerations[2], [0, 0, 0, 0]);
+        assert_eq!(accelerations[4], [-301, -301, -301, -301]);
+
+        // The whole sequence survives serialization.
+        let payload = engine.encode(&compressed);
+        assert_eq!(
+            deserialize_messages(&payload, CURRENT_ENCODING_VERSION, 0).unwrap(),
+            compressed
+        );
     }
 
     #[test]
@@ -439,6 +546,127 @@ mod tests {
         assert_ne!(networks_before, networks_after);
     }
 
+    fn four_networks() -> Vec<(String, Network)> {
+        ["A:1", "B:2", "C:3", "D:4"]
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.to_string(), Network::new(i as u64 * 10, 1, i as u64)))
+            .collect()
+    }
+
+    #[test]
+    fn register_networks_like_the_subgraph() {
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, four_networks()).unwrap();
+        let mut decoder = Decoder::new(CURRENT_ENCODING_VERSION, four_networks()).unwrap();
+        let block_ptrs = |block_numbers: &[(&str, u64)]| Message::SetBlockNumbersForNextEpoch {
+            epoch_number: None,
+            block_ptrs: block_numbers
+                .iter()
+                .map(|(id, number)| (id.to_string(), BlockPtr::new(*number, [0; 32])))
+                .collect(),
+        };
+
+        let compressed = encoder
+            .compress(&[
+                // Indices refer to the networks before the message: A and C are removed, and
+                // then B, D and E are reindexed from 0.
+                Message::RegisterNetworks {
+                    remove: vec![2, 0],
+                    add: vec!["E:5".to_string()],
+                },
+                block_ptrs(&[("B:2", 15), ("D:4", 35), ("E:5", 100)]),
+                // D is now at index 1. C is registered again and picks up from block 20.
+                Message::RegisterNetworksAndAliases {
+                    remove: vec![1],
+                    add: vec![("C:3".to_string(), "c".to_string())],
+                },
+                block_ptrs(&[("B:2", 16), ("E:5", 101), ("C:3", 25)]),
+            ])
+            .unwrap();
+        assert_eq!(
+            compressed[3].as_non_empty_block_numbers().unwrap().0,
+            [-4, -99, 4]
+        );
+
+        let ids: Vec<_> = encoder.networks.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["B:2", "E:5", "C:3"]);
+        let indices: Vec<_> = encoder
+            .networks
+            .iter()
+            .map(|(_, network)| network.array_index)
+            .collect();
+        assert_eq!(indices, [0, 1, 2]);
+
+        // The decoder replays payloads like the subgraph, and ends up in the same state.
+        decoder.decode(&encoder.encode(&compressed)).unwrap();
+        assert_eq!(decoder.networks(), encoder.network_deltas());
+    }
+
+    #[test]
+    fn register_networks_removed_before_the_encoder() {
+        let removed = vec![("C:3".to_string(), Network::new(20, 1, 0))];
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, vec![])
+            .unwrap()
+            .with_removed_networks(removed);
+
+        let compressed = encoder
+            .compress(&[
+                Message::RegisterNetworks {
+                    remove: vec![],
+                    add: vec!["C:3".to_string(), "E:5".to_string()],
+                },
+                Message::SetBlockNumbersForNextEpoch {
+                    epoch_number: None,
+                    block_ptrs: [
+                        ("C:3".to_string(), BlockPtr::new(25, [0; 32])),
+                        ("E:5".to_string(), BlockPtr::new(100, [0; 32])),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
+            ])
+            .unwrap();
+        assert_eq!(
+            compressed[1].as_non_empty_block_numbers().unwrap().0,
+            [4, 100]
+        );
+    }
+
+    #[test]
+    fn invalid_network_registrations() {
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, four_networks()).unwrap();
+        let mut register = |remove: Vec<u64>, add: &[&str]| {
+            encoder.compress(&[Message::RegisterNetworks {
+                remove,
+                add: add.iter().map(|id| id.to_string()).collect(),
+            }])
+        };
+
+        assert!(matches!(
+            register(vec![4], &[]),
+            Err(Error::UnknownNetworkIndex(4))
+        ));
+        // The subgraph can't remove the same network twice.
+        assert!(matches!(
+            register(vec![1, 1], &[]),
+            Err(Error::UnknownNetworkIndex(1))
+        ));
+        assert!(matches!(
+            register(vec![], &["B:2"]),
+            Err(Error::NetworkAlreadyRegistered(id)) if id == "B:2"
+        ));
+        // Networks removed by the same message are still registered while it adds networks.
+        assert!(matches!(
+            register(vec![1], &["B:2"]),
+            Err(Error::NetworkAlreadyRegistered(id)) if id == "B:2"
+        ));
+        assert!(matches!(
+            register(vec![], &["E:5", "E:5"]),
+            Err(Error::NetworkAlreadyRegistered(id)) if id == "E:5"
+        ));
+        assert_eq!(encoder.networks, four_networks());
+    }
+
     #[test]
     fn set_block_numbers_changes_state() {
         let mut encoder = Encoder::new(
@@ -449,22 +677,24 @@ mod tests {
         let networks_before = encoder.networks.clone();
 
         encoder
-            .compress(&[Message::SetBlockNumbersForNextEpoch(
-                vec![("foo:bar".to_string(), BlockPtr::new(42, [0; 32]))]
+            .compress(&[Message::SetBlockNumbersForNextEpoch {
+                epoch_number: None,
+                block_ptrs: vec![("foo:bar".to_string(), BlockPtr::new(42, [0; 32]))]
                     .into_iter()
                     .collect(),
-            )])
+            }])
             .unwrap();
 
         // We didn't update any block numbers.
         assert_eq!(networks_before, encoder.networks);
 
         encoder
-            .compress(&[Message::SetBlockNumbersForNextEpoch(
-                vec![("foo:bar".to_string(), BlockPtr::new(1337, [0; 32]))]
+            .compress(&[Message::SetBlockNumbersForNextEpoch {
+                epoch_number: None,
+                block_ptrs: vec![("foo:bar".to_string(), BlockPtr::new(1337, [0; 32]))]
                     .into_iter()
                     .collect(),
-            )])
+            }])
             .unwrap();
 
         // We did update block numbers, this time around.
@@ -507,6 +737,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn correct_epochs_message() {
+        let networks = vec![
+            ("A:1".to_string(), Network::new(100, 10, 0)),
+            ("B:2".to_string(), Network::new(200, 20, 1)),
+        ];
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, networks).unwrap();
+        let message = Message::CorrectEpochs {
+            epoch_number: 7,
+            merkle_root: [3; 32],
+            data_by_network_id: [(1, 195)].into_iter().collect(),
+        };
+
+        let compressed = encoder.compress(&[message]).unwrap();
+        assert_eq!(
+            compressed,
+            [CompressedMessage::CorrectEpochs {
+                epoch_number: 7,
+                merkle_root: [3; 32],
+                data_by_network_id: [(1, 195)].into_iter().collect(),
+            }]
+        );
+        assert_eq!(encoder.encode(&compressed)[0] & 0x0F, 1);
+    }
+
+    #[test]
+    fn correct_epochs_rejects_invalid_corrections() {
+        let networks = vec![("A:1".to_string(), Network::new(100, 10, 0))];
+        let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, networks).unwrap();
+
+        let epoch_zero = Message::CorrectEpochs {
+            epoch_number: 0,
+            merkle_root: [0; 32],
+            data_by_network_id: [(0, 95)].into_iter().collect(),
+        };
+        assert!(matches!(
+            encoder.compress(&[epoch_zero]),
+            Err(Error::CannotCorrectEpochZero)
+        ));
+
+        let unknown_network = Message::CorrectEpochs {
+            epoch_number: 1,
+            merkle_root: [0; 32],
+            data_by_network_id: [(1, 95)].into_iter().collect(),
+        };
+        assert!(matches!(
+            encoder.compress(&[unknown_network]),
+            Err(Error::UnknownNetworkIndex(1))
+        ));
+    }
+
     #[test]
     fn correct_last_epoch_message() {
         let mut encoder = Encoder::new(CURRENT_ENCODING_VERSION, vec![]).unwrap();
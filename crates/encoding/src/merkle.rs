@@ -2,6 +2,8 @@ use super::Bytes32;
 use crate::NetworkIndex;
 use tiny_keccak::{Hasher, Keccak};
 
+/// A leaf of the Merkle tree posted along with the block numbers of an epoch. There's one per
+/// registered network, sorted by network index.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MerkleLeaf {
     pub network_index: NetworkIndex,
@@ -10,7 +12,7 @@ pub struct MerkleLeaf {
 }
 
 impl MerkleLeaf {
-    fn hash(&self) -> Bytes32 {
+    pub fn hash(&self) -> Bytes32 {
         keccak([
             &self.network_index.to_le_bytes(),
             &self.block_number.to_le_bytes(),
@@ -19,30 +21,72 @@ impl MerkleLeaf {
     }
 }
 
+/// Proves that a [`MerkleLeaf`] is part of a Merkle tree.
+///
+/// Pairs of nodes are sorted before hashing, so the proof is just the sibling of every node on the
+/// path from the leaf to the root. The last node of a level with an odd number of nodes has no
+/// sibling and is carried over to the next level as-is, so it contributes nothing to the proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub leaf: MerkleLeaf,
+    pub siblings: Vec<Bytes32>,
+}
+
+impl MerkleProof {
+    /// Computes the Merkle root that this proof leads to.
+    pub fn root(&self) -> Bytes32 {
+        self.siblings
+            .iter()
+            .fold(self.leaf.hash(), |node, sibling| combine(&node, sibling))
+    }
+
+    /// Checks whether the leaf is part of the Merkle tree with the given root.
+    pub fn verify(&self, root: &Bytes32) -> bool {
+        self.root() == *root
+    }
+}
+
+/// Computes the root of the Merkle tree over `data`. An empty tree has an all-zeros root.
 pub fn merkle_root(data: &[MerkleLeaf]) -> Bytes32 {
     let mut scratch: Vec<Bytes32> = data.iter().map(MerkleLeaf::hash).collect();
 
     while scratch.len() > 1 {
-        let mut write = 0;
-        let mut read = 0;
-        while read + 1 < scratch.len() {
-            let a = scratch[read];
-            let b = scratch[read + 1];
-            read += 2;
-            scratch[write] = combine(&a, &b);
-            write += 1;
-        }
-        if read < scratch.len() {
-            scratch[write] = scratch[read];
-            write += 1;
-        }
-
-        scratch.truncate(write);
+        scratch = next_level(&scratch);
     }
 
     scratch.first().cloned().unwrap_or_default()
 }
 
+/// Generates an inclusion proof for the leaf at `position` within `data`, or `None` if there's no
+/// such leaf.
+pub fn merkle_proof(data: &[MerkleLeaf], position: usize) -> Option<MerkleProof> {
+    let leaf = data.get(position)?.clone();
+    let mut scratch: Vec<Bytes32> = data.iter().map(MerkleLeaf::hash).collect();
+    let mut position = position;
+    let mut siblings = Vec::new();
+
+    while scratch.len() > 1 {
+        if let Some(sibling) = scratch.get(position ^ 1) {
+            siblings.push(*sibling);
+        }
+        position /= 2;
+        scratch = next_level(&scratch);
+    }
+
+    Some(MerkleProof { leaf, siblings })
+}
+
+fn next_level(nodes: &[Bytes32]) -> Vec<Bytes32> {
+    nodes
+        .chunks(2)
+        .map(|pair| match pair {
+            [a, b] => combine(a, b),
+            [a] => *a,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
 fn keccak<const N: usize>(data: [&[u8]; N]) -> Bytes32 {
     let mut hasher = Keccak::v256();
     for elem in data {
@@ -191,7 +235,7 @@ mod tests {
             let leaves: Vec<MerkleLeaf> = (0..size)
                 .map(|i| MerkleLeaf {
                     network_index: i,
-                    block_number: i as u64 + 1000,
+                    block_number: i + 1000,
                     block_hash: {
                         let mut hash = [0; 32];
                         hash[0] = i as u8;
@@ -210,18 +254,83 @@ mod tests {
         }
     }
 
+    fn leaves(size: u64) -> Vec<MerkleLeaf> {
+        (0..size)
+            .map(|i| MerkleLeaf {
+                network_index: i,
+                block_number: 1000 + i,
+                block_hash: [i as u8; 32],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merkle_proofs_lead_to_the_root() {
+        for size in 1..=33 {
+            let leaves = leaves(size);
+            let root = merkle_root(&leaves);
+            for position in 0..leaves.len() {
+                let proof = merkle_proof(&leaves, position).unwrap();
+                assert_eq!(proof.leaf, leaves[position]);
+                assert!(
+                    proof.verify(&root),
+                    "Bad proof for leaf {} out of {}",
+                    position,
+                    size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merkle_proof_with_one_leaf_is_empty() {
+        let leaves = leaves(1);
+        let proof = merkle_proof(&leaves, 0).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert_eq!(proof.root(), leaves[0].hash());
+    }
+
+    #[test]
+    fn merkle_proof_out_of_bounds() {
+        assert_eq!(merkle_proof(&[], 0), None);
+        assert_eq!(merkle_proof(&leaves(3), 3), None);
+    }
+
+    #[test]
+    fn tampered_merkle_proofs_dont_verify() {
+        let leaves = leaves(5);
+        let root = merkle_root(&leaves);
+        let proof = merkle_proof(&leaves, 2).unwrap();
+
+        let mut wrong_block = proof.clone();
+        wrong_block.leaf.block_number += 1;
+        assert!(!wrong_block.verify(&root));
+
+        let mut wrong_index = proof.clone();
+        wrong_index.leaf.network_index = 3;
+        assert!(!wrong_index.verify(&root));
+
+        let mut wrong_sibling = proof.clone();
+        wrong_sibling.siblings[0] = [0; 32];
+        assert!(!wrong_sibling.verify(&root));
+
+        let mut missing_sibling = proof;
+        missing_sibling.siblings.pop();
+        assert!(!missing_sibling.verify(&root));
+    }
+
     #[test]
     fn merkle_root_26_leaves_real_scenario() {
         // Test the exact scenario from the bug report - 26 networks
         let leaves: Vec<MerkleLeaf> = (0..26)
             .map(|i| MerkleLeaf {
                 network_index: i,
-                block_number: 23052969 + (i as u64 * 1000000), // Varying block numbers
+                block_number: 23052969 + (i * 1000000), // Varying block numbers
                 block_hash: {
                     let mut hash = [0; 32];
                     // Create some variety in the hashes
-                    for j in 0..32 {
-                        hash[j] = ((i as usize + j) % 256) as u8;
+                    for (j, byte) in hash.iter_mut().enumerate() {
+                        *byte = ((i as usize + j) % 256) as u8;
                     }
                     hash
                 },
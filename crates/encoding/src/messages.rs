@@ -26,8 +26,12 @@ impl std::fmt::Debug for BlockPtr {
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    // TODO: Consider specifying epoch number here?
-    SetBlockNumbersForNextEpoch(BTreeMap<String, BlockPtr>),
+    SetBlockNumbersForNextEpoch {
+        // The epoch that the block numbers are for. Only encoded from encoding version 1 on, where
+        // it's required.
+        epoch_number: Option<u64>,
+        block_ptrs: BTreeMap<String, BlockPtr>,
+    },
     RegisterNetworks {
         // Remove is by index
         remove: Vec<NetworkIndex>,
@@ -35,8 +39,12 @@ pub enum Message {
         add: Vec<String>,
     },
     CorrectEpochs {
-        // TODO: include hash, count, and (if count is nonzero) merkle root
-        data_by_network_id: BTreeMap<NetworkIndex, EpochDetails>,
+        // Epoch 0 can't be corrected
+        epoch_number: u64,
+        // The new Merkle root for the whole epoch
+        merkle_root: Bytes32,
+        // Corrected block numbers by network index
+        data_by_network_id: BTreeMap<NetworkIndex, u64>,
     },
     UpdateVersion {
         version_number: u64,
@@ -74,13 +82,30 @@ impl Message {
             _ => 8,
         }
     }
+
+    /// The inverse of [`Message::str_to_u64`]. Returns `None` for unknown permissions.
+    pub fn u64_to_str(value: u64) -> Option<&'static str> {
+        match value {
+            0 => Some("SetBlockNumbersForNextEpochMessage"),
+            1 => Some("CorrectEpochsMessage"),
+            2 => Some("UpdateVersionMessage"),
+            3 => Some("RegisterNetworksMessage"),
+            4 => Some("ChangePermissionsMessage"),
+            5 => Some("ResetStateMessage"),
+            6 => Some("RegisterNetworksAndAliasesMessage"),
+            7 => Some("CorrectLastEpochMessage"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum CompressedMessage {
     SetBlockNumbersForNextEpoch(CompressedSetBlockNumbersForNextEpoch),
     CorrectEpochs {
-        data_by_network_id: BTreeMap<NetworkIndex, EpochDetails>,
+        epoch_number: u64,
+        merkle_root: Bytes32,
+        data_by_network_id: BTreeMap<NetworkIndex, u64>,
     },
     RegisterNetworks {
         remove: Vec<u64>,
@@ -113,6 +138,7 @@ impl CompressedMessage {
                 CompressedSetBlockNumbersForNextEpoch::NonEmpty {
                     accelerations,
                     root,
+                    ..
                 },
             ) => Some((accelerations, *root)),
             _ => None,
@@ -125,18 +151,15 @@ pub enum CompressedSetBlockNumbersForNextEpoch {
     Empty {
         count: u64,
     },
+    /// `epoch_number` is `Some` exactly from encoding version 1 on. Empty messages are only sent
+    /// while no networks are registered, so they don't carry an epoch number in any version.
     NonEmpty {
+        epoch_number: Option<u64>,
         accelerations: Vec<i64>,
         root: Bytes32,
     },
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct EpochDetails {
-    tx_hash: Bytes32,
-    merkle_root: Bytes32,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,4 +177,13 @@ mod tests {
         assert_eq!(Message::str_to_u64("UnknownMessage"), 8);
         assert_eq!(Message::str_to_u64("AnotherUnknownMessage"), 8);
     }
+
+    #[test]
+    fn test_u64_to_str_mapping() {
+        for value in 0..8 {
+            let permission = Message::u64_to_str(value).unwrap();
+            assert_eq!(Message::str_to_u64(permission), value);
+        }
+        assert_eq!(Message::u64_to_str(8), None);
+    }
 }
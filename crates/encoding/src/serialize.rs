@@ -1,4 +1,5 @@
-use crate::{messages::*, NetworkIndex};
+use crate::messages::*;
+use std::collections::BTreeMap;
 
 const PREAMBLE_BIT_LENGTH: usize = 8;
 const TAG_BIT_LENGTH: usize = 4;
@@ -38,9 +39,11 @@ fn serialize_message(message: &CompressedMessage, bytes: &mut Vec<u8>) {
             serialize_u64(*version_number, bytes);
         }
         CompressedMessage::Reset => serialize_u64(0, bytes),
-        CompressedMessage::CorrectEpochs { .. } => {
-            todo!()
-        }
+        CompressedMessage::CorrectEpochs {
+            epoch_number,
+            merkle_root,
+            data_by_network_id,
+        } => serialize_correct_epochs(*epoch_number, merkle_root, data_by_network_id, bytes),
         CompressedMessage::RegisterNetworksAndAliases { add, remove } => {
             serialize_register_networks_and_aliases(add, remove, bytes)
         }
@@ -64,9 +67,14 @@ fn serialize_set_block_numbers_for_next_block(
     match block_numbers {
         CompressedSetBlockNumbersForNextEpoch::Empty { count } => serialize_u64(*count, bytes),
         CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+            epoch_number,
             accelerations,
             root,
         } => {
+            // Only present from encoding version 1 on.
+            if let Some(epoch_number) = epoch_number {
+                serialize_u64(*epoch_number, bytes);
+            }
             bytes.extend_from_slice(root);
             for acceleration in accelerations {
                 serialize_i64(*acceleration, bytes);
@@ -120,6 +128,21 @@ fn serialize_change_permissions(
     }
 }
 
+fn serialize_correct_epochs(
+    epoch_number: u64,
+    merkle_root: &Bytes32,
+    data_by_network_id: &BTreeMap<NetworkIndex, u64>,
+    bytes: &mut Vec<u8>,
+) {
+    serialize_u64(epoch_number, bytes);
+    bytes.extend_from_slice(merkle_root);
+    serialize_u64(data_by_network_id.len() as u64, bytes);
+    for (network_index, block_number) in data_by_network_id {
+        serialize_u64(*network_index, bytes);
+        serialize_u64(*block_number, bytes);
+    }
+}
+
 fn serialize_correct_last_epoch(
     chain_id: &str,
     block_number: u64,
@@ -256,4 +279,24 @@ mod tests {
         let merkle_start = bytes.len() - 32;
         assert_eq!(&bytes[merkle_start..], &merkle_root);
     }
+
+    #[test]
+    fn test_correct_epochs_serialization() {
+        let message = CompressedMessage::CorrectEpochs {
+            epoch_number: 9000,
+            merkle_root: [0xCD; 32],
+            data_by_network_id: [(0, 23), (2, 1455594)].into_iter().collect(),
+        };
+
+        assert_eq!(message_tag(&message), 1);
+
+        let mut bytes = Vec::new();
+        serialize_messages(&[message], &mut bytes);
+
+        let mut expected = vec![1, 162, 140];
+        expected.extend_from_slice(&[0xCD; 32]);
+        // Two corrections: (0, 23) and (2, 1455594).
+        expected.extend_from_slice(&[5, 1, 47, 5, 84, 175, 177]);
+        assert_eq!(bytes, expected);
+    }
 }
@@ -1,11 +1,16 @@
 use anyhow::anyhow;
 use epoch_encoding as ee;
-use ethabi::{encode, short_signature, ParamType, Token};
-use serde::{Deserialize, Deserializer, Serialize};
+use ethabi::{decode, encode, short_signature, ParamType, Token};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap;
 
 type EncodedMessageBlocks = Vec<(Vec<&'static str>, Vec<u8>)>;
 
+/// Printed for payloads with `CorrectEpochs` messages, until the Epoch Subgraph handles them.
+pub const CORRECT_EPOCHS_WARNING: &str = "⚠️  The payload contains a CorrectEpochs message, which \
+    the Epoch Subgraph doesn't handle yet: it would misread every later message in the payload. \
+    See docs/future_work/CorrectEpochs.md.";
+
 pub fn messages_to_payload(json: serde_json::Value) -> anyhow::Result<Vec<u8>> {
     let encoded_message_blocks = messages_to_encoded_message_blocks(json)?;
     if encoded_message_blocks.len() != 1 {
@@ -28,6 +33,12 @@ pub fn print_encoded_json_messages(
     json: serde_json::Value,
 ) -> anyhow::Result<()> {
     let encoded_message_blocks = messages_to_encoded_message_blocks(json)?;
+    if encoded_message_blocks
+        .iter()
+        .any(|(message_types, _)| message_types.contains(&"CorrectEpochs"))
+    {
+        eprintln!("{CORRECT_EPOCHS_WARNING}");
+    }
 
     match output_kind {
         OutputKind::Calldata => {
@@ -51,6 +62,20 @@ pub fn print_encoded_json_messages(
     Ok(())
 }
 
+/// Decodes a payload back into messages of the same JSON shape that [`messages_to_payload`]
+/// accepts. `encoding_version` and `network_count` are the encoding version and the number of
+/// networks registered before the payload.
+pub fn payload_to_messages(
+    payload: &[u8],
+    encoding_version: u64,
+    network_count: u64,
+) -> anyhow::Result<Vec<Message>> {
+    ee::deserialize_messages(payload, encoding_version, network_count)?
+        .into_iter()
+        .map(Message::try_from)
+        .collect()
+}
+
 fn messages_to_encoded_message_blocks(
     json: serde_json::Value,
 ) -> anyhow::Result<EncodedMessageBlocks> {
@@ -68,8 +93,21 @@ fn messages_to_encoded_message_blocks(
             let message_type = message.message_type();
             let ready_to_encode = match message {
                 Message::Reset => ee::CompressedMessage::Reset,
-                Message::CorrectEpochs {} => ee::CompressedMessage::CorrectEpochs {
-                    data_by_network_id: BTreeMap::new(),
+                Message::CorrectEpochs {
+                    epoch_number: 0, ..
+                } => {
+                    return Err(anyhow!("Bad JSON: Epoch 0 can't be corrected."));
+                }
+                Message::CorrectEpochs {
+                    epoch_number,
+                    merkle_root,
+                    data_by_network_id,
+                } => ee::CompressedMessage::CorrectEpochs {
+                    epoch_number,
+                    merkle_root: merkle_root.try_into().map_err(|_| {
+                        anyhow!("Bad JSON: The Merkle root must have exactly 32 bytes.")
+                    })?,
+                    data_by_network_id,
                 },
                 Message::UpdateVersion { version_number } => {
                     ee::CompressedMessage::UpdateVersion { version_number }
@@ -100,10 +138,12 @@ fn messages_to_encoded_message_blocks(
                     ee::CompressedSetBlockNumbersForNextEpoch::Empty { count },
                 ),
                 Message::SetBlockNumbersForNextEpoch(SetBlockNumbersForNextEpoch::NonEmpty {
+                    epoch_number,
                     merkle_root,
                     accelerations,
                 }) => ee::CompressedMessage::SetBlockNumbersForNextEpoch(
                     ee::CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                        epoch_number,
                         root: merkle_root.try_into().map_err(|_| {
                             anyhow!("Bad JSON: The Merkle root must have exactly 32 bytes.")
                         })?,
@@ -153,8 +193,13 @@ pub enum MessageBlock {
 #[serde(rename_all = "PascalCase")]
 pub enum Message {
     SetBlockNumbersForNextEpoch(SetBlockNumbersForNextEpoch),
+    #[serde(rename_all = "camelCase")]
     CorrectEpochs {
-        // TODO.
+        epoch_number: u64,
+        #[serde(deserialize_with = "deserialize_hex", serialize_with = "serialize_hex")]
+        merkle_root: Vec<u8>,
+        #[serde(deserialize_with = "deserialize_network_index_keys")]
+        data_by_network_id: BTreeMap<u64, u64>,
     },
     #[serde(rename_all = "camelCase")]
     RegisterNetworks {
@@ -171,7 +216,7 @@ pub enum Message {
         add: Vec<(String, String)>,
     },
     ChangePermissions {
-        #[serde(deserialize_with = "deserialize_hex")]
+        #[serde(deserialize_with = "deserialize_hex", serialize_with = "serialize_hex")]
         address: Vec<u8>,
         valid_through: u64,
         permissions: Vec<String>,
@@ -180,11 +225,78 @@ pub enum Message {
     CorrectLastEpoch {
         chain_id: String,
         block_number: u64,
-        #[serde(deserialize_with = "deserialize_hex")]
+        #[serde(deserialize_with = "deserialize_hex", serialize_with = "serialize_hex")]
         merkle_root: Vec<u8>,
     },
 }
 
+impl TryFrom<ee::CompressedMessage> for Message {
+    type Error = anyhow::Error;
+
+    fn try_from(message: ee::CompressedMessage) -> anyhow::Result<Self> {
+        Ok(match message {
+            ee::CompressedMessage::Reset => Message::Reset,
+            ee::CompressedMessage::CorrectEpochs {
+                epoch_number,
+                merkle_root,
+                data_by_network_id,
+            } => Message::CorrectEpochs {
+                epoch_number,
+                merkle_root: merkle_root.to_vec(),
+                data_by_network_id,
+            },
+            ee::CompressedMessage::UpdateVersion { version_number } => {
+                Message::UpdateVersion { version_number }
+            }
+            ee::CompressedMessage::RegisterNetworks { remove, add } => {
+                Message::RegisterNetworks { remove, add }
+            }
+            ee::CompressedMessage::RegisterNetworksAndAliases { remove, add } => {
+                Message::RegisterNetworksAndAliases { remove, add }
+            }
+            ee::CompressedMessage::ChangePermissions {
+                address,
+                valid_through,
+                permissions,
+            } => Message::ChangePermissions {
+                address: address.to_vec(),
+                valid_through,
+                permissions: permissions
+                    .into_iter()
+                    .map(|x| {
+                        ee::Message::u64_to_str(x)
+                            .map(str::to_string)
+                            .ok_or_else(|| anyhow!("Unknown permission: {}", x))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            },
+            ee::CompressedMessage::SetBlockNumbersForNextEpoch(
+                ee::CompressedSetBlockNumbersForNextEpoch::Empty { count },
+            ) => Message::SetBlockNumbersForNextEpoch(SetBlockNumbersForNextEpoch::Empty { count }),
+            ee::CompressedMessage::SetBlockNumbersForNextEpoch(
+                ee::CompressedSetBlockNumbersForNextEpoch::NonEmpty {
+                    epoch_number,
+                    root,
+                    accelerations,
+                },
+            ) => Message::SetBlockNumbersForNextEpoch(SetBlockNumbersForNextEpoch::NonEmpty {
+                epoch_number,
+                merkle_root: root.to_vec(),
+                accelerations,
+            }),
+            ee::CompressedMessage::CorrectLastEpoch {
+                chain_id,
+                block_number,
+                merkle_root,
+            } => Message::CorrectLastEpoch {
+                chain_id,
+                block_number,
+                merkle_root: merkle_root.to_vec(),
+            },
+        })
+    }
+}
+
 impl Message {
     pub const fn message_type(&self) -> &'static str {
         match self {
@@ -208,7 +320,10 @@ pub enum SetBlockNumbersForNextEpoch {
     Empty { count: u64 },
     #[serde(rename_all = "camelCase")]
     NonEmpty {
-        #[serde(deserialize_with = "deserialize_hex")]
+        /// Required from encoding version 1 on, and not allowed before.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        epoch_number: Option<u64>,
+        #[serde(deserialize_with = "deserialize_hex", serialize_with = "serialize_hex")]
         merkle_root: Vec<u8>,
         accelerations: Vec<i64>,
     },
@@ -222,6 +337,25 @@ where
     hex::decode(s.strip_prefix("0x").unwrap_or(s.as_str())).map_err(serde::de::Error::custom)
 }
 
+/// JSON object keys are always strings, and the untagged [`MessageBlock`] buffers them in a way
+/// that prevents `serde_json` from parsing them as numbers on its own.
+fn deserialize_network_index_keys<'de, D>(deserializer: D) -> Result<BTreeMap<u64, u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, u64>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| Ok((key.parse().map_err(serde::de::Error::custom)?, value)))
+        .collect()
+}
+
+fn serialize_hex<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
+
 pub fn calldata(payload: Vec<u8>) -> Vec<u8> {
     let signature = short_signature("crossChainEpochOracle", &[ParamType::Bytes]);
     let payload = Token::Bytes(payload);
@@ -229,6 +363,24 @@ pub fn calldata(payload: Vec<u8>) -> Vec<u8> {
     signature.into_iter().chain(encoded).collect()
 }
 
+/// The inverse of [`calldata`]: extracts the payload from `crossChainEpochOracle` calldata.
+pub fn calldata_to_payload(calldata: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let signature = short_signature("crossChainEpochOracle", &[ParamType::Bytes]);
+    let arguments = calldata
+        .strip_prefix(&signature[..])
+        .ok_or_else(|| anyhow!("Not a `crossChainEpochOracle` call"))?;
+    match decode(&[ParamType::Bytes], arguments)?.pop() {
+        Some(Token::Bytes(payload)) => Ok(payload),
+        _ => Err(anyhow!("Bad calldata: expected a single `bytes` argument")),
+    }
+}
+
+/// Whether the calldata starts with the `crossChainEpochOracle` function selector.
+pub fn is_calldata(bytes: &[u8]) -> bool {
+    let signature = short_signature("crossChainEpochOracle", &[ParamType::Bytes]);
+    bytes.starts_with(&signature[..])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,6 +442,95 @@ mod tests {
         assert_eq!((preamble >> 4) & 0x0F, 7); // Second tag
     }
 
+    #[test]
+    fn test_decoded_messages_encode_back_to_the_same_payload() {
+        let json_str = r#"[
+            [
+                {
+                    "message": "SetBlockNumbersForNextEpoch",
+                    "count": 2
+                },
+                {
+                    "message": "RegisterNetworksAndAliases",
+                    "remove": [],
+                    "add": [["eip155:1", "mainnet"], ["eip155:100", "gnosis"]]
+                },
+                {
+                    "message": "ChangePermissions",
+                    "address": "0x000102030405060708090a0b0c0d0e0f10111213",
+                    "valid_through": 1000,
+                    "permissions": ["SetBlockNumbersForNextEpochMessage", "ResetStateMessage"]
+                },
+                {
+                    "message": "SetBlockNumbersForNextEpoch",
+                    "merkleRoot": "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+                    "accelerations": [15, -3]
+                },
+                {
+                    "message": "CorrectLastEpoch",
+                    "chainId": "eip155:1",
+                    "blockNumber": 99999,
+                    "merkleRoot": "0xabcdefabcdefabcdefabcdefabcdefabcdefabcdefabcdefabcdefabcdefabcd"
+                },
+                {
+                    "message": "CorrectEpochs",
+                    "epochNumber": 42,
+                    "merkleRoot": "0x1111111111111111111111111111111111111111111111111111111111111111",
+                    "dataByNetworkId": {"0": 99990, "1": 12345}
+                }
+            ]
+        ]"#;
+
+        let json: serde_json::Value = serde_json::from_str(json_str).unwrap();
+        let calldata = messages_to_calldata(json.clone()).unwrap();
+        assert!(is_calldata(&calldata));
+        let payload = calldata_to_payload(&calldata).unwrap();
+        assert_eq!(payload, messages_to_payload(json.clone()).unwrap());
+
+        let messages = payload_to_messages(&payload, ee::CURRENT_ENCODING_VERSION, 0).unwrap();
+        let decoded = serde_json::to_value(vec![MessageBlock::MessageBlock(messages)]).unwrap();
+        assert_eq!(decoded, json);
+        assert_eq!(messages_to_payload(decoded).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_correct_epochs_rejects_epoch_zero() {
+        let json_str = r#"[
+            {
+                "message": "CorrectEpochs",
+                "epochNumber": 0,
+                "merkleRoot": "0x1111111111111111111111111111111111111111111111111111111111111111",
+                "dataByNetworkId": {"0": 1}
+            }
+        ]"#;
+
+        let json: serde_json::Value = serde_json::from_str(json_str).unwrap();
+        let result = messages_to_payload(json);
+
+        assert!(result.unwrap_err().to_string().contains("Epoch 0"));
+    }
+
+    #[test]
+    fn test_epoch_numbers_from_encoding_version_1() {
+        let json = serde_json::json!([{
+            "message": "SetBlockNumbersForNextEpoch",
+            "epochNumber": 812,
+            "merkleRoot": "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+            "accelerations": [15, -3]
+        }]);
+        let payload = messages_to_payload(json.clone()).unwrap();
+
+        let messages = payload_to_messages(&payload, ee::EPOCH_NUMBER_ENCODING_VERSION, 2).unwrap();
+        assert_eq!(serde_json::to_value(messages).unwrap(), json);
+        // Without the epoch number, the rest of the message is misread.
+        assert!(payload_to_messages(&payload, ee::CURRENT_ENCODING_VERSION, 2).is_err());
+    }
+
+    #[test]
+    fn test_payload_is_not_calldata() {
+        assert!(calldata_to_payload(&[0x03, 0x01]).is_err());
+    }
+
     #[test]
     fn test_correct_last_epoch_invalid_merkle_root() {
         // Test with invalid merkle root (not 32 bytes)
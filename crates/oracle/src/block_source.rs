@@ -0,0 +1,689 @@
+//! Providers of block information for indexed chains.
+
+use crate::blockmeta::blockmeta_client::{Block, NumToIdReq};
+use crate::chain_validation::validate_chain_id;
+use crate::config::{BlockConfirmation, QuorumPolicy};
+use crate::metrics::METRICS;
+use crate::runner::jrpc_utils::{get_block_by_number, get_block_by_tag, get_latest_block};
+use crate::{BlockmetaProviderForChain, Caip2ChainId, JrpcProviderForChain};
+use alloy_primitives::BlockHash;
+use async_trait::async_trait;
+use epoch_encoding::BlockPtr;
+use futures::future::join_all;
+use prost::bytes::Bytes;
+use std::collections::BTreeMap;
+use tonic::codegen::{Body, StdError};
+use tracing::warn;
+
+/// A provider of blocks for a single indexed chain.
+///
+/// The oracle only deals with `Box<dyn BlockSource>`, so supporting a new kind of provider only
+/// requires implementing this trait and building it in [`crate::Config::block_sources`].
+#[async_trait(?Send)]
+pub trait BlockSource {
+    /// The indexed chain that this provider serves.
+    fn chain_id(&self) -> &Caip2ChainId;
+
+    /// A short name for the kind of provider, used in metrics and logs.
+    fn kind(&self) -> &'static str;
+
+    /// Fetches the chain head.
+    async fn latest_block(&self) -> anyhow::Result<BlockPtr>;
+
+    /// Fetches the canonical block with the given number.
+    async fn block_by_number(&self, number: u64) -> anyhow::Result<BlockPtr>;
+
+    /// Fetches the latest block that satisfies `confirmation`. Block tags aren't supported
+    /// unless the provider overrides this.
+    async fn confirmed_block(&self, confirmation: BlockConfirmation) -> anyhow::Result<BlockPtr> {
+        match confirmation {
+            BlockConfirmation::Head => self.latest_block().await,
+            BlockConfirmation::Depth(depth) => block_at_depth(self, depth).await,
+            BlockConfirmation::Safe | BlockConfirmation::Finalized => anyhow::bail!(
+                "{} providers don't support the '{}' block tag",
+                self.kind(),
+                confirmation.tag().unwrap_or_default()
+            ),
+        }
+    }
+
+    /// Checks that the provider actually serves [`BlockSource::chain_id`]. Providers that can't
+    /// tell which chain they serve always pass.
+    async fn check_chain_id(&self) -> anyhow::Result<()>;
+}
+
+#[async_trait(?Send)]
+impl<T> BlockSource for JrpcProviderForChain<T>
+where
+    T: web3::Transport,
+{
+    fn chain_id(&self) -> &Caip2ChainId {
+        &self.chain_id
+    }
+
+    fn kind(&self) -> &'static str {
+        "jrpc"
+    }
+
+    async fn latest_block(&self) -> anyhow::Result<BlockPtr> {
+        Ok(get_latest_block(self.web3.clone()).await?)
+    }
+
+    async fn block_by_number(&self, number: u64) -> anyhow::Result<BlockPtr> {
+        Ok(get_block_by_number(&self.web3, number).await?)
+    }
+
+    async fn confirmed_block(&self, confirmation: BlockConfirmation) -> anyhow::Result<BlockPtr> {
+        match confirmation.tag() {
+            Some(tag) => Ok(get_block_by_tag(&self.web3, tag).await?),
+            None => match confirmation {
+                BlockConfirmation::Depth(depth) => block_at_depth(self, depth).await,
+                _ => self.latest_block().await,
+            },
+        }
+    }
+
+    async fn check_chain_id(&self) -> anyhow::Result<()> {
+        let rpc_url = self
+            .rpc_url
+            .as_ref()
+            .map_or(self.chain_id.as_str(), |url| url.as_str());
+        validate_chain_id(&self.web3, &self.chain_id, rpc_url).await
+    }
+}
+
+#[async_trait(?Send)]
+impl<T> BlockSource for BlockmetaProviderForChain<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Clone,
+    T::Error: Into<StdError>,
+    T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    fn chain_id(&self) -> &Caip2ChainId {
+        &self.chain_id
+    }
+
+    fn kind(&self) -> &'static str {
+        "blockmeta"
+    }
+
+    async fn latest_block(&self) -> anyhow::Result<BlockPtr> {
+        let block = self
+            .client
+            .clone()
+            .get_latest_block()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Block not found"))?;
+        blockmeta_block_ptr(block)
+    }
+
+    async fn block_by_number(&self, number: u64) -> anyhow::Result<BlockPtr> {
+        let request = NumToIdReq { block_num: number };
+        let block = self.client.clone().num_to_id(request).await?;
+        blockmeta_block_ptr(block)
+    }
+
+    async fn check_chain_id(&self) -> anyhow::Result<()> {
+        // Blockmeta has no way to tell which chain it serves.
+        Ok(())
+    }
+}
+
+/// Fetches the block `depth` blocks below the chain head.
+async fn block_at_depth<S>(source: &S, depth: u64) -> anyhow::Result<BlockPtr>
+where
+    S: BlockSource + ?Sized,
+{
+    let head = source.latest_block().await?;
+    source
+        .block_by_number(head.number.saturating_sub(depth))
+        .await
+}
+
+fn blockmeta_block_ptr(block: Block) -> anyhow::Result<BlockPtr> {
+    let hash = block
+        .id
+        .parse::<BlockHash>()
+        .map_err(|e| anyhow::anyhow!("Invalid block hash from Blockmeta: {}", e))?;
+    Ok(BlockPtr {
+        number: block.num,
+        hash: hash.0,
+    })
+}
+
+/// Several providers for the same indexed chain, whose answers are combined according to a
+/// [`QuorumPolicy`].
+pub struct QuorumBlockSource {
+    chain_id: Caip2ChainId,
+    policy: QuorumPolicy,
+    sources: Vec<Box<dyn BlockSource>>,
+}
+
+impl QuorumBlockSource {
+    pub fn new(
+        chain_id: Caip2ChainId,
+        policy: QuorumPolicy,
+        sources: Vec<Box<dyn BlockSource>>,
+    ) -> Self {
+        Self {
+            chain_id,
+            policy,
+            sources,
+        }
+    }
+
+    /// Asks every provider concurrently, and keeps the ones that answered.
+    async fn healthy_confirmed_blocks(
+        &self,
+        confirmation: BlockConfirmation,
+    ) -> Vec<(&dyn BlockSource, BlockPtr)> {
+        let results = join_all(
+            self.sources
+                .iter()
+                .map(|source| source.confirmed_block(confirmation)),
+        )
+        .await;
+        self.healthy(results)
+    }
+
+    async fn healthy_blocks_by_number(&self, number: u64) -> Vec<(&dyn BlockSource, BlockPtr)> {
+        let results = join_all(
+            self.sources
+                .iter()
+                .map(|source| source.block_by_number(number)),
+        )
+        .await;
+        self.healthy(results)
+    }
+
+    fn healthy(&self, results: Vec<anyhow::Result<BlockPtr>>) -> Vec<(&dyn BlockSource, BlockPtr)> {
+        self.sources
+            .iter()
+            .zip(results)
+            .enumerate()
+            .filter_map(|(i, (source, result))| match result {
+                Ok(block) => Some((source.as_ref(), block)),
+                Err(e) => {
+                    self.log_failure(i, source.as_ref(), &e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn log_failure(&self, provider: usize, source: &dyn BlockSource, error: &anyhow::Error) {
+        warn!(
+            chain_id = self.chain_id.as_str(),
+            provider,
+            kind = source.kind(),
+            error = error.to_string().as_str(),
+            "A block provider failed."
+        );
+    }
+
+    /// Asks the providers in order, until one of them answers. Asks for the block with the given
+    /// number, if any, and for the confirmed block otherwise.
+    async fn first_healthy(
+        &self,
+        number: Option<u64>,
+        confirmation: BlockConfirmation,
+    ) -> anyhow::Result<BlockPtr> {
+        for (i, source) in self.sources.iter().enumerate() {
+            let result = match number {
+                Some(number) => source.block_by_number(number).await,
+                None => source.confirmed_block(confirmation).await,
+            };
+            match result {
+                Ok(block) => return Ok(block),
+                Err(e) => self.log_failure(i, source.as_ref(), &e),
+            }
+        }
+        anyhow::bail!(
+            "All {} providers for {} failed",
+            self.sources.len(),
+            self.chain_id
+        )
+    }
+
+    /// Picks the block that at least `min_agreement` providers returned, reporting any
+    /// disagreement.
+    fn agreed_block(&self, blocks: &[BlockPtr], min_agreement: usize) -> anyhow::Result<BlockPtr> {
+        report_disagreements(&self.chain_id, blocks);
+        let (block, votes) = most_voted_block(blocks)
+            .ok_or_else(|| anyhow::anyhow!("All providers for {} failed", self.chain_id))?;
+        if votes < min_agreement {
+            anyhow::bail!(
+                "Only {} providers for {} agree on block {}, but {} must agree",
+                votes,
+                self.chain_id,
+                block.number,
+                min_agreement
+            );
+        }
+        Ok(block)
+    }
+}
+
+#[async_trait(?Send)]
+impl BlockSource for QuorumBlockSource {
+    fn chain_id(&self) -> &Caip2ChainId {
+        &self.chain_id
+    }
+
+    fn kind(&self) -> &'static str {
+        "quorum"
+    }
+
+    async fn latest_block(&self) -> anyhow::Result<BlockPtr> {
+        self.confirmed_block(BlockConfirmation::Head).await
+    }
+
+    async fn block_by_number(&self, number: u64) -> anyhow::Result<BlockPtr> {
+        match self.policy {
+            QuorumPolicy::FirstHealthy => {
+                self.first_healthy(Some(number), BlockConfirmation::Head)
+                    .await
+            }
+            // A block number has no median, so every responding provider must agree on its hash.
+            QuorumPolicy::Median => {
+                let blocks: Vec<BlockPtr> = self
+                    .healthy_blocks_by_number(number)
+                    .await
+                    .into_iter()
+                    .map(|(_, block)| block)
+                    .collect();
+                self.agreed_block(&blocks, blocks.len())
+            }
+            QuorumPolicy::Agreement { min_agreement } => {
+                let blocks: Vec<BlockPtr> = self
+                    .healthy_blocks_by_number(number)
+                    .await
+                    .into_iter()
+                    .map(|(_, block)| block)
+                    .collect();
+                self.agreed_block(&blocks, min_agreement)
+            }
+        }
+    }
+
+    /// Applies the policy to the confirmed blocks of every provider.
+    async fn confirmed_block(&self, confirmation: BlockConfirmation) -> anyhow::Result<BlockPtr> {
+        match self.policy {
+            QuorumPolicy::FirstHealthy => self.first_healthy(None, confirmation).await,
+            QuorumPolicy::Median => {
+                let heads: Vec<BlockPtr> = self
+                    .healthy_confirmed_blocks(confirmation)
+                    .await
+                    .into_iter()
+                    .map(|(_, block)| block)
+                    .collect();
+                report_disagreements(&self.chain_id, &heads);
+                median_block(heads)
+                    .ok_or_else(|| anyhow::anyhow!("All providers for {} failed", self.chain_id))
+            }
+            QuorumPolicy::Agreement { min_agreement } => {
+                let heads = self.healthy_confirmed_blocks(confirmation).await;
+                let numbers: Vec<u64> = heads.iter().map(|(_, block)| block.number).collect();
+                let height = agreement_height(&numbers, min_agreement).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Only {} providers for {} are healthy, but {} must agree",
+                        heads.len(),
+                        self.chain_id,
+                        min_agreement
+                    )
+                })?;
+
+                let mut blocks = Vec::new();
+                for (i, (source, head)) in heads.into_iter().enumerate() {
+                    if head.number == height {
+                        blocks.push(head);
+                    } else if head.number > height {
+                        match source.block_by_number(height).await {
+                            Ok(block) => blocks.push(block),
+                            Err(e) => self.log_failure(i, source, &e),
+                        }
+                    }
+                }
+                self.agreed_block(&blocks, min_agreement)
+            }
+        }
+    }
+
+    async fn check_chain_id(&self) -> anyhow::Result<()> {
+        for source in &self.sources {
+            source.check_chain_id().await?;
+        }
+        Ok(())
+    }
+}
+
+/// A provider whose latest block is the latest one that satisfies a [`BlockConfirmation`],
+/// rather than the chain head.
+pub struct ConfirmedBlockSource {
+    inner: Box<dyn BlockSource>,
+    confirmation: BlockConfirmation,
+}
+
+impl ConfirmedBlockSource {
+    pub fn new(inner: Box<dyn BlockSource>, confirmation: BlockConfirmation) -> Self {
+        Self {
+            inner,
+            confirmation,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl BlockSource for ConfirmedBlockSource {
+    fn chain_id(&self) -> &Caip2ChainId {
+        self.inner.chain_id()
+    }
+
+    fn kind(&self) -> &'static str {
+        self.inner.kind()
+    }
+
+    async fn latest_block(&self) -> anyhow::Result<BlockPtr> {
+        self.inner.confirmed_block(self.confirmation).await
+    }
+
+    async fn block_by_number(&self, number: u64) -> anyhow::Result<BlockPtr> {
+        self.inner.block_by_number(number).await
+    }
+
+    async fn confirmed_block(&self, confirmation: BlockConfirmation) -> anyhow::Result<BlockPtr> {
+        self.inner.confirmed_block(confirmation).await
+    }
+
+    async fn check_chain_id(&self) -> anyhow::Result<()> {
+        self.inner.check_chain_id().await
+    }
+}
+
+/// Logs and counts every block number for which providers returned different hashes.
+fn report_disagreements(chain_id: &Caip2ChainId, blocks: &[BlockPtr]) {
+    let mut hashes_by_number: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+    for block in blocks {
+        let hashes = hashes_by_number.entry(block.number).or_default();
+        let hash = hex::encode(block.hash);
+        if !hashes.contains(&hash) {
+            hashes.push(hash);
+        }
+    }
+    for (number, hashes) in hashes_by_number {
+        if hashes.len() > 1 {
+            warn!(
+                chain_id = chain_id.as_str(),
+                number,
+                hashes = ?hashes,
+                "Block providers disagree on a block hash."
+            );
+            METRICS.track_block_source_disagreement(chain_id.as_str());
+        }
+    }
+}
+
+/// The block with the median number. With an even number of blocks, the lower one is picked.
+fn median_block(mut blocks: Vec<BlockPtr>) -> Option<BlockPtr> {
+    blocks.sort_by_key(|block| block.number);
+    let middle = blocks.len().checked_sub(1)? / 2;
+    Some(blocks[middle])
+}
+
+/// The highest block number that at least `min_agreement` providers reached, given their heads.
+fn agreement_height(head_numbers: &[u64], min_agreement: usize) -> Option<u64> {
+    let mut numbers = head_numbers.to_vec();
+    numbers.sort_unstable_by(|a, b| b.cmp(a));
+    numbers.get(min_agreement.checked_sub(1)?).copied()
+}
+
+/// The block that most providers returned, with its number of votes.
+fn most_voted_block(blocks: &[BlockPtr]) -> Option<(BlockPtr, usize)> {
+    let mut votes: Vec<(BlockPtr, usize)> = Vec::new();
+    for block in blocks {
+        match votes.iter_mut().find(|(voted, _)| voted == block) {
+            Some((_, count)) => *count += 1,
+            None => votes.push((*block, 1)),
+        }
+    }
+    votes.into_iter().max_by_key(|(_, count)| *count)
+}
+
+/// Fetches the latest block from all `sources` concurrently.
+pub async fn get_latest_blocks(
+    sources: &[Box<dyn BlockSource>],
+) -> Vec<(&dyn BlockSource, anyhow::Result<BlockPtr>)> {
+    let results = join_all(sources.iter().map(|source| source.latest_block())).await;
+    sources
+        .iter()
+        .map(|source| source.as_ref())
+        .zip(results)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::{Call, Value};
+    use std::future::Future;
+    use std::pin::Pin;
+    use url::Url;
+    use web3::RequestId;
+
+    /// Answers `eth_getBlockByNumber` with blocks whose hash is filled with the lowest byte of
+    /// their number, plus `fork`. Only blocks up to `head` exist, and the `safe` and `finalized`
+    /// blocks are 5 and 10 blocks deep.
+    #[derive(Debug, Clone)]
+    struct MockTransport {
+        head: u64,
+        fork: u8,
+    }
+
+    impl web3::Transport for MockTransport {
+        type Out = Pin<Box<dyn Future<Output = Result<Value, web3::Error>>>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            let call = Call::MethodCall(jsonrpc_core::MethodCall {
+                jsonrpc: Some(jsonrpc_core::Version::V2),
+                method: method.to_string(),
+                params: jsonrpc_core::Params::Array(params),
+                id: jsonrpc_core::Id::Num(1),
+            });
+            (1, call)
+        }
+
+        fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+            let response = match request {
+                Call::MethodCall(call) if call.method == "eth_getBlockByNumber" => {
+                    let params: Vec<Value> = call.params.parse().unwrap();
+                    let number = match params[0].as_str().unwrap() {
+                        "latest" => self.head,
+                        "safe" => self.head - 5,
+                        "finalized" => self.head - 10,
+                        hex => u64::from_str_radix(hex.trim_start_matches("0x"), 16).unwrap(),
+                    };
+                    if number <= self.head {
+                        Ok(serde_json::json!({
+                            "number": format!("0x{number:x}"),
+                            "hash": format!("0x{}", hex::encode([number as u8 + self.fork; 32])),
+                        }))
+                    } else {
+                        Ok(Value::Null)
+                    }
+                }
+                _ => Err(web3::Error::Decoder("Unexpected request".to_string())),
+            };
+            Box::pin(futures::future::ready(response))
+        }
+    }
+
+    fn jrpc_source(head: u64) -> Box<dyn BlockSource> {
+        forked_jrpc_source(head, 0)
+    }
+
+    fn forked_jrpc_source(head: u64, fork: u8) -> Box<dyn BlockSource> {
+        let chain_id = Caip2ChainId::ethereum_mainnet();
+        Box::new(JrpcProviderForChain::new(
+            chain_id,
+            MockTransport { head, fork },
+        ))
+    }
+
+    fn quorum(policy: QuorumPolicy, sources: Vec<Box<dyn BlockSource>>) -> QuorumBlockSource {
+        QuorumBlockSource::new(Caip2ChainId::ethereum_mainnet(), policy, sources)
+    }
+
+    fn block(number: u64) -> BlockPtr {
+        BlockPtr {
+            number,
+            hash: [number as u8; 32],
+        }
+    }
+
+    #[tokio::test]
+    async fn jrpc_block_source() {
+        let source = jrpc_source(20);
+        assert_eq!(source.kind(), "jrpc");
+        assert_eq!(
+            source.latest_block().await.unwrap(),
+            BlockPtr {
+                number: 20,
+                hash: [20; 32]
+            }
+        );
+        assert_eq!(
+            source.block_by_number(7).await.unwrap(),
+            BlockPtr {
+                number: 7,
+                hash: [7; 32]
+            }
+        );
+        assert!(source.block_by_number(21).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn chain_id_errors_name_the_endpoint() {
+        let url: Url = "http://mainnet.example.com/".parse().unwrap();
+        let source = JrpcProviderForChain::new(
+            Caip2ChainId::ethereum_mainnet(),
+            MockTransport { head: 0, fork: 0 },
+        )
+        .with_rpc_url(url);
+        let err = source.check_chain_id().await.unwrap_err().to_string();
+        assert!(err.contains("http://mainnet.example.com/"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn latest_blocks_keep_the_order_of_sources() {
+        let sources = vec![jrpc_source(5), jrpc_source(3)];
+        let numbers: Vec<u64> = get_latest_blocks(&sources)
+            .await
+            .into_iter()
+            .map(|(_, block)| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, vec![5, 3]);
+    }
+
+    #[test]
+    fn median_and_agreement_height() {
+        assert_eq!(median_block(vec![]), None);
+        assert_eq!(
+            median_block(vec![block(3), block(1), block(2)]),
+            Some(block(2))
+        );
+        assert_eq!(median_block(vec![block(4), block(1)]), Some(block(1)));
+
+        assert_eq!(agreement_height(&[10, 30, 20], 1), Some(30));
+        assert_eq!(agreement_height(&[10, 30, 20], 2), Some(20));
+        assert_eq!(agreement_height(&[10, 30, 20], 4), None);
+        assert_eq!(agreement_height(&[10], 0), None);
+    }
+
+    #[tokio::test]
+    async fn quorum_policies() {
+        let sources = || vec![jrpc_source(10), jrpc_source(30), jrpc_source(12)];
+
+        let first_healthy = quorum(QuorumPolicy::FirstHealthy, sources());
+        assert_eq!(first_healthy.latest_block().await.unwrap(), block(10));
+
+        let median = quorum(QuorumPolicy::Median, sources());
+        assert_eq!(median.latest_block().await.unwrap(), block(12));
+
+        let agreement = quorum(QuorumPolicy::Agreement { min_agreement: 2 }, sources());
+        assert_eq!(agreement.latest_block().await.unwrap(), block(12));
+        assert_eq!(agreement.block_by_number(5).await.unwrap(), block(5));
+        assert!(agreement.block_by_number(13).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn quorum_agreement_rejects_forks() {
+        let sources = vec![jrpc_source(20), forked_jrpc_source(20, 1), jrpc_source(25)];
+        let agreement = quorum(QuorumPolicy::Agreement { min_agreement: 2 }, sources);
+        assert_eq!(agreement.latest_block().await.unwrap(), block(20));
+
+        let sources = vec![jrpc_source(20), forked_jrpc_source(20, 1), jrpc_source(25)];
+        let agreement = quorum(QuorumPolicy::Agreement { min_agreement: 3 }, sources);
+        let err = agreement.latest_block().await.unwrap_err().to_string();
+        assert!(err.contains("Only 2 providers"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn quorum_median_requires_agreement_by_number() {
+        let sources = vec![jrpc_source(20), jrpc_source(25), jrpc_source(10)];
+        let median = quorum(QuorumPolicy::Median, sources);
+        assert_eq!(median.block_by_number(15).await.unwrap(), block(15));
+
+        let sources = vec![forked_jrpc_source(20, 1), jrpc_source(20), jrpc_source(20)];
+        let median = quorum(QuorumPolicy::Median, sources);
+        let err = median.block_by_number(15).await.unwrap_err().to_string();
+        assert!(err.contains("Only 2 providers"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn confirmed_blocks() {
+        let source = jrpc_source(20);
+        assert_eq!(
+            source
+                .confirmed_block(BlockConfirmation::Head)
+                .await
+                .unwrap(),
+            block(20)
+        );
+        assert_eq!(
+            source
+                .confirmed_block(BlockConfirmation::Depth(3))
+                .await
+                .unwrap(),
+            block(17)
+        );
+        assert_eq!(
+            source
+                .confirmed_block(BlockConfirmation::Safe)
+                .await
+                .unwrap(),
+            block(15)
+        );
+        assert_eq!(
+            source
+                .confirmed_block(BlockConfirmation::Finalized)
+                .await
+                .unwrap(),
+            block(10)
+        );
+
+        let confirmed = ConfirmedBlockSource::new(jrpc_source(20), BlockConfirmation::Depth(4));
+        assert_eq!(confirmed.latest_block().await.unwrap(), block(16));
+        assert_eq!(confirmed.block_by_number(19).await.unwrap(), block(19));
+
+        let sources = vec![jrpc_source(30), jrpc_source(20), jrpc_source(40)];
+        let median = quorum(QuorumPolicy::Median, sources);
+        assert_eq!(
+            median
+                .confirmed_block(BlockConfirmation::Finalized)
+                .await
+                .unwrap(),
+            block(20)
+        );
+    }
+}
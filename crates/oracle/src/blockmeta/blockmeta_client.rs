@@ -1,12 +1,9 @@
 //! StreamingFast Blockmeta gRPC client.
 
-use std::collections::BTreeMap;
 use std::time::Duration;
 
 use prost::bytes::Bytes;
 
-use futures::stream::{FuturesUnordered, StreamExt};
-
 use tonic::codegen::{Body, InterceptedService, StdError};
 use tonic::transport::{Channel, Uri};
 
@@ -15,7 +12,6 @@ use self::gen::block_client::BlockClient;
 pub use self::gen::BlockResp as Block;
 use self::gen::Empty;
 pub use self::gen::{BlockResp, NumToIdReq};
-use crate::{BlockmetaProviderForChain, Caip2ChainId};
 
 /// This file is **generated** by the `build.rs` when compiling the crate with the `proto-gen`
 /// feature enabled. The `build.rs` script uses the `tonic-build` crate to generate the files.
@@ -146,43 +142,3 @@ where
         }
     }
 }
-
-/// Fetches the latest available block number and hash from all `chains`.
-pub async fn get_latest_blockmeta_blocks<T>(
-    chains: &[BlockmetaProviderForChain<T>],
-) -> BTreeMap<Caip2ChainId, anyhow::Result<Block>>
-where
-    T: tonic::client::GrpcService<tonic::body::BoxBody>,
-    T::Error: Into<StdError>,
-    T::ResponseBody: Body<Data = Bytes> + Send + 'static,
-    <T::ResponseBody as Body>::Error: Into<StdError> + Send,
-    T: std::clone::Clone,
-{
-    let mut tasks = chains
-        .iter()
-        .cloned()
-        .map(|mut chain| async move {
-            chain.client.get_latest_block().await.map(|block| {
-                (
-                    chain.chain_id,
-                    block.ok_or_else(|| anyhow::anyhow!("Block not found")),
-                )
-            })
-        })
-        .collect::<FuturesUnordered<_>>();
-
-    let mut block_ptr_per_chain = BTreeMap::new();
-    while let Some(result) = tasks.next().await {
-        match result {
-            Ok((chain_id, block)) => {
-                block_ptr_per_chain.insert(chain_id, block);
-            }
-            Err(e) => {
-                println!("Error: {e:?}");
-            }
-        }
-    }
-
-    assert!(block_ptr_per_chain.len() == chains.len());
-    block_ptr_per_chain
-}
@@ -1,4 +1,4 @@
-use crate::config::{IndexedChain, ProtocolChain};
+use crate::config::ProtocolChain;
 use crate::runner::jrpc_utils::JrpcExpBackoff;
 use crate::{Caip2ChainId, Config};
 use anyhow::anyhow;
@@ -15,8 +15,8 @@ pub async fn validate_chain_ids(config: &Config) -> anyhow::Result<()> {
     validate_protocol_chain(&config.protocol_chain).await?;
 
     // Validate indexed chains
-    for chain in &config.indexed_chains {
-        validate_indexed_chain(chain).await?;
+    for source in config.block_sources() {
+        source.check_chain_id().await?;
     }
 
     info!("All RPC chain IDs validated successfully");
@@ -24,28 +24,21 @@ pub async fn validate_chain_ids(config: &Config) -> anyhow::Result<()> {
 }
 
 async fn validate_protocol_chain(chain: &ProtocolChain) -> anyhow::Result<()> {
-    let transport = JrpcExpBackoff::http(
-        chain.jrpc_url.clone(),
-        chain.id.clone(),
-        std::time::Duration::from_secs(30),
-    );
-    let web3 = Web3::new(transport);
-
-    validate_chain_id(&web3, &chain.id, chain.jrpc_url.as_ref()).await
-}
-
-async fn validate_indexed_chain(chain: &IndexedChain) -> anyhow::Result<()> {
-    let transport = JrpcExpBackoff::http(
-        chain.jrpc_url.clone(),
-        chain.id.clone(),
-        std::time::Duration::from_secs(30),
-    );
-    let web3 = Web3::new(transport);
+    // Every fallback endpoint must serve the same chain as the primary one.
+    for jrpc_url in &chain.jrpc_urls {
+        let transport = JrpcExpBackoff::http(
+            jrpc_url.clone(),
+            chain.id.clone(),
+            std::time::Duration::from_secs(30),
+        );
+        let web3 = Web3::new(transport);
 
-    validate_chain_id(&web3, &chain.id, chain.jrpc_url.as_ref()).await
+        validate_chain_id(&web3, &chain.id, jrpc_url.as_ref()).await?;
+    }
+    Ok(())
 }
 
-async fn validate_chain_id<T>(
+pub(crate) async fn validate_chain_id<T>(
     web3: &Web3<T>,
     expected_chain: &Caip2ChainId,
     rpc_url: &str,
@@ -0,0 +1,310 @@
+use super::correct_epoch::fetch_all_block_ptrs;
+use crate::metrics::METRICS;
+use crate::subgraph::{query_epoch_payload, EpochPayload};
+use crate::{query_subgraph, Caip2ChainId, Config};
+use epoch_encoding::messages::Bytes32;
+use epoch_encoding::{merkle_root, BlockPtr, Decoder, MerkleLeaf};
+use std::collections::BTreeMap;
+
+/// The result of recomputing the Merkle root of an epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditOutcome {
+    /// The recomputed Merkle root matches the posted one.
+    Match,
+    /// The canonical hashes of the posted block numbers don't add up to the posted Merkle root,
+    /// e.g. because of a reorg or a misbehaving provider.
+    Mismatch { computed: Bytes32, posted: Bytes32 },
+    /// The Merkle root couldn't be recomputed, for the given reason.
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochAudit {
+    pub epoch_number: u64,
+    /// The networks whose blocks are in the epoch's Merkle tree. Empty if the audit was skipped.
+    pub networks: Vec<Caip2ChainId>,
+    pub outcome: AuditOutcome,
+}
+
+/// Audits the Merkle roots of the latest epochs and prints the results. Fails if any of them
+/// doesn't match.
+pub async fn audit(config: Config, epoch_count: u64) -> anyhow::Result<()> {
+    println!("🔍 Auditing the Merkle roots of the latest {epoch_count} epoch(s)...");
+    let audits = audit_epochs(&config, epoch_count).await?;
+
+    for audit in &audits {
+        match &audit.outcome {
+            AuditOutcome::Match => {
+                println!("   ✅ Epoch {}: Merkle root matches", audit.epoch_number)
+            }
+            AuditOutcome::Mismatch { computed, posted } => println!(
+                "   ❌ Epoch {}: computed Merkle root 0x{}, but 0x{} was posted",
+                audit.epoch_number,
+                hex::encode(computed),
+                hex::encode(posted)
+            ),
+            AuditOutcome::Skipped(reason) => {
+                println!("   ⚠️  Epoch {}: skipped: {}", audit.epoch_number, reason)
+            }
+        }
+    }
+
+    let mismatches = audits
+        .iter()
+        .filter(|audit| matches!(audit.outcome, AuditOutcome::Mismatch { .. }))
+        .count();
+    if mismatches > 0 {
+        anyhow::bail!(
+            "{} epoch(s) have a Merkle root that doesn't match their block numbers",
+            mismatches
+        );
+    }
+    Ok(())
+}
+
+/// Recomputes the Merkle roots of the latest `epoch_count` epochs from the block numbers in the
+/// payloads that posted them and the current canonical block hashes, and compares them with the
+/// posted Merkle roots. The audit metrics are updated with the results.
+pub(crate) async fn audit_epochs(
+    config: &Config,
+    epoch_count: u64,
+) -> anyhow::Result<Vec<EpochAudit>> {
+    if epoch_count == 0 {
+        anyhow::bail!("The number of epochs to audit must be at least 1");
+    }
+
+    let subgraph_state = query_subgraph(&config.subgraph_url, &config.bearer_token).await?;
+    let latest_epoch_number = subgraph_state
+        .latest_epoch_number()
+        .ok_or_else(|| anyhow::anyhow!("No latest epoch found in subgraph"))?;
+    let first_epoch_number = latest_epoch_number.saturating_sub(epoch_count - 1);
+
+    let mut audits = Vec::new();
+    for epoch_number in first_epoch_number..=latest_epoch_number {
+        let audit = audit_epoch(config, epoch_number)
+            .await
+            .unwrap_or_else(|err| EpochAudit {
+                epoch_number,
+                networks: vec![],
+                outcome: AuditOutcome::Skipped(err.to_string()),
+            });
+        audits.push(audit);
+    }
+
+    for (network, mismatch) in mismatches_by_network(&audits) {
+        METRICS.set_audit_merkle_root_mismatch(network.as_str(), mismatch);
+    }
+    Ok(audits)
+}
+
+async fn audit_epoch(config: &Config, epoch_number: u64) -> anyhow::Result<EpochAudit> {
+    let payload =
+        query_epoch_payload(&config.subgraph_url, &config.bearer_token, epoch_number).await?;
+    let networks = decode_epoch_networks(&payload)?;
+
+    let block_numbers = networks
+        .iter()
+        .map(|(chain_id, network)| (chain_id.clone(), network.block_number))
+        .collect();
+    let blocks = fetch_all_block_ptrs(config, &block_numbers).await?;
+    let computed_merkle_root = merkle_root(&merkle_leaves(&networks, &blocks));
+
+    let outcome = if computed_merkle_root == payload.merkle_root {
+        AuditOutcome::Match
+    } else {
+        AuditOutcome::Mismatch {
+            computed: computed_merkle_root,
+            posted: payload.merkle_root,
+        }
+    };
+    Ok(EpochAudit {
+        epoch_number,
+        networks: networks.into_iter().map(|(chain_id, _)| chain_id).collect(),
+        outcome,
+    })
+}
+
+/// Builds the Merkle leaves of an epoch's networks, with the given blocks.
+pub(crate) fn merkle_leaves(
+    networks: &[(Caip2ChainId, epoch_encoding::Network)],
+    blocks: &BTreeMap<Caip2ChainId, BlockPtr>,
+) -> Vec<MerkleLeaf> {
+    networks
+        .iter()
+        .map(|(chain_id, network)| {
+            let block = &blocks[chain_id];
+            MerkleLeaf {
+                network_index: network.array_index,
+                block_number: block.number,
+                block_hash: block.hash,
+            }
+        })
+        .collect()
+}
+
+/// Replays the payload that posted an epoch's Merkle root from the subgraph state right before it,
+/// and returns the block numbers that it set, by network index.
+pub(crate) fn decode_epoch_networks(
+    payload: &EpochPayload,
+) -> anyhow::Result<Vec<(Caip2ChainId, epoch_encoding::Network)>> {
+    let networks = payload
+        .networks
+        .iter()
+        .map(|network| (network.id.as_str().to_owned(), network.clone().into()))
+        .collect();
+    let removed_networks = payload
+        .removed_networks
+        .iter()
+        .map(|network| (network.id.as_str().to_owned(), network.clone().into()));
+    let mut decoder =
+        Decoder::new(payload.encoding_version, networks)?.with_removed_networks(removed_networks);
+
+    let epoch = decoder
+        .decode(&payload.data)?
+        .into_iter()
+        .find(|epoch| epoch.merkle_root == Some(payload.merkle_root))
+        .ok_or_else(|| anyhow::anyhow!("The payload doesn't post the epoch's Merkle root"))?;
+    epoch
+        .networks
+        .into_iter()
+        .map(|(id, network)| {
+            let chain_id = id
+                .parse()
+                .map_err(|s| anyhow::anyhow!("Invalid network name: {}", s))?;
+            Ok((chain_id, network))
+        })
+        .collect()
+}
+
+/// Tells, for every audited network, whether the Merkle root of the latest epoch that could be
+/// audited for it is a mismatch.
+fn mismatches_by_network(audits: &[EpochAudit]) -> BTreeMap<&Caip2ChainId, bool> {
+    let mut mismatches = BTreeMap::new();
+    let mut audits: Vec<&EpochAudit> = audits.iter().collect();
+    audits.sort_by_key(|audit| audit.epoch_number);
+    for audit in audits {
+        let mismatch = match audit.outcome {
+            AuditOutcome::Match => false,
+            AuditOutcome::Mismatch { .. } => true,
+            AuditOutcome::Skipped(_) => continue,
+        };
+        for network in &audit.networks {
+            mismatches.insert(network, mismatch);
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn epoch_audit(epoch_number: u64, networks: &[&str], outcome: AuditOutcome) -> EpochAudit {
+        EpochAudit {
+            epoch_number,
+            networks: networks.iter().map(|id| id.parse().unwrap()).collect(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn decode_payload_from_the_previous_state() {
+        use crate::subgraph::{BlockUpdate, Network, RemovedNetwork};
+        use epoch_encoding::{BlockPtr, Encoder, Message, CURRENT_ENCODING_VERSION};
+
+        let block_update = |block_number, delta| {
+            Some(BlockUpdate {
+                block_number,
+                acceleration: 0,
+                delta,
+                updated_at_epoch_number: 4,
+            })
+        };
+        let mut payload = EpochPayload {
+            merkle_root: [0; 32],
+            data: vec![],
+            encoding_version: CURRENT_ENCODING_VERSION,
+            networks: vec![Network {
+                id: "eip155:1".parse().unwrap(),
+                array_index: 0,
+                latest_block_update: block_update(100, 10),
+            }],
+            removed_networks: vec![RemovedNetwork {
+                id: "eip155:100".parse().unwrap(),
+                latest_block_update: block_update(500, 50),
+            }],
+        };
+
+        // The removed network is registered again, and picks up from its latest block number.
+        let mut encoder = Encoder::new(
+            CURRENT_ENCODING_VERSION,
+            vec![(
+                "eip155:1".to_string(),
+                epoch_encoding::Network::new(100, 10, 0),
+            )],
+        )
+        .unwrap()
+        .with_removed_networks([(
+            "eip155:100".to_string(),
+            epoch_encoding::Network::new(500, 50, 0),
+        )]);
+        let compressed = encoder
+            .compress(&[
+                Message::RegisterNetworks {
+                    remove: vec![],
+                    add: vec!["eip155:100".to_string()],
+                },
+                Message::SetBlockNumbersForNextEpoch {
+                    epoch_number: None,
+                    block_ptrs: [
+                        ("eip155:1".to_string(), BlockPtr::new(111, [1; 32])),
+                        ("eip155:100".to_string(), BlockPtr::new(555, [2; 32])),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
+            ])
+            .unwrap();
+        payload.data = encoder.encode(&compressed);
+        payload.merkle_root = compressed[1].as_non_empty_block_numbers().unwrap().1;
+
+        let decoded = decode_epoch_networks(&payload).unwrap();
+        let networks: Vec<(&str, u64, u64)> = decoded
+            .iter()
+            .map(|(id, network)| (id.as_str(), network.block_number, network.array_index))
+            .collect();
+        assert_eq!(networks, [("eip155:1", 111, 0), ("eip155:100", 555, 1)]);
+
+        // A payload that doesn't post the epoch's Merkle root can't be audited.
+        payload.merkle_root = [3; 32];
+        assert!(decode_epoch_networks(&payload).is_err());
+    }
+
+    #[test]
+    fn latest_audited_epoch_determines_mismatch() {
+        let mismatch = AuditOutcome::Mismatch {
+            computed: [1; 32],
+            posted: [2; 32],
+        };
+        let audits = vec![
+            epoch_audit(12, &[], AuditOutcome::Skipped("no root".to_owned())),
+            epoch_audit(11, &["eip155:1", "eip155:100"], mismatch.clone()),
+            epoch_audit(10, &["eip155:1", "eip155:100"], AuditOutcome::Match),
+            epoch_audit(11, &["eip155:137"], AuditOutcome::Match),
+            epoch_audit(9, &["eip155:137"], mismatch),
+        ];
+
+        let mismatches: Vec<(&str, bool)> = mismatches_by_network(&audits)
+            .into_iter()
+            .map(|(network, mismatch)| (network.as_str(), mismatch))
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                ("eip155:1", true),
+                ("eip155:100", true),
+                ("eip155:137", false)
+            ]
+        );
+    }
+}
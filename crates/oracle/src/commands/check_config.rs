@@ -0,0 +1,11 @@
+use crate::preflight::preflight;
+use crate::Config;
+
+pub async fn check_config(config: Config) -> anyhow::Result<()> {
+    let report = preflight(&config).await;
+    print!("{report}");
+    if !report.passed() {
+        anyhow::bail!("The configuration failed some checks");
+    }
+    Ok(())
+}
@@ -1,16 +1,14 @@
-use crate::blockmeta::blockmeta_client;
-use crate::runner::jrpc_utils::{get_latest_block, JrpcExpBackoff};
-use crate::{
-    query_subgraph, BlockmetaProviderForChain, Caip2ChainId, Config, JrpcProviderForChain,
+use super::audit::{decode_epoch_networks, merkle_leaves};
+use crate::subgraph::{
+    query_block_number_history, query_epoch_payload, BlockUpdate, NetworkHistory,
+    MAX_BLOCK_NUMBER_HISTORY,
 };
-use alloy_primitives::BlockHash;
+use crate::{query_subgraph, Caip2ChainId, Config};
+use epoch_encoding::messages::Bytes32;
 use epoch_encoding::BlockPtr;
 use json_oracle_encoder::messages_to_payload;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::{self, Write};
-use web3::helpers::CallFuture;
-use web3::types::{BlockNumber, U64};
-use web3::Transport;
 
 pub async fn correct_last_epoch(
     config: Config,
@@ -48,12 +46,7 @@ pub async fn correct_last_epoch(
         target_network.array_index
     );
 
-    // Step 2: Initialize RPC clients for all networks
-    println!("📡 Setting up RPC clients for all networks...");
-    let indexed_chains = indexed_chains(&config);
-    let blockmeta_indexed_chains = blockmeta_indexed_chains(&config);
-
-    // Step 3: Get corrected block number for target network
+    // Step 2: Get corrected block number for target network
     let corrected_block_number = match block_number {
         Some(num) => {
             println!("   Using provided block number: {num}");
@@ -61,52 +54,26 @@ pub async fn correct_last_epoch(
         }
         None => {
             println!("   Auto-detecting current block for {chain_id}...");
-
-            // Try to find the target chain in JSON-RPC providers first
-            let mut found_block = None;
-            for jrpc_chain in &indexed_chains {
-                if jrpc_chain.chain_id.as_str() == chain_id {
-                    let latest_block =
-                        get_latest_block(jrpc_chain.web3.clone())
-                            .await
-                            .map_err(|e| {
-                                anyhow::anyhow!(
-                                    "Failed to get latest block from {}: {}",
-                                    chain_id,
-                                    e
-                                )
-                            })?;
-                    found_block = Some(latest_block.number);
-                    println!("     Current block from JSON-RPC: {}", latest_block.number);
-                    break;
-                }
-            }
-
-            // If not found in JSON-RPC, try Blockmeta providers
-            if found_block.is_none() {
-                for blockmeta_chain in &blockmeta_indexed_chains {
-                    if blockmeta_chain.chain_id.as_str() == chain_id {
-                        let mut client = blockmeta_chain.client.clone();
-                        let latest_block = client.get_latest_block().await?.ok_or_else(|| {
-                            anyhow::anyhow!("No latest block found from Blockmeta for {}", chain_id)
-                        })?;
-                        found_block = Some(latest_block.num);
-                        println!("     Current block from Blockmeta: {}", latest_block.num);
-                        break;
-                    }
-                }
-            }
-
-            found_block.ok_or_else(|| {
-                anyhow::anyhow!(
-                    "Chain '{}' not found in either JSON-RPC or Blockmeta providers",
-                    chain_id
-                )
-            })?
+            let source = config
+                .block_sources()
+                .into_iter()
+                .find(|source| source.chain_id() == &target_network.id)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("No provider is configured for chain '{}'", chain_id)
+                })?;
+            let latest_block = source.latest_block().await.map_err(|e| {
+                anyhow::anyhow!("Failed to get latest block from {}: {}", chain_id, e)
+            })?;
+            println!(
+                "     Current block from {}: {}",
+                source.kind(),
+                latest_block.number
+            );
+            latest_block.number
         }
     };
 
-    // Step 4: Get block numbers for all networks from the latest epoch
+    // Step 3: Get block numbers for all networks from the latest epoch
     println!("🔍 Collecting block data from latest epoch for all networks...");
     let mut epoch_blocks: BTreeMap<Caip2ChainId, (u64, u64)> = BTreeMap::new(); // (block_number, array_index)
 
@@ -131,162 +98,34 @@ pub async fn correct_last_epoch(
         anyhow::bail!("No networks have block data for epoch {}. This might indicate the epoch is too recent.", latest_epoch_number);
     }
 
-    // Step 5: Fetch block hashes for all networks using their epoch block numbers
+    // Step 4: Fetch block hashes for all networks using their epoch block numbers
     println!("🔗 Fetching block hashes for merkle root computation...");
-    let mut all_blocks: BTreeMap<Caip2ChainId, BlockPtr> = BTreeMap::new();
-
-    // Fetch from JSON-RPC providers
-    for jrpc_chain in &indexed_chains {
-        if let Some((block_num, _array_index)) = epoch_blocks.get(&jrpc_chain.chain_id) {
-            let use_corrected_block = jrpc_chain.chain_id.as_str() == chain_id;
-            let target_block_number = if use_corrected_block {
-                corrected_block_number
-            } else {
-                *block_num
-            };
-
-            // Get block by number
-            let block_id =
-                web3::helpers::serialize(&BlockNumber::Number(U64::from(target_block_number)));
-            let include_txs = web3::helpers::serialize(&false);
-            let fut = jrpc_chain
-                .web3
-                .transport()
-                .execute("eth_getBlockByNumber", vec![block_id, include_txs]);
-
-            #[derive(serde::Deserialize)]
-            struct BlockResponse {
-                hash: web3::types::H256,
-                number: U64,
-            }
-
-            let call_fut: CallFuture<BlockResponse, _> = CallFuture::new(fut);
-            let block = call_fut.await.map_err(|e| {
-                anyhow::anyhow!(
-                    "Failed to get block {} from {}: {}",
-                    target_block_number,
-                    jrpc_chain.chain_id.as_str(),
-                    e
-                )
-            })?;
-
-            let block_ptr = BlockPtr {
-                number: block.number.as_u64(),
-                hash: block.hash.0,
-            };
-
-            all_blocks.insert(jrpc_chain.chain_id.clone(), block_ptr);
-
-            if use_corrected_block {
-                println!(
-                    "   {} (CORRECTED): block {} -> hash {}",
-                    jrpc_chain.chain_id.as_str(),
-                    target_block_number,
-                    hex::encode(block_ptr.hash)
-                );
-            } else {
-                println!(
-                    "   {}: block {} -> hash {}",
-                    jrpc_chain.chain_id.as_str(),
-                    target_block_number,
-                    hex::encode(block_ptr.hash)
-                );
-            }
-        }
-    }
-
-    // Fetch from Blockmeta providers
-    for blockmeta_chain in &blockmeta_indexed_chains {
-        if let Some((block_num, _array_index)) = epoch_blocks.get(&blockmeta_chain.chain_id) {
-            let use_corrected_block = blockmeta_chain.chain_id.as_str() == chain_id;
-            let target_block_number = if use_corrected_block {
-                corrected_block_number
-            } else {
-                *block_num
-            };
-
-            // Get block by number using Blockmeta gRPC
-            let mut client = blockmeta_chain.client.clone();
-            let request = blockmeta_client::NumToIdReq {
-                block_num: target_block_number,
-            };
-
-            let block_resp = client.num_to_id(request).await?;
-
-            let block_hash = block_resp
-                .id
-                .parse::<BlockHash>()
-                .map_err(|e| anyhow::anyhow!("Invalid block hash from Blockmeta: {}", e))?;
-
-            let block_ptr = BlockPtr {
-                number: block_resp.num,
-                hash: block_hash.0,
-            };
-
-            all_blocks.insert(blockmeta_chain.chain_id.clone(), block_ptr);
-
-            if use_corrected_block {
-                println!(
-                    "   {} (CORRECTED): block {} -> hash {}",
-                    blockmeta_chain.chain_id.as_str(),
-                    target_block_number,
-                    hex::encode(block_ptr.hash)
-                );
-            } else {
-                println!(
-                    "   {}: block {} -> hash {}",
-                    blockmeta_chain.chain_id.as_str(),
-                    target_block_number,
-                    hex::encode(block_ptr.hash)
-                );
-            }
-        }
+    let mut block_numbers: BTreeMap<Caip2ChainId, u64> = epoch_blocks
+        .into_iter()
+        .map(|(chain_id, (block_number, _array_index))| (chain_id, block_number))
+        .collect();
+    if let Some(block_number) = block_numbers.get_mut(&target_network.id) {
+        *block_number = corrected_block_number;
     }
+    let corrected = BTreeSet::from([target_network.id.clone()]);
+    let all_blocks = fetch_block_ptrs(&config, &block_numbers).await?;
+    print_block_ptrs(&all_blocks, &corrected);
 
-    // Step 6: Compute merkle root using the same algorithm as the oracle
+    // Step 5: Compute merkle root using the same algorithm as the oracle
     println!("🧮 Computing merkle root...");
-
-    // Use the encoder to compute the merkle root by creating a temporary SetBlockNumbersForNextEpoch message
-    let available_networks: Vec<(String, epoch_encoding::Network)> = {
-        global_state
-            .networks
-            .iter()
-            .map(|network| (network.id.as_str().to_owned(), network.clone().into()))
-            .collect()
-    };
-
-    let mut encoder =
-        epoch_encoding::Encoder::new(epoch_encoding::CURRENT_ENCODING_VERSION, available_networks)
-            .expect("Failed to create encoder");
-
-    // Create a temporary message with our corrected blocks to compute the merkle root
-    let message = epoch_encoding::Message::SetBlockNumbersForNextEpoch(
-        all_blocks
-            .iter()
-            .map(|(chain_id, block_ptr)| (chain_id.as_str().to_owned(), *block_ptr))
-            .collect(),
-    );
-
-    let compressed = encoder
-        .compress(&[message])
-        .expect("Failed to compress message for merkle root computation");
-
-    let computed_merkle_root = if let Some(compressed_msg) = compressed.first() {
-        if let Some((_, root)) = compressed_msg.as_non_empty_block_numbers() {
-            root
-        } else {
-            anyhow::bail!("Expected non-empty block numbers message for merkle root computation");
-        }
-    } else {
-        anyhow::bail!("Failed to compress message for merkle root computation");
-    };
+    let available_networks: Vec<(String, epoch_encoding::Network)> = global_state
+        .networks
+        .iter()
+        .map(|network| (network.id.as_str().to_owned(), network.clone().into()))
+        .collect();
+    let computed_merkle_root = merkle_root(available_networks, &all_blocks)?;
 
     println!(
         "   Computed merkle root: 0x{}",
         hex::encode(computed_merkle_root)
     );
 
-    // Step 7: Display correction summary
+    // Step 6: Display correction summary
     println!();
     println!("📋 Correction Summary:");
     println!("   Epoch: {latest_epoch_number}");
@@ -298,7 +137,7 @@ pub async fn correct_last_epoch(
     );
     println!("   Total networks in merkle tree: {}", all_blocks.len());
 
-    // Step 8: Create the CorrectLastEpoch message and show details
+    // Step 7: Create the CorrectLastEpoch message and show details
     println!();
     println!("📝 Message Details:");
 
@@ -322,6 +161,294 @@ pub async fn correct_last_epoch(
     println!("   From: {}", config.owner_address);
     println!("   To (DataEdge): {}", config.data_edge_address);
 
+    // Step 8: Submit the transaction
+    submit_message(&config, payload, "CorrectLastEpoch", dry_run, yes).await
+}
+
+/// Prepares a `CorrectEpochs` message that corrects the block numbers of some networks in a past
+/// epoch, after previewing how the deltas and accelerations of all later epochs change.
+///
+/// Only dry runs are accepted for now: the Epoch Subgraph doesn't implement `CorrectEpochs` yet,
+/// and would misread the rest of the payload as other messages.
+pub async fn correct_epoch(
+    config: Config,
+    epoch_number: u64,
+    corrections: Vec<(Caip2ChainId, u64)>,
+    dry_run: bool,
+    yes: bool,
+) -> anyhow::Result<()> {
+    if epoch_number == 0 {
+        anyhow::bail!("Epoch 0 can't be corrected");
+    }
+    let corrections: BTreeMap<Caip2ChainId, u64> = corrections.into_iter().collect();
+
+    // Step 1: Query subgraph for the block numbers since the epoch before the corrected one
+    println!("🔍 Querying subgraph for block numbers since epoch {epoch_number}...");
+    let history = query_block_number_history(
+        &config.subgraph_url,
+        &config.bearer_token,
+        epoch_number - 1..=u64::MAX,
+    )
+    .await?;
+
+    let latest_epoch_number = history
+        .latest_epoch_number
+        .ok_or_else(|| anyhow::anyhow!("No latest epoch found in subgraph"))?;
+    if epoch_number > latest_epoch_number {
+        anyhow::bail!(
+            "Epoch {} is in the future; the latest epoch is {}",
+            epoch_number,
+            latest_epoch_number
+        );
+    }
+
+    println!("   Latest epoch: {latest_epoch_number}");
+    println!("   Registered networks: {}", history.networks.len());
+
+    for network in &history.networks {
+        let reached_latest_epoch = network
+            .block_updates
+            .last()
+            .is_none_or(|update| update.updated_at_epoch_number == latest_epoch_number);
+        if network.block_updates.len() >= MAX_BLOCK_NUMBER_HISTORY && !reached_latest_epoch {
+            anyhow::bail!(
+                "Epoch {} is too far back: {} has more than {} later block numbers",
+                epoch_number,
+                network.id.as_str(),
+                MAX_BLOCK_NUMBER_HISTORY
+            );
+        }
+    }
+
+    // Step 2: Get the networks of the corrected epoch and their array indices at the time, which
+    // might have changed since, by decoding the payload that posted it
+    println!("🔍 Decoding the payload that posted epoch {epoch_number}...");
+    let payload =
+        query_epoch_payload(&config.subgraph_url, &config.bearer_token, epoch_number).await?;
+    let epoch_networks = decode_epoch_networks(&payload)?;
+    println!(
+        "   Networks in epoch {epoch_number}: {}",
+        epoch_networks.len()
+    );
+    println!(
+        "   Posted merkle root: 0x{}",
+        hex::encode(payload.merkle_root)
+    );
+    let mut block_numbers: BTreeMap<Caip2ChainId, u64> = epoch_networks
+        .iter()
+        .map(|(chain_id, network)| (chain_id.clone(), network.block_number))
+        .collect();
+
+    // The posted blocks are often corrected because they were reorged out, so their canonical
+    // hashes don't have to add up to the posted merkle root
+    println!("🔗 Fetching block hashes for the posted blocks...");
+    let mut all_blocks = fetch_all_block_ptrs(&config, &block_numbers).await?;
+    let canonical_merkle_root =
+        epoch_encoding::merkle_root(&merkle_leaves(&epoch_networks, &all_blocks));
+    if canonical_merkle_root != payload.merkle_root {
+        println!(
+            "   ⚠️  The canonical hashes of the posted blocks add up to merkle root 0x{}, not to \
+             the posted one. Some of the posted blocks were probably reorged out.",
+            hex::encode(canonical_merkle_root)
+        );
+    }
+
+    // Step 3: Compute how the correction cascades through later epochs
+    println!();
+    println!("📊 Cascade preview:");
+    let mut data_by_network_id = BTreeMap::new();
+    for (chain_id, corrected_block_number) in &corrections {
+        let (_, epoch_network) = epoch_networks
+            .iter()
+            .find(|(id, _)| id == chain_id)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Chain ID '{}' wasn't registered in epoch {}",
+                    chain_id.as_str(),
+                    epoch_number
+                )
+            })?;
+        let network = history
+            .networks
+            .iter()
+            .find(|network| &network.id == chain_id)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Chain ID '{}' is not registered in the oracle",
+                    chain_id.as_str()
+                )
+            })?;
+        let cascade = cascade(
+            &network.block_updates,
+            epoch_number,
+            *corrected_block_number,
+        )?;
+        print_cascade(network, epoch_network.array_index, &cascade);
+
+        block_numbers.insert(chain_id.clone(), *corrected_block_number);
+        data_by_network_id.insert(epoch_network.array_index, *corrected_block_number);
+    }
+
+    // Step 4: Fetch the corrected block hashes and compute the new merkle root
+    println!();
+    println!("🔗 Fetching block hashes for the corrected blocks...");
+    let corrected_block_numbers = block_numbers
+        .iter()
+        .filter(|(chain_id, _)| corrections.contains_key(*chain_id))
+        .map(|(chain_id, block_number)| (chain_id.clone(), *block_number))
+        .collect();
+    all_blocks.extend(fetch_all_block_ptrs(&config, &corrected_block_numbers).await?);
+    let corrected = corrections.keys().cloned().collect();
+    print_block_ptrs(&all_blocks, &corrected);
+
+    println!("🧮 Computing merkle root...");
+    let computed_merkle_root =
+        epoch_encoding::merkle_root(&merkle_leaves(&epoch_networks, &all_blocks));
+    println!(
+        "   Computed merkle root: 0x{}",
+        hex::encode(computed_merkle_root)
+    );
+
+    // Step 5: Create the CorrectEpochs message and show details
+    println!();
+    println!("📝 Message Details:");
+
+    let json_message = serde_json::to_value([json_oracle_encoder::Message::CorrectEpochs {
+        epoch_number,
+        merkle_root: computed_merkle_root.to_vec(),
+        data_by_network_id,
+    }])?;
+
+    println!("   JSON message:");
+    println!("   {}", serde_json::to_string_pretty(&json_message)?);
+
+    let payload = messages_to_payload(json_message)?;
+    println!();
+    println!("   Encoded payload ({} bytes):", payload.len());
+    println!("   0x{}", hex::encode(&payload));
+
+    println!();
+    println!("   Transaction details:");
+    println!("   From: {}", config.owner_address);
+    println!("   To (DataEdge): {}", config.data_edge_address);
+
+    // Step 6: Submit the transaction, once the subgraph can handle it
+    if !dry_run {
+        anyhow::bail!(
+            "The Epoch Subgraph doesn't handle CorrectEpochs messages yet, and would misread the \
+             payload, so it can't be submitted. See docs/future_work/CorrectEpochs.md, and use \
+             --dry-run meanwhile"
+        );
+    }
+    submit_message(&config, payload, "CorrectEpochs", dry_run, yes).await
+}
+
+/// Parses a `<CAIP-2 chain ID>=<block number>` correction.
+pub fn parse_correction(s: &str) -> Result<(Caip2ChainId, u64), String> {
+    let (chain_id, block_number) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("Expected <CHAIN_ID>=<BLOCK_NUMBER>, found '{s}'"))?;
+    let block_number = block_number
+        .parse()
+        .map_err(|e| format!("Invalid block number '{block_number}': {e}"))?;
+    Ok((chain_id.parse()?, block_number))
+}
+
+/// Recomputes the block updates of a network from `epoch_number` onwards, after its block number
+/// in that epoch is corrected. Later block numbers stay the same, but their deltas and
+/// accelerations change. Returns every affected update before and after the correction.
+fn cascade(
+    block_updates: &[BlockUpdate],
+    epoch_number: u64,
+    corrected_block_number: u64,
+) -> anyhow::Result<Vec<(BlockUpdate, BlockUpdate)>> {
+    let start = block_updates
+        .iter()
+        .position(|update| update.updated_at_epoch_number == epoch_number)
+        .ok_or_else(|| anyhow::anyhow!("No block number found for epoch {}", epoch_number))?;
+
+    // The update right before the corrected one stays the same. If there's none, we can still
+    // work out its block number and delta backwards.
+    let (mut previous_block_number, mut previous_delta) = match start.checked_sub(1) {
+        Some(i) => (block_updates[i].block_number as i64, block_updates[i].delta),
+        None => {
+            let first = &block_updates[start];
+            (
+                first.block_number as i64 - first.delta,
+                first.delta - first.acceleration,
+            )
+        }
+    };
+
+    let mut cascade = Vec::with_capacity(block_updates.len() - start);
+    for old in &block_updates[start..] {
+        let block_number = if old.updated_at_epoch_number == epoch_number {
+            corrected_block_number
+        } else {
+            old.block_number
+        };
+        let delta = block_number as i64 - previous_block_number;
+        if delta < 0 {
+            anyhow::bail!(
+                "The correction would make block numbers go backwards in epoch {}",
+                old.updated_at_epoch_number
+            );
+        }
+        let new = BlockUpdate {
+            block_number,
+            acceleration: delta - previous_delta,
+            delta,
+            updated_at_epoch_number: old.updated_at_epoch_number,
+        };
+        previous_block_number = block_number as i64;
+        previous_delta = delta;
+        cascade.push((old.clone(), new));
+    }
+    Ok(cascade)
+}
+
+/// Prints how the block updates of a network change, along with its array index in the corrected
+/// epoch.
+fn print_cascade(
+    network: &NetworkHistory,
+    array_index: u64,
+    cascade: &[(BlockUpdate, BlockUpdate)],
+) {
+    fn change<T: PartialEq + std::fmt::Display>(old: T, new: T) -> String {
+        if old == new {
+            format!("{new}")
+        } else {
+            format!("{old} -> {new}")
+        }
+    }
+
+    println!("   {} (index {}):", network.id.as_str(), array_index);
+    for (old, new) in cascade {
+        if old == new {
+            continue;
+        }
+        println!(
+            "     epoch {}: block {}, delta {}, acceleration {}",
+            new.updated_at_epoch_number,
+            change(old.block_number, new.block_number),
+            change(old.delta, new.delta),
+            change(old.acceleration, new.acceleration)
+        );
+    }
+    let unchanged = cascade.iter().filter(|(old, new)| old == new).count();
+    if unchanged > 0 {
+        println!("     ({unchanged} later epoch(s) are unchanged)");
+    }
+}
+
+/// Submits a message to the DataEdge, after asking for confirmation unless `yes` is set.
+pub(crate) async fn submit_message(
+    config: &Config,
+    payload: Vec<u8>,
+    message_name: &str,
+    dry_run: bool,
+    yes: bool,
+) -> anyhow::Result<()> {
     if dry_run {
         println!();
         println!("🏃 Dry run complete. No transaction submitted.");
@@ -329,67 +456,191 @@ pub async fn correct_last_epoch(
     }
 
     if !yes {
-        print!("\n❓ This will submit a correction to the blockchain. Are you sure you want to proceed? (y/N): ");
+        print!("\n❓ This will submit a {message_name} message to the blockchain. Are you sure you want to proceed? (y/N): ");
         io::stdout().flush()?;
 
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
 
         if !input.trim().to_lowercase().starts_with('y') {
-            println!("❌ Correction cancelled.");
+            println!("❌ {message_name} message cancelled.");
             return Ok(());
         }
     }
 
-    // Step 9: Submit the transaction
     println!();
     println!("🚀 Submitting transaction...");
     let contracts = super::init_contracts(config.clone())?;
-    let tx = contracts
-        .submit_call(payload, &config.owner_private_key)
-        .await?;
+    let tx = contracts.submit_call(payload).await?;
 
-    println!("✅ CorrectLastEpoch message submitted successfully!");
+    println!("✅ {message_name} message submitted successfully!");
     println!("   Transaction hash: {tx:?}");
-    println!("   The subgraph will process this correction in the next few minutes.");
+    println!("   The subgraph will process this message in the next few minutes.");
 
     Ok(())
 }
 
-fn indexed_chains(config: &Config) -> Vec<JrpcProviderForChain<JrpcExpBackoff>> {
-    config
-        .indexed_chains
-        .iter()
-        .map(|chain| {
-            let transport = JrpcExpBackoff::http(
-                chain.jrpc_url.clone(),
-                chain.id.clone(),
-                config.retry_strategy_max_wait_time,
-            );
-            JrpcProviderForChain::new(chain.id.clone(), transport)
-        })
-        .collect()
+/// Fetches the hashes of the given blocks from the configured block sources.
+/// Networks that no provider is configured for are skipped.
+pub(crate) async fn fetch_block_ptrs(
+    config: &Config,
+    block_numbers: &BTreeMap<Caip2ChainId, u64>,
+) -> anyhow::Result<BTreeMap<Caip2ChainId, BlockPtr>> {
+    let mut all_blocks: BTreeMap<Caip2ChainId, BlockPtr> = BTreeMap::new();
+    for source in config.block_sources() {
+        if let Some(target_block_number) = block_numbers.get(source.chain_id()).copied() {
+            let block_ptr = source
+                .block_by_number(target_block_number)
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to get block {} from {}: {}",
+                        target_block_number,
+                        source.chain_id().as_str(),
+                        e
+                    )
+                })?;
+            all_blocks.insert(source.chain_id().clone(), block_ptr);
+        }
+    }
+    Ok(all_blocks)
 }
 
-fn blockmeta_indexed_chains(
+/// Like [`fetch_block_ptrs`], but fails if any block can't be fetched.
+pub(crate) async fn fetch_all_block_ptrs(
     config: &Config,
-) -> Vec<
-    BlockmetaProviderForChain<
-        tonic::codegen::InterceptedService<
-            tonic::transport::Channel,
-            blockmeta_client::AuthInterceptor,
-        >,
-    >,
-> {
-    config
-        .blockmeta_indexed_chains
-        .iter()
-        .map(|chain| {
-            BlockmetaProviderForChain::new(
-                chain.id.clone(),
-                chain.url.clone(),
-                &config.blockmeta_auth_token,
-            )
-        })
-        .collect()
+    block_numbers: &BTreeMap<Caip2ChainId, u64>,
+) -> anyhow::Result<BTreeMap<Caip2ChainId, BlockPtr>> {
+    let all_blocks = fetch_block_ptrs(config, block_numbers).await?;
+    if let Some(chain_id) = block_numbers
+        .keys()
+        .find(|chain_id| !all_blocks.contains_key(*chain_id))
+    {
+        anyhow::bail!(
+            "No provider is configured for {}, so its block hash is unknown",
+            chain_id.as_str()
+        );
+    }
+    Ok(all_blocks)
+}
+
+fn print_block_ptrs(blocks: &BTreeMap<Caip2ChainId, BlockPtr>, corrected: &BTreeSet<Caip2ChainId>) {
+    for (chain_id, block_ptr) in blocks {
+        let label = if corrected.contains(chain_id) {
+            " (CORRECTED)"
+        } else {
+            ""
+        };
+        println!(
+            "   {}{}: block {} -> hash {}",
+            chain_id.as_str(),
+            label,
+            block_ptr.number,
+            hex::encode(block_ptr.hash)
+        );
+    }
+}
+
+/// Computes the Merkle root of an epoch the same way the oracle does. Networks without a block
+/// keep their current block number, with a zero hash.
+pub(crate) fn merkle_root(
+    available_networks: Vec<(String, epoch_encoding::Network)>,
+    blocks: &BTreeMap<Caip2ChainId, BlockPtr>,
+) -> anyhow::Result<Bytes32> {
+    // Use the encoder to compute the merkle root by creating a temporary SetBlockNumbersForNextEpoch message
+    let mut encoder =
+        epoch_encoding::Encoder::new(epoch_encoding::CURRENT_ENCODING_VERSION, available_networks)
+            .expect("Failed to create encoder");
+
+    // Create a temporary message with our corrected blocks to compute the merkle root
+    let message = epoch_encoding::Message::SetBlockNumbersForNextEpoch {
+        epoch_number: None,
+        block_ptrs: blocks
+            .iter()
+            .map(|(chain_id, block_ptr)| (chain_id.as_str().to_owned(), *block_ptr))
+            .collect(),
+    };
+
+    let compressed = encoder
+        .compress(&[message])
+        .expect("Failed to compress message for merkle root computation");
+
+    match compressed
+        .first()
+        .and_then(|m| m.as_non_empty_block_numbers())
+    {
+        Some((_, root)) => Ok(root),
+        None => {
+            anyhow::bail!("Expected non-empty block numbers message for merkle root computation")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(epoch: u64, block_number: u64, delta: i64, acceleration: i64) -> BlockUpdate {
+        BlockUpdate {
+            block_number,
+            acceleration,
+            delta,
+            updated_at_epoch_number: epoch,
+        }
+    }
+
+    #[test]
+    fn cascade_recomputes_later_deltas_and_accelerations() {
+        let updates = [
+            update(4, 100, 10, 0),
+            update(5, 110, 10, 0),
+            update(6, 120, 10, 0),
+            update(7, 130, 10, 0),
+            update(8, 140, 10, 0),
+        ];
+        let new: Vec<_> = cascade(&updates, 5, 115)
+            .unwrap()
+            .into_iter()
+            .map(|(_, new)| new)
+            .collect();
+        assert_eq!(
+            new,
+            [
+                update(5, 115, 15, 5),
+                update(6, 120, 5, -10),
+                update(7, 130, 10, 5),
+                update(8, 140, 10, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn cascade_without_previous_update() {
+        // The network was registered in epoch 5, so there's nothing before it.
+        let updates = [update(5, 110, 110, 110), update(6, 120, 10, -100)];
+        let new: Vec<_> = cascade(&updates, 5, 105)
+            .unwrap()
+            .into_iter()
+            .map(|(_, new)| new)
+            .collect();
+        assert_eq!(new, [update(5, 105, 105, 105), update(6, 120, 15, -90)]);
+    }
+
+    #[test]
+    fn cascade_rejects_decreasing_block_numbers() {
+        let updates = [update(4, 100, 10, 0), update(5, 110, 10, 0)];
+        assert!(cascade(&updates, 5, 99).is_err());
+        assert!(cascade(&updates, 6, 120).is_err());
+    }
+
+    #[test]
+    fn parse_corrections() {
+        assert_eq!(
+            parse_correction("eip155:42161=12345678"),
+            Ok(("eip155:42161".parse().unwrap(), 12345678))
+        );
+        assert!(parse_correction("eip155:42161").is_err());
+        assert!(parse_correction("eip155:42161=abc").is_err());
+        assert!(parse_correction("mainnet=1").is_err());
+    }
 }
@@ -0,0 +1,137 @@
+use crate::subgraph::query_state_at_block;
+use crate::{query_subgraph, Config};
+use anyhow::{anyhow, Context};
+use epoch_encoding::CURRENT_ENCODING_VERSION;
+use json_oracle_encoder::{calldata_to_payload, is_calldata, payload_to_messages, MessageBlock};
+use web3::types::{TransactionId, H256};
+
+/// Decodes a hex-encoded payload, `crossChainEpochOracle` calldata, or with `tx_hash`, the
+/// transaction hash of such a call on the protocol chain, and prints its messages as JSON that
+/// `encode` accepts.
+pub async fn decode(
+    config: Option<Config>,
+    input: String,
+    tx_hash: bool,
+    network_count: Option<u64>,
+    encoding_version: Option<u64>,
+) -> anyhow::Result<()> {
+    let bytes = hex::decode(input.trim().trim_start_matches("0x"))
+        .context("The input must be hex-encoded")?;
+
+    // Transactions are decoded from the subgraph state right before their block, and everything
+    // else from the current state.
+    let mut payload_block = None;
+    let payload = if tx_hash {
+        if bytes.len() != H256::len_bytes() {
+            return Err(anyhow!(
+                "Transaction hashes are {} bytes long, not {}",
+                H256::len_bytes(),
+                bytes.len()
+            ));
+        }
+        let config = config.as_ref().ok_or_else(|| {
+            anyhow!("A configuration file is required to look up transaction hashes")
+        })?;
+        let (calldata, block_number) = transaction_input(config, H256::from_slice(&bytes)).await?;
+        payload_block = block_number;
+        calldata_to_payload(&calldata)?
+    } else if is_calldata(&bytes) {
+        calldata_to_payload(&bytes)?
+    } else {
+        bytes
+    };
+
+    let subgraph_state = match (&config, network_count, encoding_version) {
+        (Some(config), None, _) | (Some(config), _, None) => {
+            Some(subgraph_state(config, payload_block).await?)
+        }
+        _ => None,
+    };
+
+    let network_count = match (network_count, &subgraph_state) {
+        (Some(count), _) => count,
+        (None, Some(state)) => {
+            let count = state.as_ref().map_or(0, |(_, count)| *count);
+            eprintln!(
+                "Assuming the {count} network(s) registered in the subgraph {}; \
+                 use --network-count to override.",
+                state_description(payload_block)
+            );
+            count
+        }
+        (None, None) => {
+            eprintln!("Assuming no registered networks; use --network-count to override.");
+            0
+        }
+    };
+
+    let encoding_version = match (encoding_version, &subgraph_state) {
+        (Some(version), _) => version,
+        (None, Some(state)) => {
+            let version = state
+                .as_ref()
+                .map_or(CURRENT_ENCODING_VERSION, |(version, _)| *version);
+            eprintln!(
+                "Assuming the subgraph's encoding version {version} {}; \
+                 use --encoding-version to override.",
+                state_description(payload_block)
+            );
+            version
+        }
+        (None, None) => CURRENT_ENCODING_VERSION,
+    };
+
+    let messages = payload_to_messages(&payload, encoding_version, network_count)?;
+    let json = serde_json::to_string_pretty(&[MessageBlock::MessageBlock(messages)])?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Gets the encoding version and the number of registered networks of the subgraph, right before
+/// `block_number` if given, or currently otherwise. `None` means the subgraph wasn't initialized.
+pub(super) async fn subgraph_state(
+    config: &Config,
+    block_number: Option<u64>,
+) -> anyhow::Result<Option<(u64, u64)>> {
+    let (url, bearer_token) = (&config.subgraph_url, &config.bearer_token);
+    let state = match block_number {
+        Some(block_number) => query_state_at_block(url, bearer_token, block_number as i64 - 1)
+            .await?
+            .map(|state| (state.encoding_version, state.networks.len() as u64)),
+        None => query_subgraph(url, bearer_token)
+            .await?
+            .global_state
+            .map(|state| (state.encoding_version as u64, state.networks.len() as u64)),
+    };
+    Ok(state)
+}
+
+fn state_description(block_number: Option<u64>) -> String {
+    match block_number {
+        Some(block_number) => format!("before block {block_number}"),
+        None => "currently".to_string(),
+    }
+}
+
+/// Gets the calldata of a DataEdge transaction, and the block that it was included in, if any.
+async fn transaction_input(
+    config: &Config,
+    tx_hash: H256,
+) -> anyhow::Result<(Vec<u8>, Option<u64>)> {
+    let protocol_chain = super::protocol_chain(config);
+    let transaction = protocol_chain
+        .web3
+        .eth()
+        .transaction(TransactionId::Hash(tx_hash))
+        .await?
+        .ok_or_else(|| anyhow!("Transaction {tx_hash:?} not found on the protocol chain"))?;
+    if transaction.to != Some(config.data_edge_address) {
+        return Err(anyhow!(
+            "Transaction {tx_hash:?} was not sent to the DataEdge contract"
+        ));
+    }
+    Ok((
+        transaction.input.0,
+        transaction.block_number.map(|number| number.as_u64()),
+    ))
+}
@@ -1,28 +1,56 @@
+pub mod audit;
+pub mod check_config;
 pub mod correct_epoch;
 pub mod current_epoch;
+pub mod decode;
+pub mod networks;
+pub mod prove;
 pub mod send_message;
 
-pub use correct_epoch::correct_last_epoch;
+pub use audit::audit;
+pub use check_config::check_config;
+pub use correct_epoch::{correct_epoch, correct_last_epoch};
 pub use current_epoch::print_current_epoch;
+pub use decode::decode;
+pub use networks::{add_networks, remove_networks};
+pub use prove::prove;
 pub use send_message::send_message;
 
 use crate::contracts::Contracts;
+use crate::runner::jrpc_utils::JrpcFallback;
+use crate::signer::owner_signer;
 use crate::{Config, JrpcProviderForChain};
 use reqwest::Client;
 use std::time::Duration;
 use web3::transports::Http;
 
-pub(crate) fn init_contracts(config: Config) -> anyhow::Result<Contracts<Http>> {
+pub(crate) fn protocol_chain(config: &Config) -> JrpcProviderForChain<JrpcFallback<Http>> {
     let client = Client::builder()
         .timeout(Duration::from_secs(60))
         .build()
         .unwrap();
-    let transport = Http::with_client(client, config.protocol_chain.jrpc_url);
-    let protocol_chain = JrpcProviderForChain::new(config.protocol_chain.id, transport);
+    let endpoints = config
+        .protocol_chain
+        .jrpc_urls
+        .iter()
+        .map(|url| Http::with_client(client.clone(), url.clone()))
+        .collect();
+    let transport = JrpcFallback::new(
+        endpoints,
+        config.protocol_chain.id.clone(),
+        config.protocol_chain.endpoint_cooldown,
+    );
+    JrpcProviderForChain::new(config.protocol_chain.id.clone(), transport)
+}
+
+pub(crate) fn init_contracts(config: Config) -> anyhow::Result<Contracts<JrpcFallback<Http>>> {
+    let protocol_chain = protocol_chain(&config);
+    let signer = owner_signer(&config, protocol_chain.web3.clone());
     Contracts::new(
         protocol_chain.web3,
         config.data_edge_address,
         config.epoch_manager_address,
-        config.transaction_monitoring_options,
+        config.transaction_monitoring_options.clone(),
+        signer,
     )
 }
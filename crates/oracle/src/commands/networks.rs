@@ -0,0 +1,264 @@
+use super::correct_epoch::submit_message;
+use crate::subgraph::Network;
+use crate::{query_subgraph, Caip2ChainId, Config};
+use json_oracle_encoder::messages_to_payload;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// Registers new networks with a `RegisterNetworks` message, or with a
+/// `RegisterNetworksAndAliases` message if they come with aliases.
+pub async fn add_networks(
+    config: Config,
+    additions: Vec<(Caip2ChainId, Option<String>)>,
+    dry_run: bool,
+    yes: bool,
+) -> anyhow::Result<()> {
+    let registered = registered_networks(&config).await?;
+    let message = registration_message(&registered, &additions, &[])?;
+    preview_and_submit(&config, &registered, &additions, &[], message, dry_run, yes).await
+}
+
+/// Unregisters networks by chain ID, with a `RegisterNetworks` message that removes their array
+/// indices.
+pub async fn remove_networks(
+    config: Config,
+    removals: Vec<Caip2ChainId>,
+    dry_run: bool,
+    yes: bool,
+) -> anyhow::Result<()> {
+    let registered = registered_networks(&config).await?;
+    let message = registration_message(&registered, &[], &removals)?;
+    preview_and_submit(&config, &registered, &[], &removals, message, dry_run, yes).await
+}
+
+/// Parses a network to register, as `<CAIP-2 chain ID>` or `<CAIP-2 chain ID>=<alias>`.
+pub fn parse_network(s: &str) -> Result<(Caip2ChainId, Option<String>), String> {
+    let (chain_id, alias) = match s.split_once('=') {
+        Some((chain_id, "")) => return Err(format!("The alias of '{chain_id}' is empty")),
+        Some((chain_id, alias)) => (chain_id, Some(alias.to_string())),
+        None => (s, None),
+    };
+    let chain_id = Caip2ChainId::from_str(chain_id)
+        .map_err(|err| format!("Invalid CAIP-2 chain ID '{chain_id}': {err}"))?;
+    Ok((chain_id, alias))
+}
+
+async fn registered_networks(config: &Config) -> anyhow::Result<Vec<Network>> {
+    println!("🔍 Querying subgraph for the registered networks...");
+    let subgraph_state = query_subgraph(&config.subgraph_url, &config.bearer_token).await?;
+    let networks = subgraph_state
+        .global_state
+        .map(|global_state| global_state.networks)
+        .unwrap_or_default();
+    println!("   Registered networks: {}", networks.len());
+    for network in &networks {
+        println!("   [{}] {}", network.array_index, network.id.as_str());
+    }
+    Ok(networks)
+}
+
+/// Builds the JSON message that adds and removes the given networks. Removals are turned into the
+/// array indices the subgraph knows the networks by.
+fn registration_message(
+    registered: &[Network],
+    additions: &[(Caip2ChainId, Option<String>)],
+    removals: &[Caip2ChainId],
+) -> anyhow::Result<serde_json::Value> {
+    let mut added = BTreeSet::new();
+    for (chain_id, _) in additions {
+        if registered.iter().any(|network| &network.id == chain_id) {
+            anyhow::bail!("Network '{}' is already registered", chain_id.as_str());
+        }
+        if !added.insert(chain_id) {
+            anyhow::bail!("Network '{}' is added more than once", chain_id.as_str());
+        }
+    }
+
+    let mut remove = BTreeSet::new();
+    for chain_id in removals {
+        let network = registered
+            .iter()
+            .find(|network| &network.id == chain_id)
+            .ok_or_else(|| anyhow::anyhow!("Network '{}' is not registered", chain_id.as_str()))?;
+        // The subgraph can't remove the same index twice.
+        if !remove.insert(network.array_index) {
+            anyhow::bail!("Network '{}' is removed more than once", chain_id.as_str());
+        }
+    }
+
+    let aliases = additions
+        .iter()
+        .filter(|(_, alias)| alias.is_some())
+        .count();
+    let message = if aliases == 0 {
+        let add: Vec<&str> = additions
+            .iter()
+            .map(|(chain_id, _)| chain_id.as_str())
+            .collect();
+        json!({ "message": "RegisterNetworks", "remove": remove, "add": add })
+    } else if aliases == additions.len() {
+        let add: Vec<(&str, &str)> = additions
+            .iter()
+            .map(|(chain_id, alias)| (chain_id.as_str(), alias.as_deref().unwrap_or_default()))
+            .collect();
+        json!({ "message": "RegisterNetworksAndAliases", "remove": remove, "add": add })
+    } else {
+        anyhow::bail!("Either all networks or none of them must have an alias");
+    };
+    Ok(json!([message]))
+}
+
+/// The chain IDs of the registered networks after the message, by array index. Remaining networks
+/// keep their order and new ones are appended, like the subgraph does.
+fn networks_after(
+    registered: &[Network],
+    additions: &[(Caip2ChainId, Option<String>)],
+    removals: &[Caip2ChainId],
+) -> Vec<Caip2ChainId> {
+    registered
+        .iter()
+        .map(|network| network.id.clone())
+        .filter(|chain_id| !removals.contains(chain_id))
+        .chain(additions.iter().map(|(chain_id, _)| chain_id.clone()))
+        .collect()
+}
+
+async fn preview_and_submit(
+    config: &Config,
+    registered: &[Network],
+    additions: &[(Caip2ChainId, Option<String>)],
+    removals: &[Caip2ChainId],
+    message: serde_json::Value,
+    dry_run: bool,
+    yes: bool,
+) -> anyhow::Result<()> {
+    println!();
+    println!("📋 Registered networks after the message:");
+    for (array_index, chain_id) in networks_after(registered, additions, removals)
+        .iter()
+        .enumerate()
+    {
+        let label = if additions.iter().any(|(added, _)| added == chain_id) {
+            " (ADDED)"
+        } else {
+            ""
+        };
+        println!("   [{array_index}] {}{label}", chain_id.as_str());
+    }
+    for chain_id in removals {
+        println!("   {} (REMOVED)", chain_id.as_str());
+    }
+
+    println!();
+    println!("📝 Message Details:");
+    println!("   JSON message:");
+    println!("   {}", serde_json::to_string_pretty(&message)?);
+
+    let message_name = message[0]["message"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let payload = messages_to_payload(message)?;
+    println!();
+    println!("   Encoded payload ({} bytes):", payload.len());
+    println!("   0x{}", hex::encode(&payload));
+
+    println!();
+    println!("   Transaction details:");
+    println!("   From: {}", config.owner_address);
+    println!("   To (DataEdge): {}", config.data_edge_address);
+
+    submit_message(config, payload, &message_name, dry_run, yes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered(chain_ids: &[&str]) -> Vec<Network> {
+        chain_ids
+            .iter()
+            .enumerate()
+            .map(|(array_index, chain_id)| Network {
+                id: chain_id.parse().unwrap(),
+                array_index: array_index as u64,
+                latest_block_update: None,
+            })
+            .collect()
+    }
+
+    fn chain_ids(chain_ids: &[&str]) -> Vec<Caip2ChainId> {
+        chain_ids.iter().map(|id| id.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_networks() {
+        let (chain_id, alias) = parse_network("eip155:100=gnosis").unwrap();
+        assert_eq!(chain_id.as_str(), "eip155:100");
+        assert_eq!(alias.as_deref(), Some("gnosis"));
+        assert_eq!(parse_network("eip155:1").unwrap().1, None);
+        assert!(parse_network("eip155:1=").is_err());
+        assert!(parse_network("mainnet").is_err());
+    }
+
+    #[test]
+    fn registration_messages() {
+        let registered = registered(&["eip155:1", "eip155:100", "eip155:137"]);
+
+        let additions = vec![(chain_ids(&["eip155:10"])[0].clone(), None)];
+        let message = registration_message(&registered, &additions, &[]).unwrap();
+        assert_eq!(
+            message,
+            json!([{ "message": "RegisterNetworks", "remove": [], "add": ["eip155:10"] }])
+        );
+
+        let removals = chain_ids(&["eip155:137", "eip155:1"]);
+        let message = registration_message(&registered, &[], &removals).unwrap();
+        assert_eq!(
+            message,
+            json!([{ "message": "RegisterNetworks", "remove": [0, 2], "add": [] }])
+        );
+        assert_eq!(
+            networks_after(&registered, &additions, &removals),
+            chain_ids(&["eip155:100", "eip155:10"])
+        );
+
+        let additions = vec![(
+            chain_ids(&["eip155:10"])[0].clone(),
+            Some("optimism".to_string()),
+        )];
+        let message = registration_message(&registered, &additions, &[]).unwrap();
+        assert_eq!(
+            message,
+            json!([{
+                "message": "RegisterNetworksAndAliases",
+                "remove": [],
+                "add": [["eip155:10", "optimism"]]
+            }])
+        );
+        messages_to_payload(message).unwrap();
+    }
+
+    #[test]
+    fn invalid_registrations() {
+        let registered = registered(&["eip155:1", "eip155:100"]);
+        let already_registered = vec![(chain_ids(&["eip155:1"])[0].clone(), None)];
+        assert!(registration_message(&registered, &already_registered, &[]).is_err());
+
+        let not_registered = chain_ids(&["eip155:10"]);
+        assert!(registration_message(&registered, &[], &not_registered).is_err());
+
+        let removed_twice = chain_ids(&["eip155:1", "eip155:1"]);
+        assert!(registration_message(&registered, &[], &removed_twice).is_err());
+
+        let some_aliases = vec![
+            (chain_ids(&["eip155:10"])[0].clone(), None),
+            (
+                chain_ids(&["eip155:137"])[0].clone(),
+                Some("polygon".to_string()),
+            ),
+        ];
+        assert!(registration_message(&registered, &some_aliases, &[]).is_err());
+    }
+}
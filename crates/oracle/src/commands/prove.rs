@@ -0,0 +1,178 @@
+use super::correct_epoch::fetch_all_block_ptrs;
+use crate::subgraph::{query_epoch_networks, EpochNetworks};
+use crate::{Caip2ChainId, Config};
+use epoch_encoding::messages::Bytes32;
+use epoch_encoding::{merkle_proof, merkle_root, BlockPtr, MerkleLeaf, MerkleProof};
+use std::collections::BTreeMap;
+
+/// Prints a JSON proof that a network was at some block in an epoch, which can be verified against
+/// the Merkle root posted for that epoch.
+///
+/// The leaves are built from the networks that were registered when the epoch was set, by their
+/// array index at the time, so networks added or removed since don't affect the proof.
+pub async fn prove(
+    config: Config,
+    epoch_number: u64,
+    chain_id: Caip2ChainId,
+) -> anyhow::Result<()> {
+    let epoch_networks =
+        query_epoch_networks(&config.subgraph_url, &config.bearer_token, epoch_number).await?;
+    let network_index = epoch_networks
+        .networks
+        .iter()
+        .position(|(id, _)| id == &chain_id)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Chain ID '{}' wasn't registered in epoch {}",
+                chain_id.as_str(),
+                epoch_number
+            )
+        })?;
+
+    let block_numbers = epoch_networks.networks.iter().cloned().collect();
+    let blocks = fetch_all_block_ptrs(&config, &block_numbers).await?;
+    let leaves = merkle_leaves(&epoch_networks, &blocks);
+
+    let computed_merkle_root = merkle_root(&leaves);
+    let posted_merkle_root = epoch_networks.merkle_root;
+    if computed_merkle_root != posted_merkle_root {
+        anyhow::bail!(
+            "The computed Merkle root 0x{} doesn't match the one posted for epoch {}: 0x{}",
+            hex::encode(computed_merkle_root),
+            epoch_number,
+            hex::encode(posted_merkle_root)
+        );
+    }
+
+    let proof = merkle_proof(&leaves, network_index).expect("The network has a leaf");
+    let json = proof_json(epoch_number, &chain_id, &proof, &posted_merkle_root);
+    println!("{}", serde_json::to_string_pretty(&json)?);
+    Ok(())
+}
+
+/// Builds the Merkle leaves of an epoch, by the array indices of its networks at the time.
+fn merkle_leaves(
+    epoch_networks: &EpochNetworks,
+    blocks: &BTreeMap<Caip2ChainId, BlockPtr>,
+) -> Vec<MerkleLeaf> {
+    epoch_networks
+        .networks
+        .iter()
+        .enumerate()
+        .map(|(network_index, (chain_id, _))| {
+            let block = &blocks[chain_id];
+            MerkleLeaf {
+                network_index: network_index as u64,
+                block_number: block.number,
+                block_hash: block.hash,
+            }
+        })
+        .collect()
+}
+
+fn proof_json(
+    epoch_number: u64,
+    chain_id: &Caip2ChainId,
+    proof: &MerkleProof,
+    merkle_root: &Bytes32,
+) -> serde_json::Value {
+    serde_json::json!({
+        "epochNumber": epoch_number,
+        "chainId": chain_id.as_str(),
+        "networkIndex": proof.leaf.network_index,
+        "blockNumber": proof.leaf.block_number,
+        "blockHash": format!("0x{}", hex::encode(proof.leaf.block_hash)),
+        "merkleRoot": format!("0x{}", hex::encode(merkle_root)),
+        "proof": proof
+            .siblings
+            .iter()
+            .map(|sibling| format!("0x{}", hex::encode(sibling)))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_bytes32(value: &serde_json::Value) -> [u8; 32] {
+        let hex_str = value.as_str().unwrap().trim_start_matches("0x");
+        hex::decode(hex_str).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn leaves_use_the_network_indices_of_the_epoch() {
+        let gnosis: Caip2ChainId = "eip155:100".parse().unwrap();
+        let epoch_networks = EpochNetworks {
+            merkle_root: [0; 32],
+            networks: vec![
+                (gnosis.clone(), 200),
+                (Caip2ChainId::ethereum_mainnet(), 100),
+            ],
+        };
+        let blocks = BTreeMap::from([
+            (
+                Caip2ChainId::ethereum_mainnet(),
+                BlockPtr {
+                    number: 100,
+                    hash: [1; 32],
+                },
+            ),
+            (
+                gnosis,
+                BlockPtr {
+                    number: 200,
+                    hash: [2; 32],
+                },
+            ),
+        ]);
+        let leaves = merkle_leaves(&epoch_networks, &blocks);
+        assert_eq!(
+            leaves,
+            vec![
+                MerkleLeaf {
+                    network_index: 0,
+                    block_number: 200,
+                    block_hash: [2; 32],
+                },
+                MerkleLeaf {
+                    network_index: 1,
+                    block_number: 100,
+                    block_hash: [1; 32],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn json_proofs_can_be_verified() {
+        let leaves: Vec<MerkleLeaf> = (0..5)
+            .map(|i| MerkleLeaf {
+                network_index: i,
+                block_number: 100 * i,
+                block_hash: [i as u8; 32],
+            })
+            .collect();
+        let root = merkle_root(&leaves);
+        let proof = merkle_proof(&leaves, 3).unwrap();
+        let json = proof_json(42, &Caip2ChainId::ethereum_mainnet(), &proof, &root);
+
+        let parsed = MerkleProof {
+            leaf: MerkleLeaf {
+                network_index: json["networkIndex"].as_u64().unwrap(),
+                block_number: json["blockNumber"].as_u64().unwrap(),
+                block_hash: decode_bytes32(&json["blockHash"]),
+            },
+            siblings: json["proof"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(decode_bytes32)
+                .collect(),
+        };
+        assert_eq!(parsed, proof);
+        assert!(parsed.verify(&decode_bytes32(&json["merkleRoot"])));
+        assert_eq!(json["chainId"], "eip155:1");
+        assert_eq!(json["epochNumber"], 42);
+    }
+}
@@ -1,9 +1,38 @@
+use super::decode::subgraph_state;
 use crate::Config;
+use epoch_encoding::{deserialize_messages, CompressedMessage, CURRENT_ENCODING_VERSION};
+use json_oracle_encoder::CORRECT_EPOCHS_WARNING;
 
 pub async fn send_message(config: Config, payload: Vec<u8>) -> anyhow::Result<()> {
-    let private_key = config.owner_private_key;
+    match payload_messages(&config, &payload).await {
+        Ok(messages) => {
+            let correct_epochs = messages
+                .iter()
+                .any(|message| matches!(message, CompressedMessage::CorrectEpochs { .. }));
+            if correct_epochs {
+                eprintln!("{CORRECT_EPOCHS_WARNING}");
+            }
+        }
+        Err(error) => eprintln!("⚠️  Couldn't decode the payload to check its messages: {error:#}"),
+    }
+
     let contracts = super::init_contracts(config)?;
-    let tx = contracts.submit_call(payload, &private_key).await?;
+    let tx = contracts.submit_call(payload).await?;
     println!("Sent message.\nTransaction hash: {tx:?}");
     Ok(())
 }
+
+/// Decodes the payload with the subgraph's current encoding version and networks.
+async fn payload_messages(
+    config: &Config,
+    payload: &[u8],
+) -> anyhow::Result<Vec<CompressedMessage>> {
+    let (encoding_version, network_count) = subgraph_state(config, None)
+        .await?
+        .unwrap_or((CURRENT_ENCODING_VERSION, 0));
+    Ok(deserialize_messages(
+        payload,
+        encoding_version,
+        network_count,
+    )?)
+}
@@ -1,15 +1,24 @@
+use crate::block_source::{BlockSource, ConfirmedBlockSource, QuorumBlockSource};
+use crate::keystore::decrypt_keystore;
 use crate::models::Caip2ChainId;
+use crate::runner::jrpc_utils::JrpcExpBackoff;
+use crate::{BlockmetaProviderForChain, JrpcProviderForChain};
 use anyhow::Context;
 use secp256k1::SecretKey;
 use serde::Deserialize;
-use serde_utils::{EitherLiteralOrEnvVar, FromStrWrapper};
+use serde_utils::{EitherLiteralOrEnvVar, FromStrWrapper, OneOrMany};
 use std::{
-    collections::HashMap, fmt::Display, fs::read_to_string, path::Path, str::FromStr,
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::FromStr,
     time::Duration,
 };
 use thiserror::Error;
 use tracing_subscriber::filter::LevelFilter;
 use url::Url;
+use web3::signing::{Key, SecretKeyRef};
 use web3::types::H160;
 
 #[derive(Error, Debug)]
@@ -20,6 +29,7 @@ pub enum ConfigError {
     Toml(#[from] toml::de::Error),
 }
 
+/// An indexed chain provider. Chains with several providers have one entry per provider.
 #[derive(Clone, Debug)]
 pub struct IndexedChain {
     pub id: Caip2ChainId,
@@ -35,11 +45,14 @@ pub struct BlockmetaIndexedChain {
 #[derive(Clone, Debug)]
 pub struct ProtocolChain {
     pub id: Caip2ChainId,
-    pub jrpc_url: Url,
+    /// The primary JSON-RPC endpoint first, followed by the fallback ones.
+    pub jrpc_urls: Vec<Url>,
     pub polling_interval: Duration,
+    /// How long a failed endpoint is skipped before being tried again.
+    pub endpoint_cooldown: Duration,
 }
 
-#[derive(Clone, Deserialize, Debug, Copy)]
+#[derive(Clone, Deserialize, Debug)]
 pub struct TransactionMonitoringOptions {
     #[serde(default = "serde_defaults::transaction_monitoring_confirmation_timeout_in_seconds")]
     /// How long to wait for a transaction to be confirmed
@@ -56,12 +69,35 @@ pub struct TransactionMonitoringOptions {
     /// How many confirmations to wait for
     #[serde(default = "serde_defaults::transaction_monitoring_confirmations")]
     pub confirmations: usize,
+    /// The gas limit of DataEdge calls whose gas couldn't be estimated.
     #[serde(default = "serde_defaults::transaction_monitoring_gas_limit")]
     pub gas_limit: u64,
+    /// How much to multiply `eth_estimateGas` results by, as a safety margin.
+    #[serde(default = "serde_defaults::transaction_monitoring_gas_estimate_multiplier")]
+    pub gas_estimate_multiplier: f64,
+    /// The highest gas limit that estimates can lead to.
+    #[serde(default)]
+    pub gas_estimate_cap: Option<u64>,
     #[serde(default)]
     pub max_fee_per_gas: Option<u64>,
     #[serde(default)]
     pub max_priority_fee_per_gas: Option<u64>,
+    /// Send EIP-1559 transactions, with fees estimated from `eth_feeHistory`, rather than legacy
+    /// ones.
+    #[serde(default)]
+    pub eip1559: bool,
+    /// Which percentile of the priority fees paid in the latest blocks to pay, for EIP-1559
+    /// transactions.
+    #[serde(default = "serde_defaults::transaction_monitoring_priority_fee_percentile")]
+    pub priority_fee_percentile: f64,
+    /// The highest gas price, or max fee per gas for EIP-1559 transactions, that transactions are
+    /// sent with, including after fee bumps.
+    #[serde(default)]
+    pub fee_ceiling_per_gas: Option<u64>,
+    /// Where to save broadcast but unconfirmed transactions, so that they are replaced rather than
+    /// duplicated after a restart.
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
 }
 
 impl Default for TransactionMonitoringOptions {
@@ -75,16 +111,121 @@ impl Default for TransactionMonitoringOptions {
             poll_interval_in_seconds: transaction_monitoring_poll_interval_in_seconds(),
             confirmations: transaction_monitoring_confirmations(),
             gas_limit: transaction_monitoring_gas_limit(),
+            gas_estimate_multiplier: transaction_monitoring_gas_estimate_multiplier(),
+            gas_estimate_cap: None,
             max_fee_per_gas: None,
             max_priority_fee_per_gas: None,
+            eip1559: false,
+            priority_fee_percentile: transaction_monitoring_priority_fee_percentile(),
+            fee_ceiling_per_gas: None,
+            state_file: None,
         }
     }
 }
 
+/// How the block of an indexed chain is picked when it has several providers.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(tag = "policy", rename_all = "kebab-case")]
+pub enum QuorumPolicy {
+    /// Use the first provider that responds, in configuration order, with JSON-RPC providers
+    /// before Blockmeta ones.
+    #[default]
+    FirstHealthy,
+    /// Use the latest block with the median number among all responding providers. Blocks
+    /// looked up by number must have the same hash on all responding providers.
+    Median,
+    /// Require `min_agreement` providers to agree on the block hash at the highest block number
+    /// that at least `min_agreement` providers reached.
+    Agreement { min_agreement: usize },
+}
+
+/// Which block of an indexed chain gets posted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlockConfirmation {
+    /// The chain head.
+    #[default]
+    Head,
+    /// The block this many blocks below the chain head.
+    Depth(u64),
+    /// The `safe` block, which is unlikely to be reorged. JSON-RPC only.
+    Safe,
+    /// The `finalized` block, which can't be reorged. JSON-RPC only.
+    Finalized,
+}
+
+impl BlockConfirmation {
+    /// The JSON-RPC block tag, if the confirmation is one.
+    pub fn tag(&self) -> Option<&'static str> {
+        match self {
+            Self::Safe => Some("safe"),
+            Self::Finalized => Some("finalized"),
+            Self::Head | Self::Depth(_) => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockConfirmation {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum DepthOrTag {
+            Depth(u64),
+            Tag(String),
+        }
+
+        match DepthOrTag::deserialize(deserializer)? {
+            DepthOrTag::Depth(0) => Ok(Self::Head),
+            DepthOrTag::Depth(depth) => Ok(Self::Depth(depth)),
+            DepthOrTag::Tag(tag) => match tag.as_str() {
+                "latest" => Ok(Self::Head),
+                "safe" => Ok(Self::Safe),
+                "finalized" => Ok(Self::Finalized),
+                _ => Err(serde::de::Error::custom(format!(
+                    "Unknown block tag '{tag}', expected 'latest', 'safe', 'finalized' or a number of confirmations"
+                ))),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, Copy)]
+pub struct AuditOptions {
+    /// How many of the latest epochs to audit
+    #[serde(default = "serde_defaults::audit_epochs")]
+    pub epochs: u64,
+    /// How much time to wait between audits
+    #[serde(default = "serde_defaults::audit_interval_in_seconds")]
+    pub interval_in_seconds: u64,
+}
+
+/// How the transactions that the oracle sends to the protocol chain are signed.
+#[derive(Clone, Debug)]
+pub enum SignerConfig {
+    /// With the owner's private key.
+    Local(SecretKey),
+    /// By a remote signer that supports `eth_signTransaction`, e.g. web3signer, so that the
+    /// owner's private key never reaches the oracle.
+    Remote { url: Url },
+}
+
+/// Checks that a local private key belongs to the owner address, returning the key's address.
+pub fn check_owner_key(key: &SecretKey, owner_address: H160) -> anyhow::Result<H160> {
+    let key_address = SecretKeyRef::new(key).address();
+    if key_address != owner_address {
+        anyhow::bail!(
+            "The owner's private key belongs to {key_address:?}, not to the owner address {owner_address:?}"
+        );
+    }
+    Ok(key_address)
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub log_level: LevelFilter,
-    pub owner_private_key: SecretKey,
+    pub owner_signer: SignerConfig,
     pub data_edge_address: H160,
     pub epoch_manager_address: H160,
     pub subgraph_url: Url,
@@ -93,33 +234,208 @@ pub struct Config {
     pub indexed_chains: Vec<IndexedChain>,
     pub blockmeta_indexed_chains: Vec<BlockmetaIndexedChain>,
     pub freshness_threshold: u64,
+    pub payload_verification_timeout: Duration,
     pub protocol_chain: ProtocolChain,
     pub retry_strategy_max_wait_time: Duration,
     pub metrics_port: u16,
     pub transaction_monitoring_options: TransactionMonitoringOptions,
     pub blockmeta_auth_token: String,
+    pub audit_options: Option<AuditOptions>,
+    pub quorum_policies: HashMap<Caip2ChainId, QuorumPolicy>,
+    pub block_confirmations: HashMap<Caip2ChainId, BlockConfirmation>,
+    pub auto_register: bool,
+    pub network_aliases: HashMap<Caip2ChainId, String>,
 }
 
 impl Config {
     /// Loads all configuration options the provided TOML configuration file and environment
     /// variables.
     pub fn parse(config_file: impl AsRef<Path>) -> Self {
-        let config_file = ConfigFile::from_file(config_file.as_ref())
+        Self::load(config_file.as_ref(), true)
+    }
+
+    /// Like [`Config::parse`], but doesn't check that a local owner key belongs to the owner
+    /// address, so that the preflight checks can report a mismatch along with everything else.
+    pub fn parse_without_owner_key_check(config_file: impl AsRef<Path>) -> Self {
+        Self::load(config_file.as_ref(), false)
+    }
+
+    fn load(config_file: &Path, owner_key_check: bool) -> Self {
+        let config_file = ConfigFile::from_file(config_file)
             .context("Failed to read config file as valid TOML")
             .unwrap();
 
         Self::from_config_file(config_file)
+            .and_then(|config| config.validate(owner_key_check).map(|()| config))
+            .context("Invalid configuration")
+            .unwrap()
     }
 
-    fn from_config_file(config_file: ConfigFile) -> Self {
-        Self {
+    /// Builds a [`BlockSource`] for every configured indexed chain. Chains with several providers
+    /// get a [`QuorumBlockSource`] that applies their [`QuorumPolicy`], and chains that must only
+    /// post confirmed blocks get a [`ConfirmedBlockSource`].
+    pub fn block_sources(&self) -> Vec<Box<dyn BlockSource>> {
+        let mut sources_by_chain: BTreeMap<Caip2ChainId, Vec<Box<dyn BlockSource>>> =
+            BTreeMap::new();
+        for chain in &self.indexed_chains {
+            let transport = JrpcExpBackoff::http(
+                chain.jrpc_url.clone(),
+                chain.id.clone(),
+                self.retry_strategy_max_wait_time,
+            );
+            let source = JrpcProviderForChain::new(chain.id.clone(), transport)
+                .with_rpc_url(chain.jrpc_url.clone());
+            sources_by_chain
+                .entry(chain.id.clone())
+                .or_default()
+                .push(Box::new(source));
+        }
+        for chain in &self.blockmeta_indexed_chains {
+            let source = BlockmetaProviderForChain::new(
+                chain.id.clone(),
+                chain.url.clone(),
+                &self.blockmeta_auth_token,
+            );
+            sources_by_chain
+                .entry(chain.id.clone())
+                .or_default()
+                .push(Box::new(source));
+        }
+
+        sources_by_chain
+            .into_iter()
+            .map(|(chain_id, mut sources)| {
+                let confirmation = self.block_confirmation(&chain_id);
+                let source: Box<dyn BlockSource> = if sources.len() == 1 {
+                    sources.remove(0)
+                } else {
+                    let policy = self.quorum_policy(&chain_id);
+                    Box::new(QuorumBlockSource::new(chain_id, policy, sources))
+                };
+                match confirmation {
+                    BlockConfirmation::Head => source,
+                    _ => Box::new(ConfirmedBlockSource::new(source, confirmation)),
+                }
+            })
+            .collect()
+    }
+
+    pub fn block_confirmation(&self, chain_id: &Caip2ChainId) -> BlockConfirmation {
+        self.block_confirmations
+            .get(chain_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn provider_count(&self, chain_id: &Caip2ChainId) -> (usize, usize) {
+        let jrpc = self
+            .indexed_chains
+            .iter()
+            .filter(|chain| &chain.id == chain_id)
+            .count();
+        let blockmeta = self
+            .blockmeta_indexed_chains
+            .iter()
+            .filter(|chain| &chain.id == chain_id)
+            .count();
+        (jrpc, blockmeta)
+    }
+
+    pub fn quorum_policy(&self, chain_id: &Caip2ChainId) -> QuorumPolicy {
+        self.quorum_policies
+            .get(chain_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Checks that a local owner key belongs to the owner address, unless `owner_key_check` is
+    /// off, that the protocol chain has an endpoint, and that every quorum policy, block
+    /// confirmation and network alias belongs to a chain with providers.
+    fn validate(&self, owner_key_check: bool) -> anyhow::Result<()> {
+        if let SignerConfig::Local(key) = &self.owner_signer {
+            if owner_key_check {
+                check_owner_key(key, self.owner_address)?;
+            }
+        }
+        if self.protocol_chain.jrpc_urls.is_empty() {
+            anyhow::bail!("The protocol chain has no JSON-RPC endpoints");
+        }
+        let gas_estimate_multiplier = self.transaction_monitoring_options.gas_estimate_multiplier;
+        if gas_estimate_multiplier.is_nan() || gas_estimate_multiplier < 1.0 {
+            anyhow::bail!(
+                "The gas estimate multiplier must be at least 1, but it's {gas_estimate_multiplier}"
+            );
+        }
+        for (chain_id, policy) in &self.quorum_policies {
+            let (jrpc, blockmeta) = self.provider_count(chain_id);
+            let provider_count = jrpc + blockmeta;
+            if provider_count == 0 {
+                anyhow::bail!("There's a quorum policy for {chain_id}, but no providers for it");
+            }
+            if let QuorumPolicy::Agreement { min_agreement } = policy {
+                if *min_agreement == 0 || *min_agreement > provider_count {
+                    anyhow::bail!(
+                        "{chain_id} requires {min_agreement} providers to agree, but it has {provider_count}"
+                    );
+                }
+            }
+        }
+        for (chain_id, confirmation) in &self.block_confirmations {
+            let (jrpc, blockmeta) = self.provider_count(chain_id);
+            if jrpc + blockmeta == 0 {
+                anyhow::bail!(
+                    "There's a block confirmation for {chain_id}, but no providers for it"
+                );
+            }
+            if let Some(tag) = confirmation.tag() {
+                if blockmeta > 0 {
+                    anyhow::bail!(
+                        "{chain_id} has Blockmeta providers, which don't support the '{tag}' block tag"
+                    );
+                }
+            }
+        }
+        if let Some(audit_options) = self.audit_options {
+            if audit_options.interval_in_seconds == 0 {
+                anyhow::bail!("The audit interval must be at least one second");
+            }
+        }
+        for chain_id in self.network_aliases.keys() {
+            let (jrpc, blockmeta) = self.provider_count(chain_id);
+            if jrpc + blockmeta == 0 {
+                anyhow::bail!("There's a network alias for {chain_id}, but no providers for it");
+            }
+        }
+        Ok(())
+    }
+
+    fn from_config_file(config_file: ConfigFile) -> anyhow::Result<Self> {
+        let owner_signer = match (
+            config_file.owner_private_key,
+            config_file.owner_keystore,
+            config_file.remote_signer,
+        ) {
+            (Some(key), None, None) => SignerConfig::Local(key.0),
+            (None, Some(keystore), None) => SignerConfig::Local(keystore.decrypt()?),
+            (None, None, Some(remote_signer)) => SignerConfig::Remote {
+                url: remote_signer.url.0,
+            },
+            _ => anyhow::bail!(
+                "Exactly one of `owner_private_key`, `owner_keystore` or `remote_signer` must be set"
+            ),
+        };
+
+        Ok(Self {
             log_level: config_file.log_level.0,
-            owner_private_key: config_file.owner_private_key.0,
+            owner_signer,
             data_edge_address: config_file.data_edge_address.0,
             epoch_manager_address: config_file.epoch_manager_address.0,
             subgraph_url: config_file.subgraph_url.0,
             bearer_token: config_file.bearer_token.0,
             freshness_threshold: config_file.freshness_threshold,
+            payload_verification_timeout: Duration::from_secs(
+                config_file.payload_verification_timeout_in_seconds,
+            ),
             owner_address: config_file.owner_address.0,
             retry_strategy_max_wait_time: Duration::from_secs(
                 config_file.web3_transport_retry_max_wait_time_in_seconds,
@@ -127,31 +443,55 @@ impl Config {
             indexed_chains: config_file
                 .indexed_chains
                 .into_iter()
-                .map(|(id, provider)| IndexedChain {
-                    id,
-                    jrpc_url: provider.0,
+                .flat_map(|(id, providers)| {
+                    providers
+                        .into_vec()
+                        .into_iter()
+                        .map(move |provider| IndexedChain {
+                            id: id.clone(),
+                            jrpc_url: provider.0,
+                        })
                 })
                 .collect::<Vec<IndexedChain>>(),
             blockmeta_indexed_chains: config_file
                 .blockmeta_indexed_chains
                 .unwrap_or_default()
                 .into_iter()
-                .map(|(id, provider)| BlockmetaIndexedChain {
-                    id,
-                    url: provider.0,
+                .flat_map(|(id, providers)| {
+                    providers
+                        .into_vec()
+                        .into_iter()
+                        .map(move |provider| BlockmetaIndexedChain {
+                            id: id.clone(),
+                            url: provider.0,
+                        })
                 })
                 .collect::<Vec<BlockmetaIndexedChain>>(),
             protocol_chain: ProtocolChain {
                 id: config_file.protocol_chain.name,
-                jrpc_url: config_file.protocol_chain.jrpc.0,
+                jrpc_urls: config_file
+                    .protocol_chain
+                    .jrpc
+                    .into_vec()
+                    .into_iter()
+                    .map(|url| url.0)
+                    .collect(),
                 polling_interval: Duration::from_secs(
                     config_file.protocol_chain.polling_interval_in_seconds,
                 ),
+                endpoint_cooldown: Duration::from_secs(
+                    config_file.protocol_chain.endpoint_cooldown_in_seconds,
+                ),
             },
             metrics_port: config_file.metrics_port,
             transaction_monitoring_options: config_file.transaction_monitoring_options,
             blockmeta_auth_token: config_file.blockmeta_auth_token.0,
-        }
+            audit_options: config_file.audit_options,
+            quorum_policies: config_file.quorum,
+            block_confirmations: config_file.block_confirmations,
+            auto_register: config_file.auto_register,
+            network_aliases: config_file.network_aliases,
+        })
     }
 }
 
@@ -160,7 +500,11 @@ impl Config {
 #[serde(rename_all = "snake_case")]
 struct ConfigFile {
     owner_address: FromStrWrapper<H160>,
-    owner_private_key: EitherLiteralOrEnvVar<SecretKey>,
+    owner_private_key: Option<EitherLiteralOrEnvVar<SecretKey>>,
+    /// Reads the owner's private key from an encrypted JSON keystore instead.
+    owner_keystore: Option<SerdeKeystore>,
+    /// Signs transactions with a remote signer instead of `owner_private_key`.
+    remote_signer: Option<SerdeRemoteSigner>,
     data_edge_address: EitherLiteralOrEnvVar<H160>,
     epoch_manager_address: EitherLiteralOrEnvVar<H160>,
     subgraph_url: EitherLiteralOrEnvVar<Url>,
@@ -170,18 +514,43 @@ struct ConfigFile {
     /// any relevant transaction happened within that treshold.
     #[serde(default = "serde_defaults::freshness_threshold")]
     freshness_threshold: u64,
+    /// How long to wait for the Epoch Subgraph to index a submitted payload before giving up on
+    /// verifying it.
+    #[serde(default = "serde_defaults::payload_verification_timeout_in_seconds")]
+    payload_verification_timeout_in_seconds: u64,
     #[serde(default = "serde_defaults::web3_transport_retry_max_wait_time_in_seconds")]
     web3_transport_retry_max_wait_time_in_seconds: u64,
     #[serde(default = "serde_defaults::log_level")]
     log_level: FromStrWrapper<LevelFilter>,
     protocol_chain: SerdeProtocolChain,
-    indexed_chains: HashMap<Caip2ChainId, EitherLiteralOrEnvVar<Url>>,
-    blockmeta_indexed_chains: Option<HashMap<Caip2ChainId, EitherLiteralOrEnvVar<String>>>,
+    /// One or more JSON-RPC providers per indexed chain.
+    indexed_chains: HashMap<Caip2ChainId, OneOrMany<EitherLiteralOrEnvVar<Url>>>,
+    /// One or more Blockmeta providers per indexed chain.
+    blockmeta_indexed_chains:
+        Option<HashMap<Caip2ChainId, OneOrMany<EitherLiteralOrEnvVar<String>>>>,
+    /// How to pick blocks for indexed chains with several providers.
+    #[serde(default)]
+    quorum: HashMap<Caip2ChainId, QuorumPolicy>,
+    /// Which block to post for indexed chains, as a number of confirmations or a block tag. The
+    /// chain head is posted by default.
+    #[serde(default)]
+    block_confirmations: HashMap<Caip2ChainId, BlockConfirmation>,
+    /// Whether to register indexed chains that the Epoch Subgraph doesn't know, in the same
+    /// transaction as the next epoch's block numbers. Otherwise they are ignored.
+    #[serde(default)]
+    auto_register: bool,
+    /// The aliases that indexed chains are registered with by `auto_register`.
+    #[serde(default)]
+    network_aliases: HashMap<Caip2ChainId, String>,
     #[serde(default = "serde_defaults::metrics_port")]
     metrics_port: u16,
     #[serde(default, rename = "transaction_monitoring")]
     transaction_monitoring_options: TransactionMonitoringOptions,
     blockmeta_auth_token: EitherLiteralOrEnvVar<String>,
+    /// Periodically recomputes the Merkle roots of the latest epochs and reports mismatches. Only
+    /// enabled if present.
+    #[serde(default, rename = "audit")]
+    audit_options: Option<AuditOptions>,
 }
 
 impl ConfigFile {
@@ -192,12 +561,48 @@ impl ConfigFile {
     }
 }
 
+#[derive(Deserialize, Debug)]
+struct SerdeKeystore {
+    path: PathBuf,
+    password: Option<EitherLiteralOrEnvVar<String>>,
+    /// A file that contains the password, e.g. a mounted secret.
+    password_file: Option<PathBuf>,
+}
+
+impl SerdeKeystore {
+    fn decrypt(&self) -> anyhow::Result<SecretKey> {
+        let password = match (&self.password, &self.password_file) {
+            (Some(password), None) => password.0.clone(),
+            (None, Some(path)) => read_to_string(path)
+                .with_context(|| format!("Failed to read the password file {}", path.display()))?
+                .trim_end_matches(['\n', '\r'])
+                .to_owned(),
+            _ => anyhow::bail!(
+                "Exactly one of `password` or `password_file` must be set for the owner keystore"
+            ),
+        };
+        let json = std::fs::read(&self.path).with_context(|| {
+            format!("Failed to read the owner keystore {}", self.path.display())
+        })?;
+        decrypt_keystore(&json, password.as_bytes()).context("Failed to decrypt the owner keystore")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SerdeRemoteSigner {
+    /// The JSON-RPC endpoint of the signer.
+    url: EitherLiteralOrEnvVar<Url>,
+}
+
 #[derive(Deserialize, Debug)]
 struct SerdeProtocolChain {
     name: Caip2ChainId,
-    jrpc: EitherLiteralOrEnvVar<Url>,
+    /// Either a single JSON-RPC endpoint, or a list of them in order of preference.
+    jrpc: OneOrMany<EitherLiteralOrEnvVar<Url>>,
     #[serde(default = "serde_defaults::protocol_chain_polling_interval_in_seconds")]
     polling_interval_in_seconds: u64,
+    #[serde(default = "serde_defaults::protocol_chain_endpoint_cooldown_in_seconds")]
+    endpoint_cooldown_in_seconds: u64,
 }
 
 mod serde_utils {
@@ -227,6 +632,23 @@ mod serde_utils {
         }
     }
 
+    /// Either a single value or a list of them.
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    pub enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    impl<T> OneOrMany<T> {
+        pub fn into_vec(self) -> Vec<T> {
+            match self {
+                Self::One(value) => vec![value],
+                Self::Many(values) => values,
+            }
+        }
+    }
+
     pub struct FromStrWrapper<T>(pub T);
 
     impl<'de, T> Deserialize<'de> for FromStrWrapper<T>
@@ -260,10 +682,18 @@ mod serde_defaults {
         10
     }
 
+    pub fn payload_verification_timeout_in_seconds() -> u64 {
+        600
+    }
+
     pub fn protocol_chain_polling_interval_in_seconds() -> u64 {
         120
     }
 
+    pub fn protocol_chain_endpoint_cooldown_in_seconds() -> u64 {
+        300
+    }
+
     pub fn web3_transport_retry_max_wait_time_in_seconds() -> u64 {
         60
     }
@@ -272,6 +702,14 @@ mod serde_defaults {
         120
     }
 
+    pub fn transaction_monitoring_gas_estimate_multiplier() -> f64 {
+        1.2
+    }
+
+    pub fn transaction_monitoring_priority_fee_percentile() -> f64 {
+        50.0
+    }
+
     pub fn transaction_monitoring_max_retries() -> u32 {
         10
     }
@@ -295,6 +733,14 @@ mod serde_defaults {
     pub fn metrics_port() -> u16 {
         9090
     }
+
+    pub fn audit_epochs() -> u64 {
+        10
+    }
+
+    pub fn audit_interval_in_seconds() -> u64 {
+        3600
+    }
 }
 
 #[cfg(test)]
@@ -340,6 +786,151 @@ mod tests {
         Config::parse(config_file_path("config.sample.toml"));
     }
 
+    #[test]
+    fn multiple_providers_with_quorum_policy() {
+        let config = Config::parse(config_file_path("quorum.toml"));
+        let chain_id: Caip2ChainId = "eip155:100".parse().unwrap();
+
+        let providers: Vec<&str> = config
+            .indexed_chains
+            .iter()
+            .filter(|chain| chain.id == chain_id)
+            .map(|chain| chain.jrpc_url.as_str())
+            .collect();
+        assert_eq!(
+            providers,
+            vec!["https://example.com/a", "https://example.com/b"]
+        );
+        assert_eq!(
+            config.quorum_policy(&chain_id),
+            QuorumPolicy::Agreement { min_agreement: 2 }
+        );
+        assert_eq!(
+            config.quorum_policy(&Caip2ChainId::ethereum_mainnet()),
+            QuorumPolicy::FirstHealthy
+        );
+        assert_eq!(config.block_sources().len(), 2);
+    }
+
+    #[test]
+    fn remote_signer() {
+        let config = Config::parse(config_file_path("remote_signer.toml"));
+        assert!(matches!(
+            config.owner_signer,
+            SignerConfig::Remote { url } if url.as_str() == "http://localhost:9000/"
+        ));
+
+        let config = Config::parse(config_file_path("config.sample.toml"));
+        assert!(matches!(config.owner_signer, SignerConfig::Local(_)));
+    }
+
+    #[test]
+    fn owner_keystore() {
+        let config = Config::parse(config_file_path("owner_keystore.toml"));
+        let SignerConfig::Local(key) = config.owner_signer else {
+            panic!("Expected a local signer");
+        };
+        assert_eq!(SecretKeyRef::new(&key).address(), config.owner_address);
+    }
+
+    #[test]
+    #[should_panic]
+    fn owner_address_mismatch() {
+        Config::parse(config_file_path("owner_address_mismatch.toml"));
+    }
+
+    #[test]
+    fn owner_address_mismatch_is_left_to_preflight() {
+        let config =
+            Config::parse_without_owner_key_check(config_file_path("owner_address_mismatch.toml"));
+        let SignerConfig::Local(key) = &config.owner_signer else {
+            panic!("Expected a local signer");
+        };
+        assert!(check_owner_key(key, config.owner_address).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn conflicting_signers() {
+        Config::parse(config_file_path("conflicting_signers.toml"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn unsatisfiable_quorum_policy() {
+        Config::parse(config_file_path("unsatisfiable_quorum.toml"));
+    }
+
+    #[test]
+    fn protocol_chain_fallback_endpoints() {
+        let config = Config::parse(config_file_path("config.sample.toml"));
+        assert_eq!(config.protocol_chain.jrpc_urls.len(), 1);
+
+        let config = Config::parse(config_file_path("quorum.toml"));
+        let urls: Vec<&str> = config
+            .protocol_chain
+            .jrpc_urls
+            .iter()
+            .map(|url| url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            vec!["https://example.com/", "https://fallback.example.com/"]
+        );
+        assert_eq!(
+            config.protocol_chain.endpoint_cooldown,
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn block_confirmations() {
+        let config = Config::parse(config_file_path("block_confirmations.toml"));
+        let confirmation = |id: &str| config.block_confirmation(&id.parse().unwrap());
+        assert_eq!(confirmation("eip155:1"), BlockConfirmation::Depth(12));
+        assert_eq!(confirmation("eip155:100"), BlockConfirmation::Finalized);
+        assert_eq!(confirmation("eip155:137"), BlockConfirmation::Safe);
+        assert_eq!(confirmation("eip155:250"), BlockConfirmation::Head);
+        assert_eq!(confirmation("bip122:77"), BlockConfirmation::Depth(6));
+    }
+
+    #[test]
+    fn auto_register() {
+        let config = Config::parse(config_file_path("config.sample.toml"));
+        assert!(!config.auto_register);
+        assert!(config.network_aliases.is_empty());
+
+        let config = Config::parse(config_file_path("auto_register.toml"));
+        assert!(config.auto_register);
+        assert_eq!(
+            config.network_aliases.get(&"eip155:100".parse().unwrap()),
+            Some(&"gnosis".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn blockmeta_block_tag() {
+        Config::parse(config_file_path("blockmeta_block_tag.toml"));
+    }
+
+    #[test]
+    fn audit_is_opt_in() {
+        let config = Config::parse(config_file_path("config.sample.toml"));
+        assert!(config.audit_options.is_none());
+
+        let config = Config::parse(config_file_path("audit.toml"));
+        let audit_options = config.audit_options.unwrap();
+        assert_eq!(audit_options.epochs, 5);
+        assert_eq!(audit_options.interval_in_seconds, 3600);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_audit_interval() {
+        Config::parse(config_file_path("zero_audit_interval.toml"));
+    }
+
     #[test]
     fn set_provider_via_env_var() {
         let jrpc_url = "https://sokol-archive.blockscout.com/";
@@ -1,17 +1,17 @@
 use crate::{
     config::TransactionMonitoringOptions,
     metrics::METRICS,
+    signer::Signer,
     transaction_monitor::{TransactionMonitor, TransactionMonitorError},
 };
 use anyhow::Context;
-use secp256k1::SecretKey;
-use tracing::{debug, info, trace};
+use jsonrpc_core::Value;
+use tracing::{debug, info, trace, warn};
 use web3::{
     api::Eth,
     contract::{tokens::Tokenize, Contract},
-    ethabi::Address,
-    signing::SecretKeyRef,
-    types::{TransactionReceipt, U256},
+    ethabi::{decode, Address, ParamType},
+    types::{Bytes, CallRequest, TransactionReceipt, H256, U256},
     Transport, Web3,
 };
 
@@ -26,8 +26,21 @@ pub enum ContractError {
     TransactionMonitor(#[from] TransactionMonitorError),
     #[error(transparent)]
     AbiEncode(#[from] web3::ethabi::Error),
+    #[error("Transaction {0:?} was mined but reverted")]
+    Reverted(H256),
+    #[error("The simulation of the transaction failed: {0}")]
+    Simulation(String),
+    #[error("Failed to simulate the transaction: {0}")]
+    SimulationProvider(web3::Error),
+    #[error(
+        "The transaction needs an estimated {estimate} gas, above the gas estimate cap of {cap}"
+    )]
+    GasEstimateAboveCap { estimate: U256, cap: u64 },
 }
 
+/// The selector of Solidity's `Error(string)`, which wraps revert reasons.
+const REVERT_REASON_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
 pub struct Contracts<T>
 where
     T: Clone + Transport,
@@ -36,6 +49,7 @@ where
     data_edge: Contract<T>,
     epoch_manager: Contract<T>,
     transaction_monitoring_options: TransactionMonitoringOptions,
+    signer: Box<dyn Signer>,
 }
 
 impl<T> Contracts<T>
@@ -47,6 +61,7 @@ where
         data_edge_address: Address,
         epoch_manager_address: Address,
         transaction_monitoring_options: TransactionMonitoringOptions,
+        signer: Box<dyn Signer>,
     ) -> anyhow::Result<Self> {
         let data_edge = Contracts::new_contract(DATA_EDGE_ABI, &client.eth(), data_edge_address)?;
         let epoch_manager =
@@ -56,6 +71,7 @@ where
             data_edge,
             epoch_manager,
             transaction_monitoring_options,
+            signer,
         })
     }
 
@@ -82,31 +98,110 @@ where
         Ok(current_epoch)
     }
 
-    pub async fn submit_call(
-        &self,
-        payload: Vec<u8>,
-        owner_private_key: &SecretKey,
-    ) -> Result<TransactionReceipt, ContractError> {
+    pub async fn submit_call(&self, payload: Vec<u8>) -> Result<TransactionReceipt, ContractError> {
         info!("Sending transaction to DataEdge");
 
         let transaction_receipt: TransactionReceipt = {
-            let calldata: web3::types::Bytes =
-                self.abi_encode_data_edge_payload((payload,))?.into();
+            let calldata: Bytes = self.abi_encode_data_edge_payload((payload,))?.into();
+            let from = self.signer.address();
+            self.simulate_call(from, calldata.clone()).await?;
+            let gas = self.estimate_gas(from, calldata.clone()).await?;
 
             let transaction_monitor = TransactionMonitor::new(
                 self.client.clone(),
-                SecretKeyRef::new(owner_private_key),
+                &*self.signer,
                 self.data_edge.address(),
                 calldata,
-                self.transaction_monitoring_options,
+                gas,
+                self.transaction_monitoring_options.clone(),
             )
             .await?;
             transaction_monitor.execute_transaction().await?
         };
 
+        if transaction_receipt.status == Some(0.into()) {
+            METRICS.track_rejected_payload("reverted");
+            return Err(ContractError::Reverted(
+                transaction_receipt.transaction_hash,
+            ));
+        }
         Ok(transaction_receipt)
     }
 
+    /// Simulates a DataEdge call with an empty payload from the owner, which fails if the DataEdge
+    /// doesn't accept the owner's calls.
+    pub async fn simulate_owner_call(&self) -> Result<(), ContractError> {
+        let calldata = self
+            .abi_encode_data_edge_payload((Vec::<u8>::new(),))?
+            .into();
+        self.simulate_call(self.signer.address(), calldata).await
+    }
+
+    /// Runs a DataEdge call with `eth_call`, so that calls that would revert on-chain, e.g.
+    /// because of a wrong contract address or owner key, are never broadcast.
+    async fn simulate_call(&self, from: Address, calldata: Bytes) -> Result<(), ContractError> {
+        let data_edge_address = self.data_edge.address();
+        let code = self
+            .client
+            .eth()
+            .code(data_edge_address, None)
+            .await
+            .map_err(ContractError::SimulationProvider)?;
+        if code.0.is_empty() {
+            return Err(ContractError::Simulation(format!(
+                "there's no contract at the DataEdge address {data_edge_address:?}"
+            )));
+        }
+
+        let request = CallRequest {
+            from: Some(from),
+            to: Some(data_edge_address),
+            data: Some(calldata),
+            ..Default::default()
+        };
+        match self.client.eth().call(request, None).await {
+            Ok(_) => {
+                trace!("Simulated the DataEdge call successfully");
+                Ok(())
+            }
+            Err(web3::Error::Rpc(error)) if is_revert(&error) => {
+                Err(ContractError::Simulation(revert_reason(&error)))
+            }
+            Err(error) => Err(ContractError::SimulationProvider(error)),
+        }
+    }
+
+    /// Estimates the gas that a DataEdge call needs, with some margin. Falls back to the configured
+    /// gas limit if the estimation fails, and fails if the estimate exceeds the cap.
+    async fn estimate_gas(&self, from: Address, calldata: Bytes) -> Result<U256, ContractError> {
+        let options = &self.transaction_monitoring_options;
+        let request = CallRequest {
+            from: Some(from),
+            to: Some(self.data_edge.address()),
+            data: Some(calldata),
+            ..Default::default()
+        };
+        match self.client.eth().estimate_gas(request, None).await {
+            Ok(estimate) => {
+                let gas = gas_with_margin(
+                    estimate,
+                    options.gas_estimate_multiplier,
+                    options.gas_estimate_cap,
+                )?;
+                debug!(%estimate, %gas, "Estimated gas for the DataEdge call");
+                Ok(gas)
+            }
+            Err(error) => {
+                warn!(
+                    %error,
+                    gas_limit = options.gas_limit,
+                    "Failed to estimate gas for the DataEdge call, using the configured gas limit"
+                );
+                Ok(options.gas_limit.into())
+            }
+        }
+    }
+
     fn abi_encode_data_edge_payload(
         &self,
         params: impl Tokenize,
@@ -117,3 +212,116 @@ where
             .and_then(|function| function.encode_input(&params.into_tokens()))
     }
 }
+
+fn is_revert(error: &jsonrpc_core::Error) -> bool {
+    // Geth uses code 3 for reverts with data, but other clients only say so in the message.
+    error.code.code() == 3 || error.message.to_lowercase().contains("revert")
+}
+
+/// Decodes the revert reason from the data of a JSON-RPC error, falling back to its message.
+fn revert_reason(error: &jsonrpc_core::Error) -> String {
+    // Most clients return the revert data as a string, but some nest it in an object.
+    let data = match &error.data {
+        Some(Value::String(data)) => Some(data.as_str()),
+        Some(Value::Object(object)) => object.get("data").and_then(Value::as_str),
+        _ => None,
+    };
+    let reason = data
+        .and_then(|data| hex::decode(data.trim_start_matches("0x")).ok())
+        .filter(|data| data.starts_with(&REVERT_REASON_SELECTOR))
+        .and_then(|data| decode(&[ParamType::String], &data[4..]).ok())
+        .and_then(|tokens| tokens.into_iter().next()?.into_string());
+    match reason {
+        Some(reason) => format!("execution reverted: {reason}"),
+        None => error.message.clone(),
+    }
+}
+
+/// Applies the configured multiplier and cap to a gas estimate. An estimate above the cap fails,
+/// since a transaction with less gas than that would run out of it.
+fn gas_with_margin(
+    estimate: U256,
+    multiplier: f64,
+    cap: Option<u64>,
+) -> Result<U256, ContractError> {
+    if let Some(cap) = cap {
+        if estimate > cap.into() {
+            return Err(ContractError::GasEstimateAboveCap { estimate, cap });
+        }
+    }
+    let estimate = estimate.min(u64::MAX.into()).as_u64();
+    let gas = (estimate as f64 * multiplier).ceil() as u64;
+    Ok(cap.map_or(gas, |cap| gas.min(cap)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::ethabi::Token;
+
+    fn rpc_error(message: &str, data: Option<Value>) -> jsonrpc_core::Error {
+        jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(3),
+            message: message.to_owned(),
+            data,
+        }
+    }
+
+    #[test]
+    fn revert_reasons() {
+        let mut data = REVERT_REASON_SELECTOR.to_vec();
+        data.extend(web3::ethabi::encode(&[Token::String(
+            "Ownable: caller is not the owner".to_owned(),
+        )]));
+        let data = format!("0x{}", hex::encode(data));
+        let expected = "execution reverted: Ownable: caller is not the owner";
+
+        let error = rpc_error("execution reverted", Some(Value::String(data.clone())));
+        assert!(is_revert(&error));
+        assert_eq!(revert_reason(&error), expected);
+
+        let nested = serde_json::json!({ "data": data });
+        let error = rpc_error("execution reverted", Some(nested));
+        assert_eq!(revert_reason(&error), expected);
+
+        let error = rpc_error("execution reverted", Some(Value::String("0x".to_owned())));
+        assert_eq!(revert_reason(&error), "execution reverted");
+
+        let error = jsonrpc_core::Error::internal_error();
+        assert!(!is_revert(&error));
+    }
+
+    #[test]
+    fn gas_estimate_margin() {
+        assert_eq!(
+            gas_with_margin(100_000.into(), 1.5, None).unwrap(),
+            150_000.into()
+        );
+        assert_eq!(
+            gas_with_margin(100_001.into(), 1.2, None).unwrap(),
+            120_002.into()
+        );
+        assert_eq!(
+            gas_with_margin(100_000.into(), 1.5, Some(120_000)).unwrap(),
+            120_000.into()
+        );
+        assert_eq!(
+            gas_with_margin(120_000.into(), 1.5, Some(120_000)).unwrap(),
+            120_000.into()
+        );
+        assert_eq!(
+            gas_with_margin(U256::MAX, 1.0, None).unwrap(),
+            u64::MAX.into()
+        );
+    }
+
+    #[test]
+    fn gas_estimate_above_cap() {
+        let error = gas_with_margin(120_001.into(), 1.2, Some(120_000)).unwrap_err();
+        assert!(matches!(
+            error,
+            ContractError::GasEstimateAboveCap { cap: 120_000, .. }
+        ));
+        assert!(error.to_string().contains("120001"), "{error}");
+    }
+}
@@ -0,0 +1,179 @@
+//! Decryption of Ethereum JSON keystores (version 3), as written by Geth and most wallets.
+
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::{pbkdf2_hmac, scrypt};
+use openssl::symm::{decrypt, Cipher};
+use secp256k1::SecretKey;
+use serde::{Deserialize, Deserializer};
+use web3::signing::keccak256;
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeystoreError {
+    #[error("bad keystore JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported keystore {0}")]
+    Unsupported(String),
+    #[error("wrong keystore password")]
+    WrongPassword,
+    #[error("failed to decrypt the keystore: {0}")]
+    Crypto(#[from] openssl::error::ErrorStack),
+    #[error("the keystore doesn't contain a valid private key")]
+    InvalidKey,
+}
+
+#[derive(Deserialize)]
+struct Keystore {
+    version: u64,
+    // Some tools capitalize this field.
+    #[serde(alias = "Crypto")]
+    crypto: Crypto,
+}
+
+#[derive(Deserialize)]
+struct Crypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    #[serde(deserialize_with = "hex_bytes")]
+    ciphertext: Vec<u8>,
+    #[serde(flatten)]
+    kdf: Kdf,
+    #[serde(deserialize_with = "hex_bytes")]
+    mac: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct CipherParams {
+    #[serde(deserialize_with = "hex_bytes")]
+    iv: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kdf", content = "kdfparams", rename_all = "lowercase")]
+enum Kdf {
+    Scrypt {
+        dklen: usize,
+        n: u64,
+        r: u64,
+        p: u64,
+        #[serde(deserialize_with = "hex_bytes")]
+        salt: Vec<u8>,
+    },
+    Pbkdf2 {
+        dklen: usize,
+        c: usize,
+        prf: String,
+        #[serde(deserialize_with = "hex_bytes")]
+        salt: Vec<u8>,
+    },
+}
+
+impl Kdf {
+    fn derive_key(&self, password: &[u8]) -> Result<Vec<u8>, KeystoreError> {
+        match self {
+            Kdf::Scrypt {
+                dklen,
+                n,
+                r,
+                p,
+                salt,
+            } => {
+                let mut key = vec![0; *dklen];
+                // Geth's standard parameters need 256 MiB, way above OpenSSL's default limit, so
+                // the limit is raised to what the parameters require.
+                let max_memory = 128u64
+                    .saturating_mul(*r)
+                    .saturating_mul(n.saturating_add(*p).saturating_add(2));
+                scrypt(password, salt, *n, *r, *p, max_memory, &mut key)?;
+                Ok(key)
+            }
+            Kdf::Pbkdf2 {
+                dklen,
+                c,
+                prf,
+                salt,
+            } => {
+                if prf != "hmac-sha256" {
+                    return Err(KeystoreError::Unsupported(format!("PRF '{prf}'")));
+                }
+                let mut key = vec![0; *dklen];
+                pbkdf2_hmac(password, salt, *c, MessageDigest::sha256(), &mut key)?;
+                Ok(key)
+            }
+        }
+    }
+}
+
+fn hex_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    hex::decode(string.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+}
+
+/// Decrypts the private key in a JSON keystore with its password.
+pub fn decrypt_keystore(json: &[u8], password: &[u8]) -> Result<SecretKey, KeystoreError> {
+    let keystore: Keystore = serde_json::from_slice(json)?;
+    if keystore.version != 3 {
+        return Err(KeystoreError::Unsupported(format!(
+            "version {}",
+            keystore.version
+        )));
+    }
+    let crypto = keystore.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(KeystoreError::Unsupported(format!(
+            "cipher '{}'",
+            crypto.cipher
+        )));
+    }
+
+    let derived_key = crypto.kdf.derive_key(password)?;
+    if derived_key.len() < 32 {
+        return Err(KeystoreError::Unsupported(
+            "key derivation output shorter than 32 bytes".to_string(),
+        ));
+    }
+    let mac = keccak256(&[&derived_key[16..32], &crypto.ciphertext[..]].concat());
+    if mac[..] != crypto.mac[..] {
+        return Err(KeystoreError::WrongPassword);
+    }
+
+    let private_key = decrypt(
+        Cipher::aes_128_ctr(),
+        &derived_key[..16],
+        Some(&crypto.cipherparams.iv),
+        &crypto.ciphertext,
+    )?;
+    SecretKey::from_slice(&private_key).map_err(|_| KeystoreError::InvalidKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The key and password of the Web3 Secret Storage Definition test vectors. The scrypt keystore
+    // uses cheaper parameters than the specification's, which OpenSSL rejects for having `r = 1`.
+    const PASSWORD: &[u8] = b"testpassword";
+    const PRIVATE_KEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    fn keystore_file(filename: &str) -> Vec<u8> {
+        let path = format!("{}/test/keystore/{}", env!("CARGO_MANIFEST_DIR"), filename);
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn pbkdf2_keystore() {
+        let key = decrypt_keystore(&keystore_file("pbkdf2.json"), PASSWORD).unwrap();
+        assert_eq!(hex::encode(key.as_ref()), PRIVATE_KEY);
+    }
+
+    #[test]
+    fn scrypt_keystore() {
+        let key = decrypt_keystore(&keystore_file("scrypt.json"), PASSWORD).unwrap();
+        assert_eq!(hex::encode(key.as_ref()), PRIVATE_KEY);
+    }
+
+    #[test]
+    fn wrong_password() {
+        let result = decrypt_keystore(&keystore_file("pbkdf2.json"), b"wrongpassword");
+        assert!(matches!(result, Err(KeystoreError::WrongPassword)));
+    }
+}
@@ -1,13 +1,17 @@
+pub mod block_source;
 pub mod chain_validation;
 pub mod commands;
 pub mod config;
 pub mod contracts;
+pub mod keystore;
 pub mod metrics;
 pub mod models;
+pub mod preflight;
 pub mod runner;
+pub mod signer;
 pub mod subgraph;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use json_oracle_encoder::{print_encoded_json_messages, OutputKind};
 use std::path::PathBuf;
 
@@ -37,6 +41,35 @@ async fn main() -> anyhow::Result<()> {
             };
             print_encoded_json_messages(output_kind, json)?;
         }
+        Clap::Decode {
+            input,
+            tx_hash,
+            config_file,
+            network_count,
+            encoding_version,
+        } => {
+            let config = config_file.map(Config::parse);
+            commands::decode(config, input, tx_hash, network_count, encoding_version).await?;
+        }
+        Clap::Prove {
+            config_file,
+            epoch,
+            chain_id,
+        } => {
+            let config = Config::parse(config_file);
+            commands::prove(config, epoch, chain_id).await?;
+        }
+        Clap::Audit {
+            config_file,
+            epochs,
+        } => {
+            let config = Config::parse(config_file);
+            commands::audit(config, epochs).await?;
+        }
+        Clap::CheckConfig { config_file } => {
+            let config = Config::parse_without_owner_key_check(config_file);
+            commands::check_config(config).await?;
+        }
         Clap::CurrentEpoch { config_file } => {
             let config = Config::parse(config_file);
             commands::print_current_epoch(config).await?;
@@ -59,6 +92,36 @@ async fn main() -> anyhow::Result<()> {
             let config = Config::parse(config_file);
             commands::correct_last_epoch(config, chain_id, block_number, dry_run, yes).await?;
         }
+        Clap::CorrectEpoch {
+            config_file,
+            epoch,
+            corrections,
+            dry_run,
+            yes,
+        } => {
+            let config = Config::parse(config_file);
+            commands::correct_epoch(config, epoch, corrections, dry_run, yes).await?;
+        }
+        Clap::Networks { command } => match command {
+            NetworksCommand::Add {
+                config_file,
+                networks,
+                dry_run,
+                yes,
+            } => {
+                let config = Config::parse(config_file);
+                commands::add_networks(config, networks, dry_run, yes).await?;
+            }
+            NetworksCommand::Remove {
+                config_file,
+                chain_ids,
+                dry_run,
+                yes,
+            } => {
+                let config = Config::parse(config_file);
+                commands::remove_networks(config, chain_ids, dry_run, yes).await?;
+            }
+        },
     }
 
     Ok(())
@@ -83,6 +146,57 @@ enum Clap {
         #[clap(short, long, action)]
         calldata: bool,
     },
+    /// Decode a payload back into the JSON messages accepted by `encode`.
+    Decode {
+        /// A hex-encoded payload, the full calldata of a `crossChainEpochOracle` call, or with
+        /// `--tx-hash`, the hash of such a transaction on the protocol chain.
+        input: String,
+        /// Treat the input as the hash of a transaction on the protocol chain.
+        #[clap(long)]
+        tx_hash: bool,
+        /// The path of the TOML configuration file. Required for transaction hashes, and used to
+        /// query the subgraph for the number of registered networks and the encoding version:
+        /// right before the transaction for transaction hashes, and currently otherwise.
+        #[clap(short, long)]
+        config_file: Option<PathBuf>,
+        /// The number of networks registered before the payload, which is needed to decode block
+        /// number updates.
+        #[clap(short, long)]
+        network_count: Option<u64>,
+        /// The encoding version before the payload, which tells whether block number updates carry
+        /// an epoch number. Defaults to the subgraph's version, or 0 without a configuration file.
+        #[clap(short, long)]
+        encoding_version: Option<u64>,
+    },
+    /// Print a Merkle proof of a network's block number in an epoch, as JSON.
+    Prove {
+        /// The path of the TOML configuration file.
+        #[clap(short, long)]
+        config_file: PathBuf,
+        /// The epoch number.
+        #[clap(short, long)]
+        epoch: u64,
+        /// The CAIP-2 chain ID of the network (e.g. "eip155:42161")
+        #[clap(short = 'n', long)]
+        chain_id: Caip2ChainId,
+    },
+    /// Recompute the Merkle roots of the latest epochs by decoding the payloads that posted them,
+    /// and looking up the canonical hashes of their blocks. Reports any that don't match.
+    Audit {
+        /// The path of the TOML configuration file.
+        #[clap(short, long)]
+        config_file: PathBuf,
+        /// How many of the latest epochs to audit.
+        #[clap(short, long, default_value = "10")]
+        epochs: u64,
+    },
+    /// Check that the owner key, the owner address, the contracts and the JSON-RPC endpoints of the
+    /// configuration agree with each other.
+    CheckConfig {
+        /// The path of the TOML configuration file.
+        #[clap(short, long)]
+        config_file: PathBuf,
+    },
     /// Query the Epoch Manager for the current epoch.
     CurrentEpoch {
         /// The path of the TOML configuration file.
@@ -114,4 +228,70 @@ enum Clap {
         #[clap(short, long)]
         yes: bool,
     },
+    /// Correct the block numbers of some networks in a past epoch, updating all later epochs.
+    /// Until the subgraph handles the correction, only `--dry-run` is accepted.
+    CorrectEpoch {
+        /// The path of the TOML configuration file.
+        #[clap(short, long)]
+        config_file: PathBuf,
+        /// The number of the epoch to correct. Epoch 0 can't be corrected.
+        #[clap(short, long)]
+        epoch: u64,
+        /// A corrected block number, as `<CAIP-2 chain ID>=<block number>` (e.g.
+        /// "eip155:42161=12345678"). Can be repeated to correct several networks.
+        #[clap(
+            short = 's',
+            long = "set",
+            required = true,
+            parse(try_from_str = commands::correct_epoch::parse_correction)
+        )]
+        corrections: Vec<(Caip2ChainId, u64)>,
+        /// Show what would be done without sending the transaction
+        #[clap(long)]
+        dry_run: bool,
+        /// Skip confirmation prompt
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Register or unregister networks, based on the networks that the subgraph knows about.
+    Networks {
+        #[clap(subcommand)]
+        command: NetworksCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum NetworksCommand {
+    /// Register new networks.
+    Add {
+        /// The path of the TOML configuration file.
+        #[clap(short, long)]
+        config_file: PathBuf,
+        /// The CAIP-2 chain IDs of the networks to register, optionally followed by an alias as
+        /// `<CAIP-2 chain ID>=<alias>` (e.g. "eip155:100=gnosis"). Either all networks or none of
+        /// them must have an alias.
+        #[clap(required = true, parse(try_from_str = commands::networks::parse_network))]
+        networks: Vec<(Caip2ChainId, Option<String>)>,
+        /// Show what would be done without sending the transaction
+        #[clap(long)]
+        dry_run: bool,
+        /// Skip confirmation prompt
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Unregister networks.
+    Remove {
+        /// The path of the TOML configuration file.
+        #[clap(short, long)]
+        config_file: PathBuf,
+        /// The CAIP-2 chain IDs of the networks to unregister.
+        #[clap(required = true)]
+        chain_ids: Vec<Caip2ChainId>,
+        /// Show what would be done without sending the transaction
+        #[clap(long)]
+        dry_run: bool,
+        /// Skip confirmation prompt
+        #[clap(short, long)]
+        yes: bool,
+    },
 }
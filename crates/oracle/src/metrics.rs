@@ -27,6 +27,11 @@ pub struct Metrics {
     subgraph_indexing_errors: IntGauge,
     subgraph_last_payload_health: IntGauge,
     subgraph_last_payload_block_number: IntGauge,
+    rejected_payload: IntCounterVec,
+    audit_merkle_root_mismatch: IntGaugeVec,
+    block_source_disagreement: IntCounterVec,
+    jrpc_protocol_chain_endpoint_failure: IntCounterVec,
+    protocol_chain_endpoint_health: IntGaugeVec,
 }
 
 impl Metrics {
@@ -105,6 +110,41 @@ impl Metrics {
             registry
         )?;
 
+        let rejected_payload = register_int_counter_vec_with_registry!(
+            "epoch_block_oracle_rejected_payload_total",
+            "Submitted Payloads Rejected By The Protocol Chain Or The Epoch Subgraph",
+            &["reason"],
+            registry
+        )?;
+
+        let audit_merkle_root_mismatch = register_int_gauge_vec_with_registry!(
+            "epoch_block_oracle_audit_merkle_root_mismatch",
+            "Latest Audited Epoch Merkle Root Mismatch",
+            &["network"],
+            registry
+        )?;
+
+        let block_source_disagreement = register_int_counter_vec_with_registry!(
+            "epoch_block_oracle_block_source_disagreement_total",
+            "Block Providers Disagreement On Block Hash",
+            &["network"],
+            registry
+        )?;
+
+        let jrpc_protocol_chain_endpoint_failure = register_int_counter_vec_with_registry!(
+            "epoch_block_oracle_jrpc_protocol_chain_endpoint_failure_total",
+            "JSON RPC Protocol Chain Endpoint Failure",
+            &["network", "endpoint"],
+            registry
+        )?;
+
+        let protocol_chain_endpoint_health = register_int_gauge_vec_with_registry!(
+            "epoch_block_oracle_protocol_chain_endpoint_health",
+            "Protocol Chain Endpoint Health",
+            &["network", "endpoint"],
+            registry
+        )?;
+
         Ok(Self {
             registry,
             jrpc_request_duration_seconds,
@@ -118,6 +158,11 @@ impl Metrics {
             subgraph_indexing_errors,
             subgraph_last_payload_health,
             subgraph_last_payload_block_number,
+            rejected_payload,
+            audit_merkle_root_mismatch,
+            block_source_disagreement,
+            jrpc_protocol_chain_endpoint_failure,
+            protocol_chain_endpoint_health,
         })
     }
 
@@ -174,6 +219,29 @@ impl Metrics {
         self.subgraph_last_payload_block_number.set(block_number)
     }
 
+    /// Counts a submitted payload that was rejected, either because its transaction `"reverted"`
+    /// or because the Epoch Subgraph considered it `"invalid"`.
+    pub fn track_rejected_payload(&self, reason: &str) {
+        self.rejected_payload
+            .get_metric_with_label_values(&[reason])
+            .unwrap()
+            .inc();
+    }
+
+    pub fn set_audit_merkle_root_mismatch(&self, network: &str, mismatch: bool) {
+        self.audit_merkle_root_mismatch
+            .get_metric_with_label_values(&[network])
+            .unwrap()
+            .set(mismatch as i64)
+    }
+
+    pub fn track_block_source_disagreement(&self, network: &str) {
+        self.block_source_disagreement
+            .get_metric_with_label_values(&[network])
+            .unwrap()
+            .inc();
+    }
+
     pub fn track_jrpc_failure(&self, network: &str) {
         self.jrpc_failure
             .get_metric_with_label_values(&[network])
@@ -188,6 +256,20 @@ impl Metrics {
             .inc();
     }
 
+    pub fn track_jrpc_protocol_chain_endpoint_failure(&self, network: &str, endpoint: &str) {
+        self.jrpc_protocol_chain_endpoint_failure
+            .get_metric_with_label_values(&[network, endpoint])
+            .unwrap()
+            .inc();
+    }
+
+    pub fn set_protocol_chain_endpoint_health(&self, network: &str, endpoint: &str, healthy: bool) {
+        self.protocol_chain_endpoint_health
+            .get_metric_with_label_values(&[network, endpoint])
+            .unwrap()
+            .set(healthy as i64)
+    }
+
     pub fn track_jrpc_indexed_chain_failure(&self, network: &str) {
         self.jrpc_indexed_chain_failure
             .get_metric_with_label_values(&[network])
@@ -3,6 +3,7 @@ use serde_with::DeserializeFromStr;
 use std::{fmt::Display, str::FromStr};
 use tonic::codegen::InterceptedService;
 use tonic::transport::{Channel, Uri};
+use url::Url;
 use web3::Web3;
 
 #[derive(Clone, Debug)]
@@ -12,6 +13,9 @@ where
 {
     pub chain_id: Caip2ChainId,
     pub web3: Web3<T>,
+    /// The endpoint behind the transport, if there's a single one, which tells providers of the
+    /// same chain apart in errors.
+    pub rpc_url: Option<Url>,
 }
 
 impl<T> JrpcProviderForChain<T>
@@ -22,8 +26,14 @@ where
         Self {
             chain_id,
             web3: Web3::new(transport),
+            rpc_url: None,
         }
     }
+
+    pub fn with_rpc_url(mut self, rpc_url: Url) -> Self {
+        self.rpc_url = Some(rpc_url);
+        self
+    }
 }
 
 #[derive(Clone, Debug)]
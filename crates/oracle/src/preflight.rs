@@ -0,0 +1,175 @@
+//! Checks that the owner key, the owner address and the contracts of the configuration agree with
+//! each other and with the chains, before the oracle sends anything.
+
+use crate::chain_validation::validate_chain_ids;
+use crate::commands::{init_contracts, protocol_chain};
+use crate::config::{check_owner_key, SignerConfig};
+use crate::Config;
+use anyhow::{bail, Context};
+use std::fmt;
+use web3::{transports::Http, types::Address, Transport, Web3};
+
+/// The outcome of a single preflight check.
+pub struct Check {
+    pub name: &'static str,
+    /// What the check found, or why it failed.
+    pub result: anyhow::Result<String>,
+}
+
+pub struct PreflightReport {
+    pub checks: Vec<Check>,
+}
+
+impl PreflightReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.result {
+                Ok(details) => writeln!(f, "✓ {}: {details}", check.name)?,
+                Err(err) => writeln!(f, "✗ {}: {err:#}", check.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs every preflight check. Checks don't stop at the first failure, so that the report covers
+/// the whole configuration.
+pub async fn preflight(config: &Config) -> PreflightReport {
+    let client = protocol_chain(config).web3;
+    let checks = vec![
+        Check {
+            name: "Owner key",
+            result: owner_key(config).await,
+        },
+        Check {
+            name: "Chain IDs",
+            result: validate_chain_ids(config)
+                .await
+                .map(|()| "every JSON-RPC endpoint serves its configured chain".to_string()),
+        },
+        Check {
+            name: "DataEdge contract",
+            result: contract_code(&client, config.data_edge_address).await,
+        },
+        Check {
+            name: "EpochManager contract",
+            result: contract_code(&client, config.epoch_manager_address).await,
+        },
+        Check {
+            name: "Current epoch",
+            result: current_epoch(config).await,
+        },
+        Check {
+            name: "DataEdge owner",
+            result: owner_call(config).await,
+        },
+    ];
+    PreflightReport { checks }
+}
+
+/// Checks that the owner's transactions will be signed for the owner address.
+async fn owner_key(config: &Config) -> anyhow::Result<String> {
+    match &config.owner_signer {
+        SignerConfig::Local(key) => {
+            let key_address = check_owner_key(key, config.owner_address)?;
+            Ok(format!(
+                "the private key belongs to the owner address {key_address:?}"
+            ))
+        }
+        SignerConfig::Remote { url } => {
+            let signer = Web3::new(Http::with_client(reqwest::Client::new(), url.clone()));
+            let accounts =
+                signer.eth().accounts().await.with_context(|| {
+                    format!("failed to list the accounts of the signer at {url}")
+                })?;
+            if !accounts.contains(&config.owner_address) {
+                bail!(
+                    "the signer at {url} doesn't hold the owner address {:?}",
+                    config.owner_address
+                );
+            }
+            Ok(format!(
+                "the signer at {url} holds the owner address {:?}",
+                config.owner_address
+            ))
+        }
+    }
+}
+
+async fn contract_code<T: Transport>(client: &Web3<T>, address: Address) -> anyhow::Result<String> {
+    let code = client
+        .eth()
+        .code(address, None)
+        .await
+        .context("failed to get the contract bytecode")?;
+    if code.0.is_empty() {
+        bail!("there's no contract at {address:?}");
+    }
+    Ok(format!("{} bytes of bytecode at {address:?}", code.0.len()))
+}
+
+async fn current_epoch(config: &Config) -> anyhow::Result<String> {
+    let contracts = init_contracts(config.clone())?;
+    let epoch = contracts.query_current_epoch().await?;
+    Ok(format!("the EpochManager is at epoch {epoch}"))
+}
+
+async fn owner_call(config: &Config) -> anyhow::Result<String> {
+    let contracts = init_contracts(config.clone())?;
+    contracts.simulate_owner_call().await?;
+    Ok(format!(
+        "the DataEdge accepts calls from {:?}",
+        config.owner_address
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::parse(format!(
+            "{}/test/config/config.sample.toml",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+    }
+
+    #[tokio::test]
+    async fn owner_key_matches_owner_address() {
+        let mut config = config();
+        let report = owner_key(&config).await.unwrap();
+        assert!(report.contains("0xe8a7"));
+
+        config.owner_address = Address::repeat_byte(1);
+        let err = owner_key(&config).await.unwrap_err();
+        assert!(err.to_string().contains("not to the owner address"));
+    }
+
+    #[test]
+    fn report() {
+        let report = PreflightReport {
+            checks: vec![
+                Check {
+                    name: "Current epoch",
+                    result: Ok("the EpochManager is at epoch 7".to_string()),
+                },
+                Check {
+                    name: "DataEdge contract",
+                    result: Err(anyhow::anyhow!("there's no contract at 0x00")),
+                },
+            ],
+        };
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_string(),
+            "✓ Current epoch: the EpochManager is at epoch 7\n\
+             ✗ DataEdge contract: there's no contract at 0x00\n"
+        );
+    }
+}
@@ -1,20 +1,16 @@
 use super::METRICS;
-use crate::{Caip2ChainId, JrpcProviderForChain};
+use crate::Caip2ChainId;
 use backoff::{future::retry, ExponentialBackoff, ExponentialBackoffBuilder};
 use epoch_encoding::BlockPtr;
 use futures::{future::try_join_all, TryFutureExt};
-use futures::{
-    stream::{FuturesUnordered, StreamExt},
-    FutureExt,
-};
 use jsonrpc_core::{Call, Value};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
 use std::ops::RangeInclusive;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use std::{future::Future, pin::Pin, time::Duration};
-use tracing::trace;
+use tracing::{trace, warn};
 use url::Url;
 use web3::helpers::CallFuture;
 use web3::types::{BlockNumber, Transaction, H160, H256, U64};
@@ -83,60 +79,181 @@ where
     }
 }
 
-/// It'd pretty weird if a provider wouldn't respond with a valid latest block; in that case,
-/// we'll raise a [`web3::Error`].
-///
-/// Note: Hardhat and other test setups might force us to rethink this and return an [`Option`].
-pub async fn get_latest_block<T>(web3: Web3<T>) -> web3::Result<BlockPtr>
+/// A subset of [`web3::types::Block`] that is compatible with Celo. Should only be used for mined
+/// blocks, i.e. with a block number. You can add fields as necessary, but you MUST make sure
+/// they're widely available across all supported indexed chains.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+struct BlockCeloCompatible {
+    hash: H256,
+    number: U64,
+}
+
+/// Fetches a block by number or by tag, e.g. `latest` or `finalized`.
+async fn get_block<T>(web3: &Web3<T>, block_num: Value) -> web3::Result<Option<BlockPtr>>
 where
     T: Transport,
 {
-    /// A subset of [`web3::types::Block`] that is compatible with Celo. Should only be used for mined
-    /// blocks, i.e. with a block number. You can add fields as necessary, but you MUST make sure
-    /// they're widely available across all supported indexed chains.
-    #[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
-    struct LatestBlockCeloCompatible {
-        hash: H256,
-        number: U64,
-    }
-
-    // We're asking for the chain head.
-    let block_num = web3::helpers::serialize(&BlockNumber::Latest);
     // We don't care about the transactions in the block.
     let include_txs = web3::helpers::serialize(&false);
 
     let fut = web3
         .transport()
         .execute("eth_getBlockByNumber", vec![block_num, include_txs]);
-    let call_fut: CallFuture<LatestBlockCeloCompatible, T::Out> = CallFuture::new(fut);
-    let latest_block = call_fut.await?;
+    let call_fut: CallFuture<Option<BlockCeloCompatible>, T::Out> = CallFuture::new(fut);
+    let block = call_fut.await?;
+
+    Ok(block.map(|block| BlockPtr {
+        number: block.number.as_u64(),
+        hash: block.hash.0,
+    }))
+}
+
+/// A [`web3::Transport`] over an ordered list of endpoints for the same chain.
+///
+/// Calls go to the first healthy endpoint, and fail over to the next one if it fails. A failed
+/// endpoint is skipped for `cooldown` and then tried again, so that calls go back to the primary
+/// endpoint once it recovers. Endpoints are labelled by their position in metrics, as their URLs
+/// may contain API keys.
+#[derive(Debug, Clone)]
+pub struct JrpcFallback<T = JrpcExpBackoff> {
+    endpoints: Arc<Vec<T>>,
+    /// When each endpoint last failed, if it's cooling off.
+    failed_at: Arc<Mutex<Vec<Option<Instant>>>>,
+    cooldown: Duration,
+    network: Arc<Caip2ChainId>,
+}
+
+impl<T> JrpcFallback<T> {
+    pub fn new(endpoints: Vec<T>, network: Caip2ChainId, cooldown: Duration) -> Self {
+        assert!(!endpoints.is_empty(), "No JSON-RPC endpoints for {network}");
+        for i in 0..endpoints.len() {
+            METRICS.set_protocol_chain_endpoint_health(network.as_str(), &i.to_string(), true);
+        }
+        Self {
+            failed_at: Arc::new(Mutex::new(vec![None; endpoints.len()])),
+            endpoints: Arc::new(endpoints),
+            cooldown,
+            network: Arc::new(network),
+        }
+    }
+
+    /// The order in which endpoints should be tried: the healthy ones first, and then the ones
+    /// that are cooling off as a last resort.
+    fn endpoint_order(&self) -> Vec<usize> {
+        let failed_at = self.failed_at.lock().unwrap();
+        let is_cooling_off =
+            |i: &usize| failed_at[*i].is_some_and(|instant| instant.elapsed() < self.cooldown);
+        let (cooling_off, healthy): (Vec<usize>, Vec<usize>) =
+            (0..self.endpoints.len()).partition(is_cooling_off);
+        healthy.into_iter().chain(cooling_off).collect()
+    }
 
-    Ok(BlockPtr {
-        number: latest_block.number.as_u64(),
-        hash: latest_block.hash.0,
-    })
+    fn set_health(&self, endpoint: usize, healthy: bool) {
+        self.failed_at.lock().unwrap()[endpoint] =
+            if healthy { None } else { Some(Instant::now()) };
+        METRICS.set_protocol_chain_endpoint_health(
+            self.network.as_str(),
+            &endpoint.to_string(),
+            healthy,
+        );
+    }
 }
 
-/// Fetches the latest available block number and hash from all `chains`.
-pub async fn get_latest_blocks<T>(
-    chains: &[JrpcProviderForChain<T>],
-) -> BTreeMap<Caip2ChainId, web3::Result<BlockPtr>>
+impl JrpcFallback {
+    pub fn http(
+        jrpc_urls: &[Url],
+        network: Caip2ChainId,
+        max_wait: Duration,
+        cooldown: Duration,
+    ) -> Self {
+        let endpoints = jrpc_urls
+            .iter()
+            .map(|url| JrpcExpBackoff::http(url.clone(), network.clone(), max_wait))
+            .collect();
+        Self::new(endpoints, network, cooldown)
+    }
+}
+
+impl<T> web3::Transport for JrpcFallback<T>
 where
-    T: web3::Transport,
+    T: web3::Transport + 'static,
 {
-    let mut tasks = chains
-        .iter()
-        .cloned()
-        .map(|chain| get_latest_block(chain.web3).map(|block| (chain.chain_id, block)))
-        .collect::<FuturesUnordered<_>>();
+    type Out = Pin<Box<dyn Future<Output = web3::error::Result<Value>>>>;
 
-    let mut block_ptr_per_chain = BTreeMap::new();
-    while let Some((chain_id, jrpc_call_result)) = tasks.next().await {
-        block_ptr_per_chain.insert(chain_id, jrpc_call_result);
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        self.endpoints[0].prepare(method, params)
     }
 
-    assert!(block_ptr_per_chain.len() == chains.len());
-    block_ptr_per_chain
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let this = self.clone();
+        Box::pin(async move {
+            let mut last_error = None;
+            for endpoint in this.endpoint_order() {
+                match this.endpoints[endpoint].send(id, request.clone()).await {
+                    Ok(value) => {
+                        this.set_health(endpoint, true);
+                        return Ok(value);
+                    }
+                    // The endpoint works, but the call itself was rejected.
+                    Err(error @ web3::Error::Rpc(_)) => {
+                        this.set_health(endpoint, true);
+                        return Err(error);
+                    }
+                    Err(error) => {
+                        warn!(
+                            network = this.network.as_str(),
+                            endpoint,
+                            error = error.to_string().as_str(),
+                            "Protocol chain endpoint failed, trying the next one."
+                        );
+                        METRICS.track_jrpc_protocol_chain_endpoint_failure(
+                            this.network.as_str(),
+                            &endpoint.to_string(),
+                        );
+                        this.set_health(endpoint, false);
+                        last_error = Some(error);
+                    }
+                }
+            }
+            Err(last_error.expect("There's at least one endpoint"))
+        })
+    }
+}
+
+/// It'd pretty weird if a provider wouldn't respond with a valid latest block; in that case,
+/// we'll raise a [`web3::Error`].
+///
+/// Note: Hardhat and other test setups might force us to rethink this and return an [`Option`].
+pub async fn get_latest_block<T>(web3: Web3<T>) -> web3::Result<BlockPtr>
+where
+    T: Transport,
+{
+    // We're asking for the chain head.
+    get_block(&web3, web3::helpers::serialize(&BlockNumber::Latest))
+        .await?
+        .ok_or_else(|| web3::Error::InvalidResponse("No latest block".to_owned()))
+}
+
+/// Fetches a mined block by its number. Fails if the provider doesn't know about it.
+pub async fn get_block_by_number<T>(web3: &Web3<T>, block_number: u64) -> web3::Result<BlockPtr>
+where
+    T: Transport,
+{
+    let block_num = web3::helpers::serialize(&BlockNumber::Number(U64::from(block_number)));
+    get_block(web3, block_num)
+        .await?
+        .ok_or_else(|| web3::Error::InvalidResponse(format!("Block {block_number} not found")))
+}
+
+/// Fetches the block with a tag that [`BlockNumber`] doesn't know about, such as `safe` or
+/// `finalized`.
+pub async fn get_block_by_tag<T>(web3: &Web3<T>, tag: &str) -> web3::Result<BlockPtr>
+where
+    T: Transport,
+{
+    get_block(web3, Value::String(tag.to_owned()))
+        .await?
+        .ok_or_else(|| web3::Error::InvalidResponse(format!("No '{tag}' block")))
 }
 
 /// Scans a block range for relevant transactions.
@@ -174,3 +291,114 @@ where
     txs.retain(|tx| tx.from == Some(from_address) && tx.to == Some(to_address));
     Ok(txs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Answers every call with its own name, unless it's down. Calls to "reject" fail with an
+    /// RPC error.
+    #[derive(Debug, Clone)]
+    struct MockEndpoint {
+        name: &'static str,
+        down: Arc<AtomicBool>,
+    }
+
+    impl MockEndpoint {
+        fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                down: Arc::new(AtomicBool::new(false)),
+            }
+        }
+
+        fn set_down(&self, down: bool) {
+            self.down.store(down, Ordering::SeqCst);
+        }
+    }
+
+    impl web3::Transport for MockEndpoint {
+        type Out = Pin<Box<dyn Future<Output = web3::error::Result<Value>>>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            let call = Call::MethodCall(jsonrpc_core::MethodCall {
+                jsonrpc: Some(jsonrpc_core::Version::V2),
+                method: method.to_string(),
+                params: jsonrpc_core::Params::Array(params),
+                id: jsonrpc_core::Id::Num(1),
+            });
+            (1, call)
+        }
+
+        fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+            let response = match request {
+                _ if self.down.load(Ordering::SeqCst) => Err(web3::Error::Unreachable),
+                Call::MethodCall(call) if call.method == "reject" => {
+                    Err(web3::Error::Rpc(jsonrpc_core::Error::invalid_request()))
+                }
+                _ => Ok(Value::String(self.name.to_owned())),
+            };
+            Box::pin(futures::future::ready(response))
+        }
+    }
+
+    async fn call(transport: &JrpcFallback<MockEndpoint>, method: &str) -> web3::Result<Value> {
+        transport.execute(method, vec![]).await
+    }
+
+    fn fallback(cooldown: Duration) -> (JrpcFallback<MockEndpoint>, MockEndpoint, MockEndpoint) {
+        let primary = MockEndpoint::new("primary");
+        let secondary = MockEndpoint::new("secondary");
+        let transport = JrpcFallback::new(
+            vec![primary.clone(), secondary.clone()],
+            Caip2ChainId::ethereum_mainnet(),
+            cooldown,
+        );
+        (transport, primary, secondary)
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_stays_on_fallback_during_cooldown() {
+        let (transport, primary, secondary) = fallback(Duration::from_secs(3600));
+        assert_eq!(call(&transport, "foo").await.unwrap(), "primary");
+
+        primary.set_down(true);
+        assert_eq!(call(&transport, "foo").await.unwrap(), "secondary");
+
+        // The primary endpoint is still cooling off, even though it's back.
+        primary.set_down(false);
+        assert_eq!(call(&transport, "foo").await.unwrap(), "secondary");
+
+        // As a last resort, endpoints that are cooling off are tried anyway.
+        secondary.set_down(true);
+        assert_eq!(call(&transport, "foo").await.unwrap(), "primary");
+        assert_eq!(transport.endpoint_order(), vec![0, 1]);
+
+        primary.set_down(true);
+        assert!(matches!(
+            call(&transport, "foo").await,
+            Err(web3::Error::Unreachable)
+        ));
+    }
+
+    #[tokio::test]
+    async fn goes_back_to_primary_after_cooldown() {
+        let (transport, primary, _) = fallback(Duration::ZERO);
+        primary.set_down(true);
+        assert_eq!(call(&transport, "foo").await.unwrap(), "secondary");
+
+        primary.set_down(false);
+        assert_eq!(call(&transport, "foo").await.unwrap(), "primary");
+    }
+
+    #[tokio::test]
+    async fn rpc_errors_dont_fail_over() {
+        let (transport, _, _) = fallback(Duration::from_secs(3600));
+        assert!(matches!(
+            call(&transport, "reject").await,
+            Err(web3::Error::Rpc(_))
+        ));
+        assert_eq!(transport.endpoint_order(), vec![0, 1]);
+    }
+}
@@ -3,8 +3,11 @@ pub mod error_handling;
 pub mod jrpc_utils;
 pub mod oracle;
 pub mod transaction_monitor;
+pub mod transaction_state;
 
 use self::ctrlc::CtrlcHandler;
+use crate::commands::audit::{audit_epochs, AuditOutcome};
+use crate::config::AuditOptions;
 use crate::contracts::ContractError;
 use crate::metrics::{metrics_server, METRICS};
 use crate::{Caip2ChainId, Config, SubgraphQueryError};
@@ -12,8 +15,10 @@ use error_handling::{MainLoopFlow, OracleControlFlow};
 use lazy_static::lazy_static;
 use oracle::Oracle;
 use std::{env::set_var, path::Path, time::Duration};
-use tracing::{error, info, metadata::LevelFilter};
+use tokio::{task::LocalSet, time::MissedTickBehavior};
+use tracing::{debug, error, info, metadata::LevelFilter, warn};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use web3::types::H256;
 
 lazy_static! {
     static ref CTRLC_HANDLER: CtrlcHandler = CtrlcHandler::init();
@@ -40,6 +45,12 @@ pub enum Error {
     SubgraphNotFresh,
     #[error("The subgraph has not been initialized yet")]
     SubgraphNotInitialized,
+    #[error("The subgraph didn't index the payload of transaction {0:?} in time")]
+    PayloadNotIndexed(H256),
+    #[error("The subgraph rejected the payload of transaction {0:?} as invalid")]
+    PayloadRejected(H256),
+    #[error("Can't encode payloads for the subgraph: {0}")]
+    Encoding(#[from] epoch_encoding::Error),
 }
 
 impl MainLoopFlow for Error {
@@ -51,6 +62,13 @@ impl MainLoopFlow for Error {
             BadJrpcIndexedChain { .. } => OracleControlFlow::Continue(0),
 
             // TODO: Put those variants under a new `contracts::Error` enum
+            // Resubmitting a reverted transaction would most likely revert again, and an estimate
+            // above the gas cap needs a configuration change.
+            ContractError(
+                crate::contracts::ContractError::Reverted(_)
+                | crate::contracts::ContractError::Simulation(_)
+                | crate::contracts::ContractError::GasEstimateAboveCap { .. },
+            ) => OracleControlFlow::Continue(40),
             ContractError(_) => OracleControlFlow::Continue(0),
             EpochManagerCallFailed(_) => OracleControlFlow::Continue(0),
             EpochManagerBehindSubgraph { .. } => OracleControlFlow::Continue(0),
@@ -58,11 +76,16 @@ impl MainLoopFlow for Error {
             // TODO: Put those variants under the `SubgraphQueryError` enum
             SubgraphNotFresh => OracleControlFlow::Continue(2),
             SubgraphNotInitialized => OracleControlFlow::Continue(2),
+            PayloadNotIndexed(_) => OracleControlFlow::Continue(2),
+            // An invalid payload requires external intervention, so we poll less frequently.
+            PayloadRejected(_) => OracleControlFlow::Continue(40),
+            // E.g. an encoding version that this oracle doesn't support, which needs an upgrade.
+            Encoding(_) => OracleControlFlow::Continue(40),
         }
     }
 }
 
-pub async fn run(config_file: impl AsRef<Path>) -> Result<(), Error> {
+pub async fn run(config_file: impl AsRef<Path>) -> anyhow::Result<()> {
     // Immediately dereference some constants to trigger `lazy_static`
     // initialization.
     let config = Config::parse(config_file);
@@ -71,19 +94,31 @@ pub async fn run(config_file: impl AsRef<Path>) -> Result<(), Error> {
     init_logging(config.log_level);
     info!(log_level = %config.log_level, "The block oracle is starting.");
 
-    // Validate RPC chain IDs before starting
-    if let Err(err) = crate::chain_validation::validate_chain_ids(&config).await {
-        error!("Chain ID validation failed: {}", err);
-        return Err(Error::BadJrpcProtocolChain(web3::Error::Decoder(
-            err.to_string(),
-        )));
+    // Check the chains, the contracts and the owner key before starting
+    let report = crate::preflight::preflight(&config).await;
+    for check in &report.checks {
+        match &check.result {
+            Ok(details) => info!("✓ {}: {details}", check.name),
+            Err(err) => error!("✗ {}: {err:#}", check.name),
+        }
+    }
+    if !report.passed() {
+        anyhow::bail!("The preflight checks failed");
     }
 
     // Spawn the metrics server
     tokio::spawn(metrics_server(&METRICS, config.metrics_port));
 
+    // Block sources aren't `Send`, so the audit task runs on the same thread as the main loop, but
+    // on its own schedule: slow audits don't hold up the main loop.
+    let tasks = LocalSet::new();
+    if let Some(audit_options) = config.audit_options {
+        tasks.spawn_local(audit_task(config.clone(), audit_options));
+    }
+
     // Start the Epoch Block Oracle
-    oracle_task(config).await
+    tasks.run_until(oracle_task(config)).await?;
+    Ok(())
 }
 
 async fn oracle_task(config: Config) -> Result<(), Error> {
@@ -107,6 +142,46 @@ async fn oracle_task(config: Config) -> Result<(), Error> {
     Ok(())
 }
 
+async fn audit_task(config: Config, options: AuditOptions) {
+    let mut interval = tokio::time::interval(Duration::from_secs(options.interval_in_seconds));
+    // A slow audit delays the next one, rather than causing a burst of audits.
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        audit(&config, options).await;
+    }
+}
+
+async fn audit(config: &Config, options: AuditOptions) {
+    match audit_epochs(config, options.epochs).await {
+        Ok(audits) => {
+            for audit in audits {
+                let epoch_number = audit.epoch_number;
+                match audit.outcome {
+                    AuditOutcome::Match => {
+                        debug!(epoch_number, "The epoch Merkle root matches.")
+                    }
+                    AuditOutcome::Mismatch { computed, posted } => error!(
+                        epoch_number,
+                        computed = hex::encode(computed).as_str(),
+                        posted = hex::encode(posted).as_str(),
+                        "The epoch Merkle root doesn't match its block numbers."
+                    ),
+                    AuditOutcome::Skipped(reason) => warn!(
+                        epoch_number,
+                        reason = reason.as_str(),
+                        "Couldn't audit the epoch Merkle root."
+                    ),
+                }
+            }
+        }
+        Err(err) => error!(
+            error = err.to_string().as_str(),
+            "Failed to audit the latest epochs."
+        ),
+    }
+}
+
 async fn handle_error(err: Error, polling_interval: Duration) -> Result<(), Error> {
     error!(
         error = err.to_string().as_str(),
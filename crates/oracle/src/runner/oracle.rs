@@ -1,47 +1,51 @@
 use crate::{
-    blockmeta::blockmeta_client::{get_latest_blockmeta_blocks, AuthInterceptor},
+    block_source::{get_latest_blocks, BlockSource},
     contracts::Contracts,
     hex_string,
-    jrpc_utils::{get_latest_block, get_latest_blocks, JrpcExpBackoff},
+    jrpc_utils::{get_latest_block, JrpcFallback},
     metrics::METRICS,
+    signer::owner_signer,
     subgraph::{query_subgraph, SubgraphState},
-    BlockmetaProviderForChain, Caip2ChainId, Config, Error, JrpcProviderForChain,
+    Caip2ChainId, Config, Error, JrpcProviderForChain,
 };
-use alloy_primitives::BlockHash;
 use epoch_encoding::{BlockPtr, Encoder, Message, CURRENT_ENCODING_VERSION};
-use std::{cmp::Ordering, collections::BTreeMap};
-use tonic::codegen::InterceptedService;
-use tonic::transport::Channel;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+use tokio::time::Instant;
 use tracing::{debug, error, info, warn};
+use web3::types::TransactionReceipt;
+
+/// How much time to wait between subgraph queries while verifying a submitted payload.
+const PAYLOAD_VERIFICATION_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 /// The main application in-memory state.
 pub struct Oracle {
     config: Config,
-    protocol_chain: JrpcProviderForChain<JrpcExpBackoff>,
-    indexed_chains: Vec<JrpcProviderForChain<JrpcExpBackoff>>,
-    blockmeta_indexed_chains:
-        Vec<BlockmetaProviderForChain<InterceptedService<Channel, AuthInterceptor>>>,
-    contracts: Contracts<JrpcExpBackoff>,
+    protocol_chain: JrpcProviderForChain<JrpcFallback>,
+    block_sources: Vec<Box<dyn BlockSource>>,
+    contracts: Contracts<JrpcFallback>,
 }
 
 impl Oracle {
     pub fn new(config: Config) -> Self {
         let protocol_chain = protocol_chain(&config);
-        let indexed_chains = indexed_chains(&config);
-        let blockmeta_indexed_chains = blockmeta_indexed_chains(&config);
+        let block_sources = config.block_sources();
         let contracts = Contracts::new(
             protocol_chain.web3.clone(),
             config.data_edge_address,
             config.epoch_manager_address,
-            config.transaction_monitoring_options,
+            config.transaction_monitoring_options.clone(),
+            owner_signer(&config, protocol_chain.web3.clone()),
         )
         .expect("Failed to initialize Block Oracle's required contracts");
 
         Self {
             config,
             protocol_chain,
-            indexed_chains,
-            blockmeta_indexed_chains,
+            block_sources,
             contracts,
         }
     }
@@ -145,9 +149,21 @@ impl Oracle {
         METRICS.set_current_epoch("subgraph", subgraph_latest_epoch as i64);
         let manager_current_epoch = self.contracts.query_current_epoch().await?;
         match subgraph_latest_epoch.cmp(&manager_current_epoch) {
-            Ordering::Less => Ok(PreviousEpoch {
-                subgraph_latest_indexed_block,
-            }),
+            Ordering::Less => {
+                let epochs_behind = manager_current_epoch - subgraph_latest_epoch;
+                if epochs_behind > 1 {
+                    // The subgraph numbers the next epoch after the Epoch Manager's current one and
+                    // backfills the skipped epochs, so a single payload catches it up.
+                    warn!(
+                        epochs_behind,
+                        "The subgraph is several epochs behind the Epoch Manager. The skipped \
+                        epochs won't have block numbers."
+                    );
+                }
+                Ok(PreviousEpoch {
+                    subgraph_latest_indexed_block,
+                })
+            }
             Ordering::Equal => Ok(SameEpoch),
             Ordering::Greater => Err(Error::EpochManagerBehindSubgraph {
                 manager: manager_current_epoch,
@@ -160,73 +176,41 @@ impl Oracle {
         info!("Entering a new epoch.");
         info!("Collecting latest block information from all indexed chains.");
 
-        let latest_jrpc_blocks_res = get_latest_blocks(&self.indexed_chains).await;
-        let mut latest_jrpc_blocks = BTreeMap::new();
-        latest_jrpc_blocks_res
-            .into_iter()
-            .try_for_each(|(chain_id, res)| match res {
-                Ok(block) => {
-                    METRICS.set_latest_block_number(chain_id.as_str(), "jrpc", block.number as i64);
-                    latest_jrpc_blocks.insert(chain_id.clone(), block);
-                    Ok(())
-                }
-                Err(e) => {
-                    METRICS.track_jrpc_indexed_chain_failure(chain_id.as_str());
-                    Err(Error::BadJrpcIndexedChain {
-                        chain_id: chain_id.clone(),
-                        error: web3::Error::InvalidResponse(e.to_string()),
-                    })
-                }
-            })?;
-
-        let latest_blockmeta_blocks_res =
-            get_latest_blockmeta_blocks(&self.blockmeta_indexed_chains).await;
-        let mut latest_blockmeta_blocks = BTreeMap::new();
-        latest_blockmeta_blocks_res
-            .into_iter()
-            .try_for_each(|(chain_id, res)| match res {
+        let mut latest_blocks: BTreeMap<Caip2ChainId, BlockPtr> = BTreeMap::new();
+        for (source, result) in get_latest_blocks(&self.block_sources).await {
+            let chain_id = source.chain_id();
+            match result {
                 Ok(block) => {
                     METRICS.set_latest_block_number(
                         chain_id.as_str(),
-                        "blockmeta",
-                        block.num as i64,
+                        source.kind(),
+                        block.number as i64,
                     );
-
-                    match block.id.clone().parse::<BlockHash>() {
-                        Ok(hash) => {
-                            let block_ptr = BlockPtr {
-                                number: block.num,
-                                hash: hash.0,
-                            };
-                            latest_blockmeta_blocks.insert(chain_id.clone(), block_ptr);
-                            Ok(())
-                        }
-                        Err(e) => {
-                            METRICS.track_jrpc_indexed_chain_failure(chain_id.as_str());
-                            Err(Error::BadJrpcIndexedChain {
-                                chain_id: chain_id.clone(),
-                                error: web3::Error::InvalidResponse(e.to_string()),
-                            })
-                        }
-                    }
+                    latest_blocks.insert(chain_id.clone(), block);
                 }
                 Err(e) => {
                     METRICS.track_jrpc_indexed_chain_failure(chain_id.as_str());
-                    Err(Error::BadJrpcIndexedChain {
+                    return Err(Error::BadJrpcIndexedChain {
                         chain_id: chain_id.clone(),
                         error: web3::Error::InvalidResponse(e.to_string()),
-                    })
+                    });
                 }
-            })?;
+            }
+        }
 
-        let latest_blocks: BTreeMap<Caip2ChainId, BlockPtr> = latest_jrpc_blocks
-            .into_iter()
-            .chain(latest_blockmeta_blocks.into_iter())
-            .collect();
-        let payload = set_block_numbers_for_next_epoch(subgraph_state, latest_blocks);
+        // The subgraph sets the block numbers for the Epoch Manager's current epoch, which payloads
+        // from encoding version 1 on state explicitly.
+        let epoch_number = self.contracts.query_current_epoch().await?;
+        let payload = set_block_numbers_for_next_epoch(
+            subgraph_state,
+            epoch_number,
+            latest_blocks,
+            self.config.auto_register,
+            &self.config.network_aliases,
+        )?;
         let transaction_receipt = self
             .contracts
-            .submit_call(payload, &self.config.owner_private_key)
+            .submit_call(payload)
             .await
             .map_err(Error::ContractError)?;
         METRICS.set_last_sent_message();
@@ -235,11 +219,56 @@ impl Oracle {
             "Contract call submitted successfully."
         );
 
-        // TODO: After broadcasting a transaction to the protocol chain and getting a transaction
-        // receipt, we should monitor it until it get enough confirmations. It's unclear which
-        // component should do this task.
+        self.verify_payload(&transaction_receipt).await
+    }
 
-        Ok(())
+    /// Waits for the subgraph to index the block that contains a submitted payload, and checks
+    /// that the subgraph considered the payload valid.
+    async fn verify_payload(&self, receipt: &TransactionReceipt) -> Result<(), Error> {
+        let tx_hash = receipt.transaction_hash;
+        let block_number = receipt
+            .block_number
+            .ok_or(Error::PayloadNotIndexed(tx_hash))?
+            .as_u64();
+        let deadline = Instant::now() + self.config.payload_verification_timeout;
+
+        loop {
+            let subgraph_state =
+                query_subgraph(&self.config.subgraph_url, &self.config.bearer_token).await?;
+            match payload_status(&subgraph_state, block_number) {
+                PayloadStatus::Valid => {
+                    info!(?tx_hash, "The subgraph accepted the payload.");
+                    return Ok(());
+                }
+                PayloadStatus::Invalid => {
+                    error!(?tx_hash, "The subgraph rejected the payload.");
+                    METRICS.track_rejected_payload("invalid");
+                    return Err(Error::PayloadRejected(tx_hash));
+                }
+                PayloadStatus::Missing => {
+                    error!(
+                        ?tx_hash,
+                        "The subgraph indexed no payload for the transaction."
+                    );
+                    return Err(Error::PayloadNotIndexed(tx_hash));
+                }
+                PayloadStatus::Pending if Instant::now() >= deadline => {
+                    warn!(
+                        ?tx_hash,
+                        "Gave up waiting for the subgraph to index the payload."
+                    );
+                    return Err(Error::PayloadNotIndexed(tx_hash));
+                }
+                PayloadStatus::Pending => {
+                    debug!(
+                        subgraph_block = subgraph_state.last_indexed_block_number,
+                        payload_block = block_number,
+                        "Waiting for the subgraph to index the payload."
+                    );
+                    tokio::time::sleep(PAYLOAD_VERIFICATION_POLL_INTERVAL).await;
+                }
+            }
+        }
     }
 
     /// Queries the Protocol Chain for the current balance of the Owner's account.
@@ -263,44 +292,90 @@ impl Oracle {
     }
 }
 
+/// Encodes the payload for the next epoch, with the subgraph's encoding version. Configured
+/// networks that the subgraph doesn't know are dropped, unless `auto_register` is set, in which
+/// case they are registered at the start of the same payload: by a `RegisterNetworksAndAliases`
+/// message if they have an alias, and by a `RegisterNetworks` message otherwise. Networks that
+/// were removed before pick up from their latest block number, like in the subgraph.
 fn set_block_numbers_for_next_epoch(
     subgraph_state: &SubgraphState,
+    epoch_number: u64,
     mut latest_blocks: BTreeMap<Caip2ChainId, BlockPtr>,
-) -> Vec<u8> {
+    auto_register: bool,
+    network_aliases: &HashMap<Caip2ChainId, String>,
+) -> Result<Vec<u8>, Error> {
     let registered_networks = subgraph_state
         .global_state
         .as_ref()
         .map(|gs| gs.networks.clone())
         // In case the subgraph is uninitialized, there's effectively no registered networks at all.
         .unwrap_or_default();
+    let encoding_version = subgraph_state
+        .global_state
+        .as_ref()
+        .map_or(CURRENT_ENCODING_VERSION, |gs| gs.encoding_version as u64);
 
-    // We're not interested in unregistered networks. So we isolate them into a separate
-    // collection, log them, and finally discard them.
-    let mut ignored_networks = Vec::new();
+    // Unregistered networks are isolated into a separate collection, and then either registered
+    // or logged and discarded.
+    let mut unregistered_networks = Vec::new();
     for chain_id in latest_blocks.keys().cloned() {
         if !registered_networks
             .iter()
             .any(|network| network.id == chain_id)
         {
-            ignored_networks.push(chain_id);
+            unregistered_networks.push(chain_id);
         }
     }
-    if !ignored_networks.is_empty() {
+
+    let mut messages = vec![];
+    if auto_register && !unregistered_networks.is_empty() {
+        info!(
+            networks = ?unregistered_networks,
+            "Registering the networks present in the configuration file"
+        );
+        // An empty alias would override the subgraph's preloaded one, so networks without a
+        // configured alias are registered without one.
+        let (with_alias, without_alias): (Vec<_>, Vec<_>) = unregistered_networks
+            .into_iter()
+            .partition(|chain_id| network_aliases.contains_key(chain_id));
+        if !without_alias.is_empty() {
+            messages.push(Message::RegisterNetworks {
+                remove: vec![],
+                add: without_alias
+                    .into_iter()
+                    .map(|chain_id| chain_id.as_str().to_owned())
+                    .collect(),
+            });
+        }
+        if !with_alias.is_empty() {
+            messages.push(Message::RegisterNetworksAndAliases {
+                remove: vec![],
+                add: with_alias
+                    .into_iter()
+                    .map(|chain_id| {
+                        let alias = network_aliases[&chain_id].clone();
+                        (chain_id.as_str().to_owned(), alias)
+                    })
+                    .collect(),
+            });
+        }
+    } else if !unregistered_networks.is_empty() {
         warn!(
-            ignored_networks = ?ignored_networks,
+            ignored_networks = ?unregistered_networks,
             "Multiple networks present in the configuration file are not registered"
         );
-    }
-    for chain_id in ignored_networks {
-        latest_blocks.remove(&chain_id);
+        for chain_id in unregistered_networks {
+            latest_blocks.remove(&chain_id);
+        }
     }
 
-    let message = Message::SetBlockNumbersForNextEpoch(
-        latest_blocks
+    messages.push(Message::SetBlockNumbersForNextEpoch {
+        epoch_number: Some(epoch_number),
+        block_ptrs: latest_blocks
             .into_iter()
             .map(|(chain_id, block_ptr)| (chain_id.as_str().to_owned(), block_ptr))
             .collect(),
-    );
+    });
     let available_networks: Vec<(String, epoch_encoding::Network)> = {
         registered_networks
             .into_iter()
@@ -309,18 +384,23 @@ fn set_block_numbers_for_next_epoch(
     };
 
     debug!(
-        message = ?message,
+        messages = ?messages,
         networks = ?available_networks,
         networks_count = available_networks.len(),
         "Compressing 'SetBlockNumbersForNextEpoch'"
     );
 
-    let mut compression_engine = Encoder::new(CURRENT_ENCODING_VERSION, available_networks)
-        .expect("Can't prepare for encoding because something went wrong.");
+    let removed_networks = subgraph_state
+        .removed_networks
+        .iter()
+        .map(|network| (network.id.as_str().to_owned(), network.clone().into()));
+
+    let mut compression_engine =
+        Encoder::new(encoding_version, available_networks)?.with_removed_networks(removed_networks);
     let compression_engine_initially = compression_engine.clone();
 
     let compressed = compression_engine
-        .compress(&[message])
+        .compress(&messages)
         .unwrap_or_else(|error| panic!("Encoding failed. Error: {error}"));
     debug!(
         compressed = ?compressed,
@@ -339,47 +419,44 @@ fn set_block_numbers_for_next_epoch(
             it had before these new messages. This is a bug!"
     );
 
-    encoded
+    Ok(encoded)
 }
 
-fn protocol_chain(config: &Config) -> JrpcProviderForChain<JrpcExpBackoff> {
-    let transport = JrpcExpBackoff::http(
-        config.protocol_chain.jrpc_url.clone(),
-        config.protocol_chain.id.clone(),
-        config.retry_strategy_max_wait_time,
-    );
-    JrpcProviderForChain::new(config.protocol_chain.id.clone(), transport)
+/// What the subgraph made of a payload that was submitted at a given protocol chain block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PayloadStatus {
+    /// The subgraph hasn't indexed the block yet.
+    Pending,
+    Valid,
+    Invalid,
+    /// The subgraph indexed the block, but has no payload for it.
+    Missing,
 }
 
-fn indexed_chains(config: &Config) -> Vec<JrpcProviderForChain<JrpcExpBackoff>> {
-    config
-        .indexed_chains
-        .iter()
-        .map(|chain| {
-            let transport = JrpcExpBackoff::http(
-                chain.jrpc_url.clone(),
-                chain.id.clone(),
-                config.retry_strategy_max_wait_time,
-            );
-            JrpcProviderForChain::new(chain.id.clone(), transport)
-        })
-        .collect()
+fn payload_status(subgraph_state: &SubgraphState, block_number: u64) -> PayloadStatus {
+    if subgraph_state.last_indexed_block_number < block_number {
+        return PayloadStatus::Pending;
+    }
+    match &subgraph_state.last_payload {
+        Some(payload) if payload.created_at >= block_number as i64 => {
+            if payload.valid {
+                PayloadStatus::Valid
+            } else {
+                PayloadStatus::Invalid
+            }
+        }
+        _ => PayloadStatus::Missing,
+    }
 }
 
-fn blockmeta_indexed_chains(
-    config: &Config,
-) -> Vec<BlockmetaProviderForChain<InterceptedService<Channel, AuthInterceptor>>> {
-    config
-        .blockmeta_indexed_chains
-        .iter()
-        .map(|chain| {
-            BlockmetaProviderForChain::new(
-                chain.id.clone(),
-                chain.url.clone(),
-                config.blockmeta_auth_token.clone(),
-            )
-        })
-        .collect()
+fn protocol_chain(config: &Config) -> JrpcProviderForChain<JrpcFallback> {
+    let transport = JrpcFallback::http(
+        &config.protocol_chain.jrpc_urls,
+        config.protocol_chain.id.clone(),
+        config.retry_strategy_max_wait_time,
+        config.protocol_chain.endpoint_cooldown,
+    );
+    JrpcProviderForChain::new(config.protocol_chain.id.clone(), transport)
 }
 
 mod freshness {
@@ -468,3 +545,162 @@ enum NewEpochCheck {
     /// The Epoch Subgraph is at the same epoch as the Epoch Manager.
     SameEpoch,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subgraph::{BlockUpdate, GlobalState, Network, Payload, RemovedNetwork};
+    use json_oracle_encoder::payload_to_messages;
+    use serde_json::json;
+
+    fn subgraph_state(
+        last_indexed_block_number: u64,
+        payload: Option<(bool, i64)>,
+    ) -> SubgraphState {
+        SubgraphState {
+            last_indexed_block_number,
+            global_state: None,
+            removed_networks: vec![],
+            last_payload: payload.map(|(valid, created_at)| Payload { valid, created_at }),
+        }
+    }
+
+    #[test]
+    fn payload_verification() {
+        let status = |state: &SubgraphState| payload_status(state, 100);
+        assert_eq!(
+            status(&subgraph_state(99, Some((true, 90)))),
+            PayloadStatus::Pending
+        );
+        assert_eq!(
+            status(&subgraph_state(100, Some((true, 100)))),
+            PayloadStatus::Valid
+        );
+        assert_eq!(
+            status(&subgraph_state(105, Some((false, 100)))),
+            PayloadStatus::Invalid
+        );
+        assert_eq!(
+            status(&subgraph_state(105, Some((true, 90)))),
+            PayloadStatus::Missing
+        );
+        assert_eq!(status(&subgraph_state(105, None)), PayloadStatus::Missing);
+    }
+
+    #[test]
+    fn auto_register() {
+        let subgraph_state = SubgraphState {
+            last_indexed_block_number: 100,
+            global_state: Some(GlobalState {
+                networks: vec![Network {
+                    id: "eip155:1".parse().unwrap(),
+                    array_index: 0,
+                    latest_block_update: None,
+                }],
+                encoding_version: 0,
+                latest_epoch_number: Some(1),
+            }),
+            removed_networks: vec![RemovedNetwork {
+                id: "eip155:137".parse().unwrap(),
+                latest_block_update: Some(BlockUpdate {
+                    block_number: 4,
+                    acceleration: 0,
+                    delta: 1,
+                    updated_at_epoch_number: 1,
+                }),
+            }],
+            last_payload: None,
+        };
+        let latest_blocks: BTreeMap<Caip2ChainId, BlockPtr> = [
+            ("eip155:1", BlockPtr::new(10, [1; 32])),
+            ("eip155:100", BlockPtr::new(20, [2; 32])),
+            ("eip155:137", BlockPtr::new(30, [3; 32])),
+        ]
+        .into_iter()
+        .map(|(chain_id, block_ptr)| (chain_id.parse().unwrap(), block_ptr))
+        .collect();
+        let aliases = HashMap::from([("eip155:100".parse().unwrap(), "gnosis".to_string())]);
+        let messages = |auto_register| {
+            let payload = set_block_numbers_for_next_epoch(
+                &subgraph_state,
+                2,
+                latest_blocks.clone(),
+                auto_register,
+                &aliases,
+            )
+            .unwrap();
+            serde_json::to_value(payload_to_messages(&payload, 0, 1).unwrap()).unwrap()
+        };
+
+        // eip155:137 was removed before, and picks up from its latest block number.
+        let registered = messages(true);
+        assert_eq!(
+            registered[0],
+            json!({
+                "message": "RegisterNetworks",
+                "remove": [],
+                "add": ["eip155:137"]
+            })
+        );
+        assert_eq!(
+            registered[1],
+            json!({
+                "message": "RegisterNetworksAndAliases",
+                "remove": [],
+                "add": [["eip155:100", "gnosis"]]
+            })
+        );
+        assert_eq!(registered[2]["accelerations"], json!([10, 25, 20]));
+
+        let ignored = messages(false);
+        assert_eq!(ignored.as_array().unwrap().len(), 1);
+        assert_eq!(ignored[0]["accelerations"], json!([10]));
+    }
+
+    #[test]
+    fn epoch_number_follows_the_encoding_version() {
+        let subgraph_state = |encoding_version| SubgraphState {
+            last_indexed_block_number: 100,
+            global_state: Some(GlobalState {
+                networks: vec![Network {
+                    id: "eip155:1".parse().unwrap(),
+                    array_index: 0,
+                    latest_block_update: None,
+                }],
+                encoding_version,
+                latest_epoch_number: Some(6),
+            }),
+            removed_networks: vec![],
+            last_payload: None,
+        };
+        let latest_blocks: BTreeMap<Caip2ChainId, BlockPtr> =
+            [("eip155:1".parse().unwrap(), BlockPtr::new(10, [1; 32]))].into();
+        let message = |encoding_version| {
+            let payload = set_block_numbers_for_next_epoch(
+                &subgraph_state(encoding_version),
+                7,
+                latest_blocks.clone(),
+                false,
+                &HashMap::new(),
+            )
+            .unwrap();
+            let messages = payload_to_messages(&payload, encoding_version as u64, 1).unwrap();
+            serde_json::to_value(&messages[0]).unwrap()
+        };
+
+        assert_eq!(message(0).get("epochNumber"), None);
+        assert_eq!(message(1)["epochNumber"], json!(7));
+        assert!(matches!(
+            set_block_numbers_for_next_epoch(
+                &subgraph_state(2),
+                7,
+                latest_blocks.clone(),
+                false,
+                &HashMap::new(),
+            ),
+            Err(Error::Encoding(
+                epoch_encoding::Error::UnsupportedEncodingVersion(2)
+            ))
+        ));
+    }
+}
@@ -1,14 +1,17 @@
 use crate::config::TransactionMonitoringOptions;
+use crate::signer::{SignedTransaction, Signer};
+use crate::transaction_state::{PendingTransaction, TransactionStateError};
 use either::Either;
 use futures::stream::{FuturesUnordered, StreamExt};
 use std::collections::HashSet;
 use tokio::time::{timeout, Duration};
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, info, trace, warn};
 use web3::{
-    api::{Accounts, Namespace},
     error::Error as Web3Error,
-    signing::{Key, SecretKeyRef},
-    types::{Address, Bytes, TransactionParameters, TransactionReceipt, H256, U256},
+    types::{
+        Address, BlockNumber, Bytes, FeeHistory, TransactionParameters, TransactionReceipt, H256,
+        U256,
+    },
     Transport, Web3,
 };
 
@@ -22,62 +25,143 @@ pub enum TransactionMonitorError {
     Provider(#[source] Web3Error),
     #[error("failed to send transaction after exhausting all retries")]
     BroadcastFailure,
+    #[error(transparent)]
+    State(#[from] TransactionStateError),
+    #[error("the fee ceiling leaves no room for replacing the transaction with a higher fee")]
+    FeeCeilingReached,
 }
 
+/// The `transactionType` of EIP-1559 transactions.
+const EIP1559_TRANSACTION_TYPE: u64 = 2;
+
+/// How many blocks of `eth_feeHistory` are used to estimate the priority fee.
+const FEE_HISTORY_BLOCK_COUNT: u64 = 10;
+
+/// Nodes only accept a transaction that replaces a pending one if it pays at least this much more,
+/// in percent.
+const REPLACEMENT_MIN_PERCENTUAL_INCREASE: u32 = 10;
+
 pub struct TransactionMonitor<'a, T: Transport> {
     client: Web3<T>,
-    signing_key: SecretKeyRef<'a>,
+    signer: &'a dyn Signer,
 
     /// The unsingned transaction that we want to broadcast.
     /// We keep it around so we can control its `nonce` and `gas_price` values.
     transaction_parameters: TransactionParameters,
 
+    /// Versions of the same transaction that were broadcast before a restart.
+    previously_sent_transactions: HashSet<H256>,
+
     options: TransactionMonitoringOptions,
 }
 
 impl<'a, T: Transport> TransactionMonitor<'a, T> {
     pub async fn new(
         client: Web3<T>,
-        signing_key: SecretKeyRef<'a>,
+        signer: &'a dyn Signer,
         contract_address: Address,
         calldata: Bytes,
+        gas: U256,
         options: TransactionMonitoringOptions,
     ) -> Result<TransactionMonitor<'a, T>, TransactionMonitorError> {
         trace!(?options, "Starting Transaction Monitor");
 
-        let from = signing_key.address();
+        let from = signer.address();
 
-        let (nonce, gas_price) = futures::future::try_join(
-            client.eth().transaction_count(from, None),
+        // With a state file, a transaction left in the mempool by a previous run is replaced at its
+        // own nonce, so the pending nonce queues this transaction after the others rather than
+        // replacing them by accident. Without one, the latest nonce replaces any stuck transaction.
+        let nonce_block = if options.state_file.is_some() {
+            BlockNumber::Pending
+        } else {
+            BlockNumber::Latest
+        };
+        let (latest_nonce, nonce, gas_price, chain_id) = futures::future::try_join4(
+            client
+                .eth()
+                .transaction_count(from, Some(BlockNumber::Latest)),
+            client.eth().transaction_count(from, Some(nonce_block)),
             client.eth().gas_price(),
+            client.eth().chain_id(),
         )
         .await
         .map_err(TransactionMonitorError::Startup)?;
 
-        // Add 20% margin to the fetched gas price
-        let gas_price = gas_price + (gas_price / 5); // 20% increase
+        let gas_price = initial_gas_price(gas_price, options.fee_ceiling_per_gas);
 
         debug!(
             %nonce,
             %gas_price, "Fetched current nonce and gas price from provider (with 20% margin)"
         );
 
-        let transaction_parameters = TransactionParameters {
+        let mut transaction_parameters = TransactionParameters {
             to: Some(contract_address),
-            gas: options.gas_limit.into(),
+            gas,
             gas_price: Some(gas_price),
             data: calldata,
             nonce: Some(nonce),
+            chain_id: Some(chain_id.as_u64()),
             max_fee_per_gas: options.max_fee_per_gas.map(Into::into),
             max_priority_fee_per_gas: options.max_priority_fee_per_gas.map(Into::into),
 
             ..Default::default()
         };
 
+        if options.eip1559 {
+            let fee_history = client
+                .eth()
+                .fee_history(
+                    FEE_HISTORY_BLOCK_COUNT.into(),
+                    BlockNumber::Latest,
+                    Some(vec![options.priority_fee_percentile]),
+                )
+                .await
+                .map_err(TransactionMonitorError::Startup)?;
+            let (max_fee_per_gas, max_priority_fee_per_gas) = estimate_eip1559_fees(&fee_history)
+                .ok_or_else(|| {
+                TransactionMonitorError::Startup(Web3Error::InvalidResponse(
+                    "the protocol chain reports no base fee, so it doesn't support EIP-1559"
+                        .to_string(),
+                ))
+            })?;
+            // Statically configured fees take precedence over the estimated ones.
+            let max_fee_per_gas = options.max_fee_per_gas.map_or(max_fee_per_gas, Into::into);
+            let max_priority_fee_per_gas = options
+                .max_priority_fee_per_gas
+                .map_or(max_priority_fee_per_gas, Into::into);
+            let max_fee_per_gas = cap_fee(max_fee_per_gas, options.fee_ceiling_per_gas);
+
+            debug!(
+                %max_fee_per_gas,
+                %max_priority_fee_per_gas,
+                "Estimated EIP-1559 fees from the fee history"
+            );
+            transaction_parameters.transaction_type = Some(EIP1559_TRANSACTION_TYPE.into());
+            transaction_parameters.gas_price = None;
+            transaction_parameters.max_fee_per_gas = Some(max_fee_per_gas);
+            transaction_parameters.max_priority_fee_per_gas =
+                Some(max_priority_fee_per_gas.min(max_fee_per_gas));
+        }
+
+        let mut previously_sent_transactions = HashSet::new();
+        if let Some(path) = &options.state_file {
+            let pending = PendingTransaction::load(path)?
+                .filter(|pending| pending.from == from && pending.nonce >= latest_nonce);
+            if let Some(pending) = pending {
+                resume_pending_transaction(
+                    &mut transaction_parameters,
+                    &mut previously_sent_transactions,
+                    pending,
+                    options.gas_percentual_increase,
+                );
+            }
+        }
+
         Ok(Self {
             client,
             transaction_parameters,
-            signing_key,
+            previously_sent_transactions,
+            signer,
             options,
         })
     }
@@ -122,23 +206,41 @@ impl<'a, T: Transport> TransactionMonitor<'a, T> {
         Ok(None)
     }
 
-    /// Attempts to sign and broadcast a transaction, returing its receipt on success.
+    async fn sign_transaction(
+        &self,
+        transaction_parameters: TransactionParameters,
+    ) -> Result<SignedTransaction, TransactionMonitorError> {
+        self.signer
+            .sign_transaction(transaction_parameters)
+            .await
+            .map_err(TransactionMonitorError::Signing)
+    }
+
+    /// Saves the transaction and the hashes of all of its broadcast versions to the state file,
+    /// if there is one.
+    fn save_state(
+        &self,
+        transaction_parameters: &TransactionParameters,
+        sent_transaction_hashes: impl Iterator<Item = H256>,
+    ) -> Result<(), TransactionStateError> {
+        let Some(path) = &self.options.state_file else {
+            return Ok(());
+        };
+        let mut pending = PendingTransaction::new(self.signer.address(), transaction_parameters);
+        pending.sent_transaction_hashes = sent_transaction_hashes.collect();
+        pending.sent_transaction_hashes.sort();
+        pending.save(path)
+    }
+
+    /// Broadcasts a signed transaction, returing its receipt on success.
     /// This function has two error types:
     /// - the generalist `web3::error:Error, and
     /// - the hash of the transaction that we given up waiting for it to be confirmed.
     async fn send_transaction_and_wait_for_confirmation(
         &self,
-        transaction_parameters: TransactionParameters,
+        signed_transaction: SignedTransaction,
+        gas: U256,
     ) -> Result<TransactionReceipt, Either<Web3Error, H256>> {
-        // we will log this later
-        let gas = transaction_parameters.gas;
-
-        // Sign the transaction
-        let signed_transaction = Accounts::new(self.client.transport().clone())
-            .sign_transaction(transaction_parameters, &*self.signing_key)
-            .await
-            .map_err(Either::Left)?;
-
         let transaction_hash = signed_transaction.transaction_hash;
 
         trace!(
@@ -173,26 +275,47 @@ impl<'a, T: Transport> TransactionMonitor<'a, T> {
     /// While doing so, it will also check if previously sent transactions were confirmed.
     ///
     /// This function will return an error if we exhaust its maximum retries attempts.
+    ///
+    /// Every broadcast version of the transaction is saved to the state file until one of them is
+    /// confirmed, so that they can be resumed after a restart.
     pub async fn execute_transaction(&self) -> Result<TransactionReceipt, TransactionMonitorError> {
         let mut retries = self.options.max_retries;
 
-        let mut sent_transactions = HashSet::new();
+        let mut sent_transactions = self.previously_sent_transactions.clone();
         let mut transaction_parameters = self.transaction_parameters.clone();
 
         while retries > 0 {
+            let signed_transaction = self
+                .sign_transaction(transaction_parameters.clone())
+                .await?;
+            self.save_state(
+                &transaction_parameters,
+                sent_transactions
+                    .iter()
+                    .copied()
+                    .chain([signed_transaction.transaction_hash]),
+            )?;
+
             // While we broadcast the current transaction, also check if any previously sent
             // transaction was confirmed.
             let (current_transaction_receipt, previous_transactions_receipt) = tokio::join!(
-                self.send_transaction_and_wait_for_confirmation(transaction_parameters.clone()),
+                self.send_transaction_and_wait_for_confirmation(
+                    signed_transaction,
+                    transaction_parameters.gas
+                ),
                 self.check_previously_sent_transactions(sent_transactions.clone()),
             );
 
             if let Ok(Some(receipt)) = previous_transactions_receipt {
+                self.clear_state()?;
                 return Ok(receipt);
             }
 
             match current_transaction_receipt {
-                Ok(receipt) => return Ok(receipt),
+                Ok(receipt) => {
+                    self.clear_state()?;
+                    return Ok(receipt);
+                }
                 Err(Either::Left(web3_error)) => {
                     // This means that we failed handling the transaction and got a provider error
                     // before the timeout.
@@ -201,9 +324,16 @@ impl<'a, T: Transport> TransactionMonitor<'a, T> {
                 Err(Either::Right(transaction_hash)) => {
                     // This means that we timed out waiting for the transaction to be confirmed.
                     sent_transactions.insert(transaction_hash);
-                    if let Some(gas) = transaction_parameters.gas_price.as_mut() {
-                        *gas = bump_gas(*gas, self.options.gas_percentual_increase)
-                            .expect("gas_price calculation won't overflow a 256-bit number")
+                    if !bump_fees(
+                        &mut transaction_parameters,
+                        self.options.gas_percentual_increase,
+                        self.options.fee_ceiling_per_gas,
+                    ) {
+                        warn!(
+                            ?transaction_hash,
+                            "The fee ceiling was reached, so the transaction can't be replaced"
+                        );
+                        return Err(TransactionMonitorError::FeeCeilingReached);
                     }
                     retries -= 1;
                     debug!(?transaction_hash, retries_left = %retries, "Timed out waiting for the transaction confirmation");
@@ -214,6 +344,51 @@ impl<'a, T: Transport> TransactionMonitor<'a, T> {
         // At this point, we have exhausted all retry attempts
         Err(TransactionMonitorError::BroadcastFailure)
     }
+
+    fn clear_state(&self) -> Result<(), TransactionStateError> {
+        match &self.options.state_file {
+            Some(path) => PendingTransaction::clear(path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Makes `transaction_parameters` replace a transaction that was broadcast before a restart but
+/// never confirmed: it reuses its nonce and outbids its fees. If it's the same transaction, its
+/// broadcast versions are also monitored.
+fn resume_pending_transaction(
+    transaction_parameters: &mut TransactionParameters,
+    previously_sent_transactions: &mut HashSet<H256>,
+    pending: PendingTransaction,
+    gas_percentual_increase: u32,
+) {
+    info!(
+        nonce = %pending.nonce,
+        hashes = ?pending.sent_transaction_hashes,
+        "Replacing a pending transaction from a previous run"
+    );
+    transaction_parameters.nonce = Some(pending.nonce);
+    let percentual_increase = gas_percentual_increase.max(REPLACEMENT_MIN_PERCENTUAL_INCREASE);
+    let outbid = |fee: &mut Option<U256>, previous_fee: Option<U256>| {
+        if let (Some(fee), Some(previous_fee)) = (fee.as_mut(), previous_fee) {
+            let outbid_fee = bump_gas(previous_fee, percentual_increase)
+                .expect("gas_price calculation won't overflow a 256-bit number");
+            *fee = (*fee).max(outbid_fee);
+        }
+    };
+    outbid(&mut transaction_parameters.gas_price, pending.gas_price);
+    outbid(
+        &mut transaction_parameters.max_fee_per_gas,
+        pending.max_fee_per_gas,
+    );
+    outbid(
+        &mut transaction_parameters.max_priority_fee_per_gas,
+        pending.max_priority_fee_per_gas,
+    );
+    if transaction_parameters.to == Some(pending.to) && transaction_parameters.data == pending.data
+    {
+        previously_sent_transactions.extend(pending.sent_transaction_hashes);
+    }
 }
 
 fn bump_gas(gas_price: U256, percentual_increase: u32) -> Option<U256> {
@@ -222,6 +397,81 @@ fn bump_gas(gas_price: U256, percentual_increase: u32) -> Option<U256> {
     gas_price.checked_mul(factor)?.checked_div(denominator)
 }
 
+fn cap_fee(fee: U256, ceiling: Option<u64>) -> U256 {
+    ceiling.map_or(fee, |ceiling| fee.min(ceiling.into()))
+}
+
+/// Adds a 20% margin to the gas price fetched from the provider, without exceeding the ceiling.
+fn initial_gas_price(gas_price: U256, ceiling: Option<u64>) -> U256 {
+    cap_fee(gas_price + (gas_price / 5), ceiling)
+}
+
+/// Picks the max fee and the priority fee of an EIP-1559 transaction from the fee history of the
+/// latest blocks: the priority fee is the median of the requested reward percentile, and the max
+/// fee leaves room for the base fee to double. Returns `None` if the chain reports no base fee.
+fn estimate_eip1559_fees(fee_history: &FeeHistory) -> Option<(U256, U256)> {
+    let base_fee_per_gas = *fee_history.base_fee_per_gas.last()?;
+    if base_fee_per_gas.is_zero() {
+        return None;
+    }
+    let mut rewards: Vec<U256> = fee_history
+        .reward
+        .iter()
+        .flatten()
+        .filter_map(|block_rewards| block_rewards.first().copied())
+        .collect();
+    rewards.sort();
+    let max_priority_fee_per_gas = rewards.get(rewards.len() / 2).copied().unwrap_or_default();
+    let max_fee_per_gas = base_fee_per_gas * 2 + max_priority_fee_per_gas;
+    Some((max_fee_per_gas, max_priority_fee_per_gas))
+}
+
+/// Raises the fees of a transaction that timed out, so that it can replace its previous versions.
+/// EIP-1559 transactions get both their max fee and their priority fee raised by at least the
+/// replacement minimum. Returns `false` if `ceiling` leaves no room for a valid replacement.
+fn bump_fees(
+    transaction_parameters: &mut TransactionParameters,
+    percentual_increase: u32,
+    ceiling: Option<u64>,
+) -> bool {
+    let bump = |fee: U256, percentual_increase: u32| {
+        bump_gas(fee, percentual_increase)
+            .expect("gas_price calculation won't overflow a 256-bit number")
+    };
+
+    if transaction_parameters.transaction_type != Some(EIP1559_TRANSACTION_TYPE.into()) {
+        return match transaction_parameters.gas_price.as_mut() {
+            Some(gas_price) => {
+                let bumped = cap_fee(bump(*gas_price, percentual_increase), ceiling);
+                let replaceable = bumped > *gas_price;
+                *gas_price = bumped;
+                replaceable
+            }
+            None => true,
+        };
+    }
+
+    let (Some(max_fee_per_gas), Some(max_priority_fee_per_gas)) = (
+        transaction_parameters.max_fee_per_gas,
+        transaction_parameters.max_priority_fee_per_gas,
+    ) else {
+        return true;
+    };
+    let percentual_increase = percentual_increase.max(REPLACEMENT_MIN_PERCENTUAL_INCREASE);
+    let bumped_max_fee = cap_fee(bump(max_fee_per_gas, percentual_increase), ceiling);
+    let bumped_priority_fee =
+        bump(max_priority_fee_per_gas, percentual_increase).min(bumped_max_fee);
+    let replaceable = bumped_max_fee >= bump(max_fee_per_gas, REPLACEMENT_MIN_PERCENTUAL_INCREASE)
+        && bumped_priority_fee
+            >= bump(
+                max_priority_fee_per_gas,
+                REPLACEMENT_MIN_PERCENTUAL_INCREASE,
+            );
+    transaction_parameters.max_fee_per_gas = Some(bumped_max_fee);
+    transaction_parameters.max_priority_fee_per_gas = Some(bumped_priority_fee);
+    replaceable
+}
+
 #[test]
 fn test_bump_gas() {
     let input: U256 = 1000.into();
@@ -230,3 +480,175 @@ fn test_bump_gas() {
     let output = bump_gas(input, percentual_increase);
     assert_eq!(output, Some(expected));
 }
+
+#[test]
+fn test_initial_gas_price() {
+    assert_eq!(initial_gas_price(100.into(), None), 120.into());
+    assert_eq!(initial_gas_price(100.into(), Some(150)), 120.into());
+    assert_eq!(initial_gas_price(100.into(), Some(110)), 110.into());
+}
+
+#[test]
+fn test_estimate_eip1559_fees() {
+    let fee_history = |base_fees: &[u64], rewards: &[u64]| FeeHistory {
+        oldest_block: BlockNumber::Number(100.into()),
+        base_fee_per_gas: base_fees.iter().map(|&fee| fee.into()).collect(),
+        gas_used_ratio: vec![0.5; rewards.len()],
+        reward: Some(rewards.iter().map(|&reward| vec![reward.into()]).collect()),
+    };
+    assert_eq!(
+        estimate_eip1559_fees(&fee_history(&[90, 95, 100], &[3, 1, 2])),
+        Some((202.into(), 2.into()))
+    );
+    assert_eq!(
+        estimate_eip1559_fees(&fee_history(&[100], &[])),
+        Some((200.into(), 0.into()))
+    );
+    assert_eq!(estimate_eip1559_fees(&fee_history(&[0, 0], &[1])), None);
+}
+
+#[test]
+fn test_bump_fees() {
+    let eip1559 = |max_fee: u64, priority_fee: u64| TransactionParameters {
+        transaction_type: Some(EIP1559_TRANSACTION_TYPE.into()),
+        gas_price: None,
+        max_fee_per_gas: Some(max_fee.into()),
+        max_priority_fee_per_gas: Some(priority_fee.into()),
+        ..Default::default()
+    };
+
+    // Both fees are bumped by at least the replacement minimum.
+    let mut parameters = eip1559(1000, 100);
+    assert!(bump_fees(&mut parameters, 5, None));
+    assert_eq!(parameters.max_fee_per_gas, Some(1100.into()));
+    assert_eq!(parameters.max_priority_fee_per_gas, Some(110.into()));
+
+    // The ceiling caps the bump, but leaves room for a replacement.
+    let mut parameters = eip1559(1000, 100);
+    assert!(bump_fees(&mut parameters, 50, Some(1200)));
+    assert_eq!(parameters.max_fee_per_gas, Some(1200.into()));
+    assert_eq!(parameters.max_priority_fee_per_gas, Some(150.into()));
+
+    // The ceiling leaves no room for a replacement.
+    let mut parameters = eip1559(1000, 100);
+    assert!(!bump_fees(&mut parameters, 50, Some(1050)));
+
+    // Legacy transactions only have their gas price bumped.
+    let mut parameters = TransactionParameters {
+        gas_price: Some(1000.into()),
+        ..Default::default()
+    };
+    assert!(bump_fees(&mut parameters, 25, None));
+    assert_eq!(parameters.gas_price, Some(1250.into()));
+    assert!(!bump_fees(&mut parameters, 25, Some(1250)));
+}
+
+#[test]
+fn test_resume_pending_transaction() {
+    let parameters = TransactionParameters {
+        to: Some(Address::repeat_byte(2)),
+        nonce: Some(8.into()),
+        gas_price: Some(1000.into()),
+        data: vec![1, 2, 3].into(),
+        ..Default::default()
+    };
+    let mut pending = PendingTransaction::new(Address::repeat_byte(1), &parameters);
+    pending.nonce = 7.into();
+    pending.gas_price = Some(1200.into());
+    pending.sent_transaction_hashes = vec![H256::repeat_byte(3)];
+
+    // The same transaction is resumed at the same nonce, outbidding its previous gas price.
+    let mut resumed = parameters.clone();
+    let mut previously_sent = HashSet::new();
+    resume_pending_transaction(&mut resumed, &mut previously_sent, pending.clone(), 25);
+    assert_eq!(resumed.nonce, Some(7.into()));
+    assert_eq!(resumed.gas_price, Some(1500.into()));
+    assert_eq!(previously_sent, HashSet::from([H256::repeat_byte(3)]));
+
+    // A different transaction replaces it, without monitoring the previous one.
+    let mut replacement = TransactionParameters {
+        data: vec![4, 5, 6].into(),
+        gas_price: Some(2000.into()),
+        ..parameters
+    };
+    let mut previously_sent = HashSet::new();
+    resume_pending_transaction(&mut replacement, &mut previously_sent, pending, 25);
+    assert_eq!(replacement.nonce, Some(7.into()));
+    assert_eq!(replacement.gas_price, Some(2000.into()));
+    assert!(previously_sent.is_empty());
+}
+
+/// Answers the calls made by [`TransactionMonitor::new`] for legacy transactions, with nonce 7 for
+/// the latest block and nonce 9 for the pending one.
+#[cfg(test)]
+#[derive(Clone, Debug)]
+struct NonceTransport;
+
+#[cfg(test)]
+impl Transport for NonceTransport {
+    type Out = futures::future::Ready<web3::Result<jsonrpc_core::Value>>;
+
+    fn prepare(
+        &self,
+        method: &str,
+        params: Vec<jsonrpc_core::Value>,
+    ) -> (web3::RequestId, jsonrpc_core::Call) {
+        (0, web3::helpers::build_request(0, method, params))
+    }
+
+    fn send(&self, _id: web3::RequestId, request: jsonrpc_core::Call) -> Self::Out {
+        let jsonrpc_core::Call::MethodCall(call) = request else {
+            panic!("Unexpected request: {request:?}");
+        };
+        let params = match call.params {
+            jsonrpc_core::Params::Array(params) => params,
+            _ => vec![],
+        };
+        let result = match call.method.as_str() {
+            "eth_getTransactionCount" if params[1] == "pending" => "0x9",
+            "eth_getTransactionCount" => "0x7",
+            "eth_gasPrice" => "0x64",
+            "eth_chainId" => "0x1",
+            method => panic!("Unexpected call to {method}"),
+        };
+        futures::future::ready(Ok(result.into()))
+    }
+}
+
+#[tokio::test]
+async fn test_nonce_follows_the_state_file() {
+    let signer = crate::signer::LocalSigner::new(
+        secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+        Web3::new(NonceTransport),
+    );
+    let nonce = |state_file| {
+        let signer = &signer;
+        async move {
+            let options = TransactionMonitoringOptions {
+                state_file,
+                ..Default::default()
+            };
+            let monitor = TransactionMonitor::new(
+                Web3::new(NonceTransport),
+                signer,
+                Address::zero(),
+                Bytes::default(),
+                100_000.into(),
+                options,
+            )
+            .await
+            .unwrap();
+            monitor.transaction_parameters.nonce
+        }
+    };
+
+    // Without a state file, a stuck transaction is replaced at the latest nonce.
+    assert_eq!(nonce(None).await, Some(7.into()));
+
+    // With one, new transactions are queued after the pending ones.
+    let state_file = std::env::temp_dir().join(format!(
+        "block-oracle-nonce-test-{}.json",
+        std::process::id()
+    ));
+    assert_eq!(nonce(Some(state_file)).await, Some(9.into()));
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::Path;
+use web3::types::{Address, Bytes, TransactionParameters, H256, U256};
+
+#[derive(thiserror::Error, Debug)]
+pub enum TransactionStateError {
+    #[error("failed to access the transaction state file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("bad transaction state file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A transaction that was broadcast but not yet confirmed, as saved to the transaction state file.
+///
+/// It survives restarts, so that a new transaction monitor can replace it instead of using a new
+/// nonce.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub from: Address,
+    pub to: Address,
+    pub nonce: U256,
+    pub data: Bytes,
+    pub gas: U256,
+    pub gas_price: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    /// The hashes of every version of this transaction that was broadcast, one per gas bump.
+    pub sent_transaction_hashes: Vec<H256>,
+}
+
+impl PendingTransaction {
+    pub fn new(from: Address, parameters: &TransactionParameters) -> Self {
+        Self {
+            from,
+            to: parameters.to.unwrap_or_default(),
+            nonce: parameters.nonce.unwrap_or_default(),
+            data: parameters.data.clone(),
+            gas: parameters.gas,
+            gas_price: parameters.gas_price,
+            max_fee_per_gas: parameters.max_fee_per_gas,
+            max_priority_fee_per_gas: parameters.max_priority_fee_per_gas,
+            sent_transaction_hashes: vec![],
+        }
+    }
+
+    /// Reads the pending transaction from the state file, if there is one.
+    pub fn load(path: &Path) -> Result<Option<Self>, TransactionStateError> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Overwrites the state file with this pending transaction. The file is replaced atomically,
+    /// so a crash never leaves it half-written.
+    pub fn save(&self, path: &Path) -> Result<(), TransactionStateError> {
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Removes the state file, once there are no pending transactions left.
+    pub fn clear(path: &Path) -> Result<(), TransactionStateError> {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_and_clear() {
+        let path = std::env::temp_dir().join(format!(
+            "block-oracle-transaction-state-{}.json",
+            std::process::id()
+        ));
+        assert_eq!(PendingTransaction::load(&path).unwrap(), None);
+
+        let parameters = TransactionParameters {
+            to: Some(Address::repeat_byte(2)),
+            nonce: Some(7.into()),
+            gas: 100_000.into(),
+            gas_price: Some(5.into()),
+            data: vec![1, 2, 3].into(),
+            ..Default::default()
+        };
+        let mut pending = PendingTransaction::new(Address::repeat_byte(1), &parameters);
+        pending.sent_transaction_hashes.push(H256::repeat_byte(3));
+        pending.save(&path).unwrap();
+        assert_eq!(PendingTransaction::load(&path).unwrap(), Some(pending));
+
+        PendingTransaction::clear(&path).unwrap();
+        PendingTransaction::clear(&path).unwrap();
+        assert_eq!(PendingTransaction::load(&path).unwrap(), None);
+    }
+}
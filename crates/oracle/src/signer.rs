@@ -0,0 +1,276 @@
+use crate::config::SignerConfig;
+use crate::Config;
+use async_trait::async_trait;
+use jsonrpc_core::Value;
+use secp256k1::SecretKey;
+use serde_json::json;
+use web3::{
+    api::{Accounts, Namespace},
+    signing::{keccak256, Key, SecretKeyRef},
+    transports::Http,
+    types::{Address, Bytes, TransactionParameters, H256, U64},
+    Transport, Web3,
+};
+
+/// A transaction that is ready to be broadcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTransaction {
+    pub raw_transaction: Bytes,
+    pub transaction_hash: H256,
+}
+
+/// Signs the transactions that the oracle sends to the protocol chain.
+#[async_trait(?Send)]
+pub trait Signer {
+    /// The address that transactions are sent from.
+    fn address(&self) -> Address;
+
+    /// Signs a transaction, whose nonce, fees and chain ID should all be set.
+    async fn sign_transaction(
+        &self,
+        transaction: TransactionParameters,
+    ) -> web3::Result<SignedTransaction>;
+}
+
+/// Builds the signer of the owner's transactions. Local keys use `client` to fill any missing
+/// transaction fields.
+pub fn owner_signer<T>(config: &Config, client: Web3<T>) -> Box<dyn Signer>
+where
+    T: Transport + 'static,
+{
+    match &config.owner_signer {
+        SignerConfig::Local(key) => Box::new(LocalSigner::new(*key, client)),
+        SignerConfig::Remote { url } => Box::new(RemoteSigner::new(
+            config.owner_address,
+            Http::with_client(reqwest::Client::new(), url.clone()),
+        )),
+    }
+}
+
+/// Signs with a private key that the oracle holds.
+pub struct LocalSigner<T: Transport> {
+    key: SecretKey,
+    accounts: Accounts<T>,
+}
+
+impl<T: Transport> LocalSigner<T> {
+    pub fn new(key: SecretKey, client: Web3<T>) -> Self {
+        Self {
+            key,
+            accounts: Accounts::new(client.transport().clone()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: Transport> Signer for LocalSigner<T> {
+    fn address(&self) -> Address {
+        SecretKeyRef::new(&self.key).address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: TransactionParameters,
+    ) -> web3::Result<SignedTransaction> {
+        let signed = self
+            .accounts
+            .sign_transaction(transaction, SecretKeyRef::new(&self.key))
+            .await?;
+        Ok(SignedTransaction {
+            raw_transaction: signed.raw_transaction,
+            transaction_hash: signed.transaction_hash,
+        })
+    }
+}
+
+/// Asks a remote signer, e.g. web3signer, to sign with `eth_signTransaction`, so that the private
+/// key never reaches the oracle.
+pub struct RemoteSigner<T: Transport = Http> {
+    address: Address,
+    transport: T,
+}
+
+impl<T: Transport> RemoteSigner<T> {
+    pub fn new(address: Address, transport: T) -> Self {
+        Self { address, transport }
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: Transport> Signer for RemoteSigner<T> {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: TransactionParameters,
+    ) -> web3::Result<SignedTransaction> {
+        let request = signing_request(self.address, &transaction);
+        let response = self
+            .transport
+            .execute("eth_signTransaction", vec![request])
+            .await?;
+        let raw_transaction = raw_transaction(response)?;
+        Ok(SignedTransaction {
+            transaction_hash: H256(keccak256(&raw_transaction.0)),
+            raw_transaction,
+        })
+    }
+}
+
+fn signing_request(from: Address, transaction: &TransactionParameters) -> Value {
+    let mut request = json!({
+        "from": from,
+        "to": transaction.to,
+        "gas": transaction.gas,
+        "value": transaction.value,
+        "data": transaction.data,
+        "nonce": transaction.nonce,
+        "chainId": transaction.chain_id.map(U64::from),
+    });
+    match transaction.transaction_type {
+        Some(transaction_type) => {
+            request["type"] = json!(transaction_type);
+            request["maxFeePerGas"] = json!(transaction.max_fee_per_gas);
+            request["maxPriorityFeePerGas"] = json!(transaction.max_priority_fee_per_gas);
+        }
+        None => request["gasPrice"] = json!(transaction.gas_price),
+    }
+    request
+}
+
+/// Extracts the signed transaction from an `eth_signTransaction` response, which is either the raw
+/// transaction itself or, like Geth does, an object with a `raw` field.
+fn raw_transaction(response: Value) -> web3::Result<Bytes> {
+    let raw = match response {
+        Value::Object(mut object) => object.remove("raw").unwrap_or_default(),
+        raw => raw,
+    };
+    serde_json::from_value(raw).map_err(|err| {
+        web3::Error::InvalidResponse(format!("Bad eth_signTransaction response: {err}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::Http as HttpServer;
+    use hyper::{Body, Request, Response};
+    use reqwest::Url;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// Answers every JSON-RPC request with `result`, and keeps the last request around.
+    async fn mock_signer(result: Value) -> (Url, Arc<Mutex<Option<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let last_request = Arc::new(Mutex::new(None));
+
+        let requests = last_request.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let requests = requests.clone();
+                let result = result.clone();
+                let service = hyper::service::service_fn(move |request: Request<Body>| {
+                    let requests = requests.clone();
+                    let result = result.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(request.into_body()).await?;
+                        let request: Value = serde_json::from_slice(&body).unwrap();
+                        let response = json!({
+                            "jsonrpc": "2.0",
+                            "id": request["id"],
+                            "result": result,
+                        });
+                        *requests.lock().unwrap() = Some(request);
+                        Ok::<_, hyper::Error>(Response::new(Body::from(response.to_string())))
+                    }
+                });
+                HttpServer::new()
+                    .serve_connection(stream, service)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let url = format!("http://127.0.0.1:{port}").parse().unwrap();
+        (url, last_request)
+    }
+
+    fn remote_signer(url: Url) -> RemoteSigner {
+        RemoteSigner::new(Address::repeat_byte(1), Http::new(url.as_str()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn remote_signer_signs_eip1559_transactions() {
+        let (url, last_request) = mock_signer(json!("0x02f8aabbcc")).await;
+        let transaction = TransactionParameters {
+            to: Some(Address::repeat_byte(2)),
+            nonce: Some(7.into()),
+            gas: 100_000.into(),
+            gas_price: None,
+            max_fee_per_gas: Some(300.into()),
+            max_priority_fee_per_gas: Some(2.into()),
+            transaction_type: Some(2.into()),
+            chain_id: Some(42161),
+            data: vec![1, 2, 3].into(),
+            ..Default::default()
+        };
+
+        let signed = remote_signer(url)
+            .sign_transaction(transaction)
+            .await
+            .unwrap();
+        assert_eq!(
+            signed.raw_transaction,
+            vec![0x02, 0xf8, 0xaa, 0xbb, 0xcc].into()
+        );
+        assert_eq!(
+            signed.transaction_hash,
+            H256(keccak256(&[0x02, 0xf8, 0xaa, 0xbb, 0xcc]))
+        );
+
+        let request = last_request.lock().unwrap().take().unwrap();
+        assert_eq!(request["method"], "eth_signTransaction");
+        let params = &request["params"][0];
+        assert_eq!(params["from"], json!(Address::repeat_byte(1)));
+        assert_eq!(params["to"], json!(Address::repeat_byte(2)));
+        assert_eq!(params["nonce"], "0x7");
+        assert_eq!(params["chainId"], "0xa4b1");
+        assert_eq!(params["type"], "0x2");
+        assert_eq!(params["maxFeePerGas"], "0x12c");
+        assert_eq!(params["maxPriorityFeePerGas"], "0x2");
+        assert_eq!(params["data"], "0x010203");
+        assert!(params.get("gasPrice").is_none());
+    }
+
+    #[tokio::test]
+    async fn remote_signer_accepts_geth_responses() {
+        let (url, last_request) =
+            mock_signer(json!({ "raw": "0xf86b01", "tx": { "nonce": "0x1" } })).await;
+        let transaction = TransactionParameters {
+            gas_price: Some(1000.into()),
+            ..Default::default()
+        };
+
+        let signed = remote_signer(url)
+            .sign_transaction(transaction)
+            .await
+            .unwrap();
+        assert_eq!(signed.raw_transaction, vec![0xf8, 0x6b, 0x01].into());
+
+        let request = last_request.lock().unwrap().take().unwrap();
+        assert_eq!(request["params"][0]["gasPrice"], "0x3e8");
+    }
+
+    #[tokio::test]
+    async fn remote_signer_rejects_bad_responses() {
+        let (url, _) = mock_signer(json!({ "signature": "0x00" })).await;
+        let result = remote_signer(url)
+            .sign_transaction(TransactionParameters::default())
+            .await;
+        assert!(matches!(result, Err(web3::Error::InvalidResponse(_))));
+    }
+}
@@ -2,11 +2,13 @@ use super::metrics::METRICS;
 use crate::models::Caip2ChainId;
 use crate::runner::error_handling::{MainLoopFlow, OracleControlFlow};
 use anyhow::ensure;
+use epoch_encoding::messages::Bytes32;
 use graphql_client::{GraphQLQuery, Response};
 use itertools::Itertools;
 use reqwest::Url;
+use std::ops::RangeInclusive;
 use std::time::Duration;
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SubgraphQueryError {
@@ -35,24 +37,27 @@ impl MainLoopFlow for SubgraphQueryError {
     }
 }
 
-pub async fn query_subgraph(
+/// The maximum number of block numbers per network that [`query_block_number_history`] can
+/// fetch, which is the largest page size allowed by Graph Node.
+pub const MAX_BLOCK_NUMBER_HISTORY: usize = 1000;
+
+async fn send_query<Q: GraphQLQuery>(
     url: &Url,
     bearer_token: &str,
-) -> Result<SubgraphState, SubgraphQueryError> {
-    info!("Fetching latest subgraph state");
-
+    variables: Q::Variables,
+) -> Result<Q::ResponseData, SubgraphQueryError> {
     let client = reqwest::Client::builder()
         .timeout(Duration::from_secs(60))
         .user_agent("block-oracle")
         .build()
         .unwrap();
-    let request_body = graphql::SubgraphState::build_query(graphql::subgraph_state::Variables);
+    let request_body = Q::build_query(variables);
     let request = client
         .post(url.clone())
         .json(&request_body)
         .bearer_auth(bearer_token);
     let response = request.send().await?.error_for_status()?;
-    let response_body: Response<graphql::subgraph_state::ResponseData> = response.json().await?;
+    let response_body: Response<Q::ResponseData> = response.json().await?;
 
     match response_body.errors.as_deref() {
         Some([]) | None => {
@@ -70,13 +75,20 @@ pub async fn query_subgraph(
         }
     }
 
-    let data = if let Some(data) = response_body.data {
-        data
-    } else {
-        return Err(SubgraphQueryError::Other(anyhow::anyhow!(
-            "No response data"
-        )));
-    };
+    response_body
+        .data
+        .ok_or_else(|| SubgraphQueryError::Other(anyhow::anyhow!("No response data")))
+}
+
+pub async fn query_subgraph(
+    url: &Url,
+    bearer_token: &str,
+) -> Result<SubgraphState, SubgraphQueryError> {
+    info!("Fetching latest subgraph state");
+
+    let data =
+        send_query::<graphql::SubgraphState>(url, bearer_token, graphql::subgraph_state::Variables)
+            .await?;
 
     let last_indexed_block_number = data.meta.block.number as u64;
     let global_state = data
@@ -84,6 +96,12 @@ pub async fn query_subgraph(
         .map(|gs| gs.try_into())
         .transpose()
         .map_err(SubgraphQueryError::BadData)?;
+    let removed_networks = data
+        .removed_networks
+        .into_iter()
+        .map(RemovedNetwork::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(SubgraphQueryError::BadData)?;
     let last_payload: Option<Payload> = data
         .payloads
         .first()
@@ -101,6 +119,7 @@ pub async fn query_subgraph(
     Ok(SubgraphState {
         last_indexed_block_number,
         global_state,
+        removed_networks,
         last_payload,
     })
 }
@@ -109,6 +128,9 @@ pub async fn query_subgraph(
 pub struct SubgraphState {
     pub last_indexed_block_number: u64,
     pub global_state: Option<GlobalState>,
+    /// Networks that were registered and then removed. The subgraph keeps their latest block
+    /// number, and picks up from it if they are registered again.
+    pub removed_networks: Vec<RemovedNetwork>,
     pub last_payload: Option<Payload>,
 }
 
@@ -150,6 +172,48 @@ pub struct BlockUpdate {
     pub updated_at_epoch_number: u64,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemovedNetwork {
+    pub id: Caip2ChainId,
+    pub latest_block_update: Option<BlockUpdate>,
+}
+
+impl From<RemovedNetwork> for epoch_encoding::Network {
+    fn from(val: RemovedNetwork) -> Self {
+        let (block_number, block_delta) = val
+            .latest_block_update
+            .map_or((0, 0), |update| (update.block_number, update.delta));
+
+        // The array index is assigned when the network is registered again.
+        epoch_encoding::Network::new(block_number, block_delta, 0)
+    }
+}
+
+impl TryFrom<graphql::subgraph_state::SubgraphStateRemovedNetworks> for RemovedNetwork {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        value: graphql::subgraph_state::SubgraphStateRemovedNetworks,
+    ) -> Result<Self, Self::Error> {
+        let latest_block_update = value
+            .latest_valid_block_number
+            .map(|block_data| {
+                block_update(
+                    &block_data.block_number,
+                    &block_data.acceleration,
+                    &block_data.delta,
+                    &block_data.epoch_number,
+                )
+            })
+            .transpose()?;
+
+        Ok(RemovedNetwork {
+            id: parse_chain_id(&value.id)?,
+            latest_block_update,
+        })
+    }
+}
+
 impl From<Network> for epoch_encoding::Network {
     fn from(val: Network) -> Self {
         let (block_number, block_delta) = if let Some(block_update) = val.latest_block_update {
@@ -249,10 +313,470 @@ impl TryFrom<graphql::subgraph_state::SubgraphStateGlobalState> for GlobalState
     }
 }
 
+/// Fetches the block numbers that every registered network got within `epochs`, sorted by epoch
+/// number. At most [`MAX_BLOCK_NUMBER_HISTORY`] are returned per network.
+pub async fn query_block_number_history(
+    url: &Url,
+    bearer_token: &str,
+    epochs: RangeInclusive<u64>,
+) -> Result<BlockNumberHistory, SubgraphQueryError> {
+    info!(?epochs, "Fetching block number history from the subgraph");
+
+    let data = send_query::<graphql::EpochBlockNumbers>(
+        url,
+        bearer_token,
+        graphql::epoch_block_numbers::Variables {
+            from_epoch: epochs.start().to_string(),
+            to_epoch: epochs.end().to_string(),
+        },
+    )
+    .await?;
+
+    data.global_state
+        .ok_or_else(|| {
+            SubgraphQueryError::BadData(anyhow::anyhow!("The subgraph has no global state"))
+        })?
+        .try_into()
+        .map_err(SubgraphQueryError::BadData)
+}
+
+/// Fetches the Merkle root that was posted for an epoch. Corrections to the epoch are taken into
+/// account.
+///
+/// Only subgraph versions that store `Epoch.merkleRoot` have it, so this fails on a subgraph that
+/// indexed the epoch before it was redeployed with Merkle root support and re-synced.
+pub async fn query_epoch_merkle_root(
+    url: &Url,
+    bearer_token: &str,
+    epoch_number: u64,
+) -> Result<Bytes32, SubgraphQueryError> {
+    info!(epoch_number, "Fetching epoch Merkle root from the subgraph");
+
+    let data = send_query::<graphql::EpochMerkleRoot>(
+        url,
+        bearer_token,
+        graphql::epoch_merkle_root::Variables {
+            id: epoch_number.to_string(),
+        },
+    )
+    .await?;
+
+    let epoch = data.epoch.ok_or_else(|| {
+        SubgraphQueryError::Other(anyhow::anyhow!("The subgraph has no epoch {epoch_number}"))
+    })?;
+    let merkle_root = epoch
+        .merkle_root
+        .ok_or_else(|| missing_merkle_root(epoch_number))?;
+    decode_merkle_root(&merkle_root).map_err(SubgraphQueryError::BadData)
+}
+
+/// The error for an epoch without a Merkle root, which is either missing from the subgraph's data
+/// or was never posted.
+fn missing_merkle_root(epoch_number: u64) -> SubgraphQueryError {
+    SubgraphQueryError::Other(anyhow::anyhow!(
+        "The subgraph has no Merkle root for epoch {epoch_number}. Either no payload set its block \
+         numbers, or it was indexed by a subgraph version that didn't store Merkle roots, and the \
+         subgraph must be redeployed and re-synced"
+    ))
+}
+
+/// Fetches the networks that were registered when the block numbers of an epoch were set, by array
+/// index at that time, along with their current block numbers for the epoch.
+///
+/// Removed networks lose their array index in the subgraph, so the network list is read from the
+/// protocol chain block of the payload that set the epoch, found by the Merkle root it posted.
+/// That requires the subgraph not to have pruned its history, and the epoch not to have been
+/// corrected since.
+pub async fn query_epoch_networks(
+    url: &Url,
+    bearer_token: &str,
+    epoch_number: u64,
+) -> Result<EpochNetworks, SubgraphQueryError> {
+    info!(
+        epoch_number,
+        "Fetching the networks of an epoch from the subgraph"
+    );
+
+    let epoch = send_query::<graphql::EpochNetworks>(
+        url,
+        bearer_token,
+        graphql::epoch_networks::Variables {
+            id: epoch_number.to_string(),
+        },
+    )
+    .await?
+    .epoch
+    .ok_or_else(|| {
+        SubgraphQueryError::Other(anyhow::anyhow!("The subgraph has no epoch {epoch_number}"))
+    })?;
+    let merkle_root = epoch
+        .merkle_root
+        .clone()
+        .ok_or_else(|| missing_merkle_root(epoch_number))?;
+
+    let payload_block = query_posted_payload(url, bearer_token, epoch_number, &merkle_root)
+        .await?
+        .created_at;
+
+    let global_state = send_query::<graphql::NetworksAtBlock>(
+        url,
+        bearer_token,
+        graphql::networks_at_block::Variables {
+            block: payload_block,
+        },
+    )
+    .await?
+    .global_state
+    .ok_or_else(|| {
+        SubgraphQueryError::BadData(anyhow::anyhow!(
+            "The subgraph has no global state at block {payload_block}"
+        ))
+    })?;
+
+    epoch_networks(
+        epoch_number,
+        &merkle_root,
+        epoch,
+        global_state,
+        payload_block,
+    )
+    .map_err(SubgraphQueryError::BadData)
+}
+
+/// A payload that the subgraph indexed.
+struct PostedPayload {
+    data: Vec<u8>,
+    created_at: i64,
+}
+
+/// Finds the payload that set the block numbers of an epoch by the Merkle root it posted, so it
+/// won't be found if the epoch was corrected since.
+async fn query_posted_payload(
+    url: &Url,
+    bearer_token: &str,
+    epoch_number: u64,
+    merkle_root: &str,
+) -> Result<PostedPayload, SubgraphQueryError> {
+    let payload = send_query::<graphql::EpochPayloadBlock>(
+        url,
+        bearer_token,
+        graphql::epoch_payload_block::Variables {
+            merkle_root: merkle_root.to_owned(),
+        },
+    )
+    .await?
+    .set_block_numbers_for_epoch_messages
+    .into_iter()
+    .next()
+    .ok_or_else(|| {
+        SubgraphQueryError::Other(anyhow::anyhow!(
+            "No payload posted the Merkle root {merkle_root} of epoch {epoch_number}, so it was \
+             corrected since"
+        ))
+    })?
+    .block
+    .payload;
+
+    let parse = || -> anyhow::Result<PostedPayload> {
+        Ok(PostedPayload {
+            data: hex::decode(payload.data.trim_start_matches("0x"))?,
+            created_at: payload.created_at.parse()?,
+        })
+    };
+    parse().map_err(SubgraphQueryError::BadData)
+}
+
+/// Fetches the payload that set the block numbers of an epoch, along with the state of the
+/// subgraph right before it, which is what the payload must be decoded from.
+pub async fn query_epoch_payload(
+    url: &Url,
+    bearer_token: &str,
+    epoch_number: u64,
+) -> Result<EpochPayload, SubgraphQueryError> {
+    info!(
+        epoch_number,
+        "Fetching the payload of an epoch from the subgraph"
+    );
+
+    let merkle_root = query_epoch_merkle_root(url, bearer_token, epoch_number).await?;
+    let payload = query_posted_payload(
+        url,
+        bearer_token,
+        epoch_number,
+        &format!("0x{}", hex::encode(merkle_root)),
+    )
+    .await?;
+
+    let state = query_state_at_block(url, bearer_token, payload.created_at - 1)
+        .await?
+        .ok_or_else(|| {
+            SubgraphQueryError::BadData(anyhow::anyhow!(
+                "The subgraph has no global state before block {}",
+                payload.created_at
+            ))
+        })?;
+
+    Ok(EpochPayload {
+        merkle_root,
+        data: payload.data,
+        encoding_version: state.encoding_version,
+        networks: state.networks,
+        removed_networks: state.removed_networks,
+    })
+}
+
+/// Fetches the encoding version and the networks of the subgraph as they were at a protocol chain
+/// block, or `None` if the subgraph wasn't initialized yet.
+pub async fn query_state_at_block(
+    url: &Url,
+    bearer_token: &str,
+    block_number: i64,
+) -> Result<Option<HistoricalState>, SubgraphQueryError> {
+    info!(block_number, "Fetching the subgraph state at a past block");
+
+    let data = send_query::<graphql::PayloadState>(
+        url,
+        bearer_token,
+        graphql::payload_state::Variables {
+            block: block_number,
+        },
+    )
+    .await?;
+    let Some(global_state) = data.global_state else {
+        return Ok(None);
+    };
+
+    let parse = || -> anyhow::Result<HistoricalState> {
+        let mut networks = vec![];
+        for (expected_i, mut network) in global_state.networks.into_iter().enumerate() {
+            ensure!(
+                network.array_index == Some(expected_i as i64),
+                "Network with ID {} has a bad index",
+                network.id
+            );
+            let latest_block_update = network
+                .block_numbers
+                .pop()
+                .map(|block_data| {
+                    block_update(
+                        &block_data.block_number,
+                        &block_data.acceleration,
+                        &block_data.delta,
+                        &block_data.epoch_number,
+                    )
+                })
+                .transpose()?;
+            networks.push(Network {
+                id: parse_chain_id(&network.id)?,
+                array_index: expected_i as u64,
+                latest_block_update,
+            });
+        }
+        let removed_networks = data
+            .removed_networks
+            .into_iter()
+            .map(|network| -> anyhow::Result<_> {
+                let latest_block_update = network
+                    .latest_valid_block_number
+                    .map(|block_data| {
+                        block_update(
+                            &block_data.block_number,
+                            &block_data.acceleration,
+                            &block_data.delta,
+                            &block_data.epoch_number,
+                        )
+                    })
+                    .transpose()?;
+                Ok(RemovedNetwork {
+                    id: parse_chain_id(&network.id)?,
+                    latest_block_update,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(HistoricalState {
+            encoding_version: global_state.encoding_version.try_into()?,
+            networks,
+            removed_networks,
+        })
+    };
+    parse().map(Some).map_err(SubgraphQueryError::BadData)
+}
+
+/// The state of the subgraph at a past block, as returned by [`query_state_at_block`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoricalState {
+    pub encoding_version: u64,
+    pub networks: Vec<Network>,
+    pub removed_networks: Vec<RemovedNetwork>,
+}
+
+/// The payload that set the block numbers of an epoch, as returned by [`query_epoch_payload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochPayload {
+    /// The Merkle root of the epoch, which the payload posted.
+    pub merkle_root: Bytes32,
+    pub data: Vec<u8>,
+    /// The encoding version and the networks right before the payload.
+    pub encoding_version: u64,
+    pub networks: Vec<Network>,
+    pub removed_networks: Vec<RemovedNetwork>,
+}
+
+fn parse_chain_id(id: &str) -> anyhow::Result<Caip2ChainId> {
+    id.parse()
+        .map_err(|s| anyhow::anyhow!("Invalid network name: {}", s))
+}
+
+fn block_update(
+    block_number: &str,
+    acceleration: &str,
+    delta: &str,
+    epoch_number: &str,
+) -> anyhow::Result<BlockUpdate> {
+    Ok(BlockUpdate {
+        block_number: block_number.parse()?,
+        acceleration: acceleration.parse()?,
+        delta: delta.parse()?,
+        updated_at_epoch_number: epoch_number.parse()?,
+    })
+}
+
+fn epoch_networks(
+    epoch_number: u64,
+    merkle_root: &str,
+    epoch: graphql::epoch_networks::EpochNetworksEpoch,
+    global_state: graphql::networks_at_block::NetworksAtBlockGlobalState,
+    payload_block: i64,
+) -> anyhow::Result<EpochNetworks> {
+    let merkle_root = decode_merkle_root(merkle_root)?;
+    let latest_epoch_number: Option<u64> = global_state
+        .latest_valid_epoch
+        .map(|epoch| epoch.epoch_number.parse())
+        .transpose()?;
+    ensure!(
+        latest_epoch_number == Some(epoch_number),
+        "The payload at block {payload_block} set epoch {latest_epoch_number:?}, not {epoch_number}"
+    );
+
+    let mut networks = vec![];
+    for (expected_i, network) in global_state.networks.into_iter().enumerate() {
+        ensure!(
+            network.array_index == Some(expected_i as i64),
+            "Network with ID {} has a bad index",
+            network.id
+        );
+        let block_number = epoch
+            .block_numbers
+            .iter()
+            .find(|block_number| block_number.network.id == network.id)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} has no block number for epoch {epoch_number}",
+                    network.id
+                )
+            })?
+            .block_number
+            .parse()?;
+        let id = network
+            .id
+            .parse()
+            .map_err(|s| anyhow::anyhow!("Invalid network name: {}", s))?;
+        networks.push((id, block_number));
+    }
+    ensure!(
+        networks.len() == epoch.block_numbers.len(),
+        "Epoch {epoch_number} has block numbers for networks that weren't registered"
+    );
+
+    Ok(EpochNetworks {
+        merkle_root,
+        networks,
+    })
+}
+
+/// The networks of an epoch, as returned by [`query_epoch_networks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochNetworks {
+    pub merkle_root: Bytes32,
+    /// Block numbers by array index.
+    pub networks: Vec<(Caip2ChainId, u64)>,
+}
+
+fn decode_merkle_root(merkle_root: &str) -> anyhow::Result<Bytes32> {
+    let bytes = hex::decode(merkle_root.trim_start_matches("0x"))?;
+    Bytes32::try_from(bytes).map_err(|_| anyhow::anyhow!("Bad Merkle root length"))
+}
+
+/// The block numbers of all registered networks over a range of epochs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockNumberHistory {
+    pub latest_epoch_number: Option<u64>,
+    pub networks: Vec<NetworkHistory>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkHistory {
+    pub id: Caip2ChainId,
+    pub array_index: u64,
+    /// Sorted by epoch number.
+    pub block_updates: Vec<BlockUpdate>,
+}
+
+impl TryFrom<graphql::epoch_block_numbers::EpochBlockNumbersGlobalState> for BlockNumberHistory {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        value: graphql::epoch_block_numbers::EpochBlockNumbersGlobalState,
+    ) -> Result<Self, Self::Error> {
+        let mut networks = vec![];
+
+        for (expected_i, network) in value.networks.into_iter().enumerate() {
+            ensure!(
+                network.array_index == Some(expected_i as i64),
+                "Network with ID {} has a bad index",
+                network.id
+            );
+
+            let block_updates = network
+                .block_numbers
+                .into_iter()
+                .map(|block_data| {
+                    Ok(BlockUpdate {
+                        block_number: block_data.block_number.parse()?,
+                        acceleration: block_data.acceleration.parse()?,
+                        delta: block_data.delta.parse()?,
+                        updated_at_epoch_number: block_data.epoch_number.parse()?,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            networks.push(NetworkHistory {
+                id: network
+                    .id
+                    .as_str()
+                    .parse()
+                    .map_err(|s| anyhow::anyhow!("Invalid network name: {}", s))?,
+                array_index: expected_i as u64,
+                block_updates,
+            });
+        }
+
+        Ok(Self {
+            latest_epoch_number: value
+                .latest_valid_epoch
+                .map(|x| x.epoch_number.parse())
+                .transpose()?,
+            networks,
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Payload {
-    valid: bool,
-    created_at: i64,
+    pub valid: bool,
+    /// The number of the protocol chain block that contains the payload.
+    pub created_at: i64,
 }
 
 impl TryFrom<&graphql::subgraph_state::SubgraphStatePayloads> for Payload {
@@ -278,6 +802,56 @@ mod graphql {
         deprecated = "warn"
     )]
     pub struct SubgraphState;
+
+    type BigInt = String;
+
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/schema.graphql",
+        query_path = "src/graphql/epoch_block_numbers.graphql",
+        deprecated = "warn"
+    )]
+    pub struct EpochBlockNumbers;
+
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/schema.graphql",
+        query_path = "src/graphql/epoch_merkle_root.graphql",
+        deprecated = "warn"
+    )]
+    pub struct EpochMerkleRoot;
+
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/schema.graphql",
+        query_path = "src/graphql/epoch_networks.graphql",
+        deprecated = "warn"
+    )]
+    pub struct EpochNetworks;
+
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/schema.graphql",
+        query_path = "src/graphql/epoch_payload_block.graphql",
+        deprecated = "warn"
+    )]
+    pub struct EpochPayloadBlock;
+
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/schema.graphql",
+        query_path = "src/graphql/networks_at_block.graphql",
+        deprecated = "warn"
+    )]
+    pub struct NetworksAtBlock;
+
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/schema.graphql",
+        query_path = "src/graphql/payload_state.graphql",
+        deprecated = "warn"
+    )]
+    pub struct PayloadState;
 }
 
 #[cfg(test)]
@@ -346,6 +920,7 @@ mod tests {
                         "number": 7333988
                     }
                 },
+                "removedNetworks": [],
                 "payloads": [
                     {
                         "valid": true,
@@ -387,8 +962,22 @@ mod tests {
                         "number": 7333988
                     }
                 },
+                "removedNetworks": [
+                    {
+                        "id": "eip155:100",
+                        "latestValidBlockNumber": {
+                            "blockNumber": "200",
+                            "acceleration": "1",
+                            "delta": "5",
+                            "epochNumber": "149"
+                        }
+                    },
+                    {
+                        "id": "eip155:137",
+                        "latestValidBlockNumber": null
+                    }
+                ],
                 "payloads": []
-
             }
         }))
         .await
@@ -398,6 +987,24 @@ mod tests {
             state.global_state.as_ref().unwrap().latest_epoch_number,
             Some(150)
         );
+        assert_eq!(
+            state.removed_networks,
+            vec![
+                RemovedNetwork {
+                    id: "eip155:100".parse().unwrap(),
+                    latest_block_update: Some(BlockUpdate {
+                        block_number: 200,
+                        acceleration: 1,
+                        delta: 5,
+                        updated_at_epoch_number: 149,
+                    }),
+                },
+                RemovedNetwork {
+                    id: "eip155:137".parse().unwrap(),
+                    latest_block_update: None,
+                },
+            ]
+        );
     }
 
     #[tokio::test]
@@ -426,6 +1033,7 @@ mod tests {
                         "number": 2
                     }
                 },
+                "removedNetworks": [],
                 "payloads":[]
             }
         }))
@@ -444,6 +1052,7 @@ mod tests {
                         "number": 2
                     }
                 },
+                "removedNetworks": [],
                 "payloads": []
             },
             "errors": [
@@ -457,4 +1066,126 @@ mod tests {
         .unwrap();
         assert!(matches!(error, SubgraphQueryError::IndexingError));
     }
+
+    #[tokio::test]
+    async fn block_number_history() {
+        let server = FakeServer::new(json!({
+            "data": {
+                "globalState": {
+                    "networks": [
+                        {
+                            "id": "eip155:1",
+                            "arrayIndex": 0,
+                            "blockNumbers": [
+                                {
+                                    "blockNumber": "100",
+                                    "acceleration": "0",
+                                    "delta": "10",
+                                    "epochNumber": "4"
+                                },
+                                {
+                                    "blockNumber": "112",
+                                    "acceleration": "2",
+                                    "delta": "12",
+                                    "epochNumber": "5"
+                                }
+                            ]
+                        }
+                    ],
+                    "latestValidEpoch": {
+                        "epochNumber": "5"
+                    }
+                }
+            }
+        }));
+        let url = server.serve().await;
+        let history = query_block_number_history(&url, "foobar", 4..=5)
+            .await
+            .unwrap();
+        assert_eq!(history.latest_epoch_number, Some(5));
+        assert_eq!(history.networks.len(), 1);
+        assert_eq!(history.networks[0].id, Caip2ChainId::ethereum_mainnet());
+        assert_eq!(
+            history.networks[0].block_updates[1],
+            BlockUpdate {
+                block_number: 112,
+                acceleration: 2,
+                delta: 12,
+                updated_at_epoch_number: 5,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn epoch_networks_at_payload_block() {
+        // The fake server answers every query with the same response, so it
+        // contains the fields of all three queries.
+        let merkle_root = format!("0x{}", "ab".repeat(32));
+        let server = FakeServer::new(json!({
+            "data": {
+                "epoch": {
+                    "merkleRoot": merkle_root,
+                    "blockNumbers": [
+                        { "blockNumber": "200", "network": { "id": "eip155:100" } },
+                        { "blockNumber": "100", "network": { "id": "eip155:1" } }
+                    ]
+                },
+                "setBlockNumbersForEpochMessages": [
+                    { "block": { "payload": { "data": "0x00", "createdAt": "1234" } } }
+                ],
+                "globalState": {
+                    "networks": [
+                        { "id": "eip155:1", "arrayIndex": 0 },
+                        { "id": "eip155:100", "arrayIndex": 1 }
+                    ],
+                    "latestValidEpoch": { "epochNumber": "5" }
+                }
+            }
+        }));
+        let url = server.serve().await;
+        let epoch_networks = query_epoch_networks(&url, "foobar", 5).await.unwrap();
+        assert_eq!(epoch_networks.merkle_root, [0xab; 32]);
+        assert_eq!(
+            epoch_networks.networks,
+            vec![
+                (Caip2ChainId::ethereum_mainnet(), 100),
+                ("eip155:100".parse().unwrap(), 200)
+            ]
+        );
+
+        let error = query_epoch_networks(&url, "foobar", 4).await.unwrap_err();
+        assert!(matches!(error, SubgraphQueryError::BadData(_)));
+    }
+
+    #[tokio::test]
+    async fn epoch_without_merkle_root() {
+        let server = FakeServer::new(json!({
+            "data": {
+                "epoch": {
+                    "merkleRoot": null,
+                    "blockNumbers": []
+                }
+            }
+        }));
+        let url = server.serve().await;
+        let error = query_epoch_merkle_root(&url, "foobar", 5)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no Merkle root for epoch 5"), "{error}");
+        assert!(error.contains("redeployed and re-synced"), "{error}");
+        let error = query_epoch_networks(&url, "foobar", 5)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no Merkle root for epoch 5"), "{error}");
+
+        let server = FakeServer::new(json!({ "data": { "epoch": null } }));
+        let url = server.serve().await;
+        let error = query_epoch_merkle_root(&url, "foobar", 5)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no epoch 5"), "{error}");
+    }
 }
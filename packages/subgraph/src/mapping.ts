@@ -260,6 +260,22 @@ function executeNonEmptySetBlockNumbersForEpochMessage(
   let nextEpochID = nextEpochId(globalState, reader);
   let nextEpochNumber = nextEpochID.toI32();
 
+  // Since encoding version 1, the payload states which epoch it's for.
+  if (globalState.encodingVersion >= 1) {
+    let epochNumber = decodeU64(reader);
+    if (!reader.ok) {
+      return;
+    }
+    if (BigInt.fromU64(epochNumber) != nextEpochID) {
+      reader.fail(
+        "Payload is for epoch {}, but the next epoch is {}."
+          .replace("{}", epochNumber.toString())
+          .replace("{}", nextEpochID.toString())
+      );
+      return;
+    }
+  }
+
   if (nextEpochNumber > previousEpochNumber + 1) {
     log.warning(
       "Next Epoch number is {}, but previous epoch number is {}. Creating empty epochs to fill the gaps",
@@ -276,6 +292,7 @@ function executeNonEmptySetBlockNumbersForEpochMessage(
 
   let merkleRoot = reader.advance(32);
   message.merkleRoot = merkleRoot;
+  newEpoch.merkleRoot = merkleRoot;
   log.warning("The Merkle root of the new epoch is {}", [
     merkleRoot.toHexString()
   ]);
@@ -458,6 +475,10 @@ function executeCorrectLastEpochMessage(
   
   // 9. Update network's latest valid block number
   network.latestValidBlockNumber = epochBlock.id;
+
+  // 10. Update the epoch's merkle root
+  let epoch = cache.getEpoch(BigInt.fromString(latestEpochId!));
+  epoch.merkleRoot = merkleRoot;
 }
 
 function executeUpdateVersionsMessage(
@@ -1011,3 +1011,43 @@ test("CorrectLastEpoch with multiple epochs calculates delta correctly", () => {
   assert.fieldEquals("NetworkEpochBlockNumber", "2-A1", "delta", "20");
   assert.fieldEquals("NetworkEpochBlockNumber", "2-A1", "acceleration", "10");
 });
+
+// Encoding version 1 prefixes the Merkle root with the epoch number.
+// 1 (UpdateVersion, RegisterNetworks): 0x32 03 01030341
+// 2 (SetBlockNumbersForNextEpoch): 0x00 03 66ebb0...87dc49 3d
+test("(UpdateVersion, RegisterNetworks) -> (SetBlockNumbersForNextEpoch) with epoch number", () => {
+  let payloadBytes1 = Bytes.fromHexString("0x320301030341") as Bytes;
+  let payloadBytes2 = Bytes.fromHexString(
+    "0x000366ebb0afd80c906e2b0564e921c3feefa9a5ecb71e98e3c7b7e661515e87dc493d"
+  ) as Bytes;
+  let submitter = "0x0000000000000000000000000000000000000000";
+
+  processPayload(submitter, payloadBytes1, "0x00", BIGINT_ONE);
+  assert.fieldEquals("GlobalState", "0", "encodingVersion", "1");
+
+  processPayload(submitter, payloadBytes2, "0x01", BIGINT_ONE);
+  assert.fieldEquals("Payload", "0x01", "valid", "true");
+  assert.entityCount("Epoch", 1);
+  assert.fieldEquals("NetworkEpochBlockNumber", "1-A", "acceleration", "15");
+  assert.fieldEquals("NetworkEpochBlockNumber", "1-A", "delta", "15");
+});
+
+test("(SetBlockNumbersForNextEpoch) with the wrong epoch number should fail", () => {
+  let payloadBytes1 = Bytes.fromHexString("0x320301030341") as Bytes;
+  let payloadBytes2 = Bytes.fromHexString(
+    "0x000566ebb0afd80c906e2b0564e921c3feefa9a5ecb71e98e3c7b7e661515e87dc493d"
+  ) as Bytes;
+  let submitter = "0x0000000000000000000000000000000000000000";
+
+  processPayload(submitter, payloadBytes1, "0x00", BIGINT_ONE);
+  processPayload(submitter, payloadBytes2, "0x01", BIGINT_ONE);
+
+  assert.fieldEquals("Payload", "0x01", "valid", "false");
+  assert.fieldEquals(
+    "Payload",
+    "0x01",
+    "errorMessage",
+    "Payload is for epoch 2, but the next epoch is 1."
+  );
+  assert.entityCount("NetworkEpochBlockNumber", 0);
+});